@@ -0,0 +1,38 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Print a sample JSON document: `cargo run --example json -- --width 30`.
+
+use pretty_simple::examples::{json::*, Args};
+
+fn main() {
+    let args = Args::from_env().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, Args::USAGE);
+        std::process::exit(2)
+    });
+    let json = Json::object(vec![
+        ("name", Json::string("pretty-simple")),
+        ("version", Json::Float(0.1)),
+        (
+            "keywords",
+            Json::Array(vec![
+                Json::string("pretty-printing"),
+                Json::string("wadler"),
+                Json::string("layout"),
+            ]),
+        ),
+        (
+            "repository",
+            Json::object(vec![
+                ("type", Json::string("git")),
+                (
+                    "url",
+                    Json::string("https://github.com/cgswords/pretty-simple"),
+                ),
+            ]),
+        ),
+        ("private", Json::Bool(false)),
+        ("dependents", Json::Null),
+    ]);
+    println!("{}", args.render(json_doc_pretty(&json)));
+}
@@ -0,0 +1,39 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Print a sample lambda-calculus program: `cargo run --example lambda -- --width 20`.
+
+use pretty_simple::examples::{lambda::*, Args};
+
+fn main() {
+    let args = Args::from_env().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, Args::USAGE);
+        std::process::exit(2)
+    });
+    let compose = Exp::lam(
+        "f",
+        Exp::lam(
+            "g",
+            Exp::lam(
+                "x",
+                Exp::app(Exp::var("f"), Exp::app(Exp::var("g"), Exp::var("x"))),
+            ),
+        ),
+    );
+    let program = Exp::let_in(
+        "compose",
+        compose,
+        Exp::let_in(
+            "twice",
+            Exp::lam(
+                "f",
+                Exp::app(Exp::app(Exp::var("compose"), Exp::var("f")), Exp::var("f")),
+            ),
+            Exp::app(
+                Exp::app(Exp::var("twice"), Exp::var("increment")),
+                Exp::var("zero"),
+            ),
+        ),
+    );
+    println!("{}", args.render(expr_doc_pretty(&program)));
+}
@@ -0,0 +1,26 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Print a sample XML document: `cargo run --example xml -- --width 40`.
+
+use pretty_simple::examples::{xml::*, Args};
+
+const SAMPLE: &str = r#"<?xml version="1.0"?>
+<catalog>
+  <book id="bk101" lang="en">
+    <title>A Prettier Printer</title>
+    <author>Philip Wadler</author>
+    <note>Layout with <em>groups</em> &amp; alternatives.</note>
+  </book>
+  <book id="bk102"><title>Pretty Printing</title><author>Derek Oppen</author></book>
+  <shelf/>
+</catalog>"#;
+
+fn main() {
+    let args = Args::from_env().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, Args::USAGE);
+        std::process::exit(2)
+    });
+    let xml = parse_xml(SAMPLE).expect("the sample is well-formed");
+    println!("{}", args.render(xml_doc_pretty(&xml)));
+}
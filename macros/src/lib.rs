@@ -0,0 +1,267 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Procedural macros for `pretty_simple`. These are re-exported from the main crate; depend on
+//! `pretty_simple` rather than on this crate directly.
+
+use proc_macro::{TokenStream, TokenTree};
+
+// -------------------------------------------------------------------------------------------------
+// Entry Point
+// -------------------------------------------------------------------------------------------------
+
+/// Build a `Doc` from a template string at compile time.
+///
+/// The template is plain text with `{...}` holes. Text is inserted verbatim, and each hole is
+/// either a layout hint or a Rust expression whose value is spliced in:
+///
+/// | Template    | Meaning                                                   |
+/// |-------------|-----------------------------------------------------------|
+/// | `{expr}`    | splice `expr`, a `Doc<A>` as is or else via `ToDoc::to_doc` |
+/// | `{{` / `}}` | a literal `{` / `}`                                       |
+/// | `{/}`       | hard line break (`Doc::line()`)                           |
+/// | `{~}`       | soft line break (`Doc::softline()`)                       |
+/// | `{-}`       | soft line break that is empty when flat (`Doc::softline_empty()`) |
+/// | `{>}` `{<}` | nest the enclosed region by 4 columns                     |
+/// | `{>N}`      | nest the enclosed region by `N` columns (closed by `{<}`) |
+/// | `{[}` `{]}` | group the enclosed region (`Doc::group`)                  |
+///
+/// The template builds a `Doc<A>` when its `Doc` holes are annotated with `A`, and a plain `Doc`
+/// otherwise. Examples are on the re-export, `pretty_simple::doc_template`.
+#[proc_macro]
+pub fn doc_template(input: TokenStream) -> TokenStream {
+    let result = template_literal(input).and_then(|template| {
+        let pieces = parse_template(&template)?;
+        build_doc(pieces)
+    });
+    match result {
+        Ok(code) => code.parse().unwrap(),
+        Err(msg) => format!("::core::compile_error!({:?})", msg)
+            .parse()
+            .unwrap(),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Literal Handling
+// -------------------------------------------------------------------------------------------------
+
+fn template_literal(input: TokenStream) -> Result<String, String> {
+    let mut tokens: Vec<TokenTree> = input.into_iter().collect();
+    // Literals forwarded through `macro_rules!` arrive wrapped in an invisible group.
+    while let [TokenTree::Group(group)] = tokens.as_slice() {
+        tokens = group.stream().into_iter().collect();
+    }
+    match tokens.as_slice() {
+        [TokenTree::Literal(lit)] => unquote(&lit.to_string()),
+        _ => Err("doc_template! expects a single string literal".to_string()),
+    }
+}
+
+fn unquote(lit: &str) -> Result<String, String> {
+    if let Some(raw) = lit.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let body = &raw[hashes..raw.len() - hashes];
+        return body
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .map(|s| s.to_string())
+            .ok_or_else(|| "doc_template! expects a string literal".to_string());
+    }
+    let body = lit
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| "doc_template! expects a string literal".to_string())?;
+
+    let mut out = String::with_capacity(body.len());
+    let mut chars = body.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some('\\') => out.push('\\'),
+            Some('"') => out.push('"'),
+            Some('\'') => out.push('\''),
+            Some('x') => {
+                let code: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&code, 16).map_err(|e| e.to_string())?;
+                out.push(byte as char);
+            }
+            Some('u') => {
+                let code: String = chars
+                    .by_ref()
+                    .skip_while(|c| *c == '{')
+                    .take_while(|c| *c != '}')
+                    .collect();
+                let value = u32::from_str_radix(&code, 16).map_err(|e| e.to_string())?;
+                out.push(char::from_u32(value).ok_or("invalid unicode escape")?);
+            }
+            Some('\n') => {
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            other => return Err(format!("unsupported escape sequence: \\{:?}", other)),
+        }
+    }
+    Ok(out)
+}
+
+// -------------------------------------------------------------------------------------------------
+// Template Parsing
+// -------------------------------------------------------------------------------------------------
+
+enum Piece {
+    Text(String),
+    Hole(String),
+    Line,
+    Softline,
+    SoftlineEmpty,
+    OpenNest(i32),
+    CloseNest,
+    OpenGroup,
+    CloseGroup,
+}
+
+fn parse_template(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err("unmatched `}` in template; use `}}` for a literal brace".into()),
+            '{' => {
+                let mut hole = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(ch) => hole.push(ch),
+                        None => return Err("unterminated `{` in template".into()),
+                    }
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(parse_hole(hole.trim())?);
+            }
+            _ => text.push(ch),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn parse_hole(hole: &str) -> Result<Piece, String> {
+    Ok(match hole {
+        "" => return Err("empty `{}` hole in template".into()),
+        "/" => Piece::Line,
+        "~" => Piece::Softline,
+        "-" => Piece::SoftlineEmpty,
+        ">" => Piece::OpenNest(4),
+        "<" => Piece::CloseNest,
+        "[" => Piece::OpenGroup,
+        "]" => Piece::CloseGroup,
+        _ if hole.starts_with('>') => {
+            let depth = hole[1..]
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("invalid nesting marker `{{{}}}`", hole))?;
+            Piece::OpenNest(depth)
+        }
+        _ => Piece::Hole(hole.to_string()),
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+// Code Generation
+// -------------------------------------------------------------------------------------------------
+
+enum Frame {
+    Root,
+    Nest(i32),
+    Group,
+}
+
+fn build_doc(pieces: Vec<Piece>) -> Result<String, String> {
+    let mut stack: Vec<(Frame, Vec<String>)> = vec![(Frame::Root, vec![])];
+
+    for piece in pieces {
+        let code = match piece {
+            Piece::Text(s) => plain(&format!("static_text({:?})", s)),
+            Piece::Hole(expr) => {
+                expr.parse::<TokenStream>()
+                    .map_err(|_| format!("invalid expression in template hole: `{}`", expr))?;
+                format!(
+                    "match &({}) {{ value => {{ \
+                     use ::pretty_simple::template::{{DocHole as _, ToDocHole as _}}; \
+                     (&::pretty_simple::template::Hole(value)).hole() }} }}",
+                    expr
+                )
+            }
+            Piece::Line => plain("line()"),
+            Piece::Softline => plain("softline()"),
+            Piece::SoftlineEmpty => plain("softline_empty()"),
+            Piece::OpenNest(depth) => {
+                stack.push((Frame::Nest(depth), vec![]));
+                continue;
+            }
+            Piece::OpenGroup => {
+                stack.push((Frame::Group, vec![]));
+                continue;
+            }
+            Piece::CloseNest => match stack.pop() {
+                Some((Frame::Nest(depth), parts)) => format!("{}.nest({})", hcat(parts), depth),
+                _ => return Err("`{<}` does not close a `{>}` region".into()),
+            },
+            Piece::CloseGroup => match stack.pop() {
+                Some((Frame::Group, parts)) => format!("{}.group()", hcat(parts)),
+                _ => return Err("`{]}` does not close a `{[}` region".into()),
+            },
+        };
+        stack.last_mut().unwrap().1.push(code);
+    }
+
+    match stack.pop() {
+        Some((Frame::Root, parts)) if stack.is_empty() => Ok(hcat(parts)),
+        Some((Frame::Nest(_), _)) => Err("unclosed `{>}` region in template".into()),
+        _ => Err("unclosed `{[}` region in template".into()),
+    }
+}
+
+// Text and hints are plain `Doc`s; `template::Join` lifts them, and the values of `ToDoc` holes, to
+// the annotation type of any annotated `Doc` hole they are joined with. The pieces are bound before
+// the traits are brought into scope, so holes' expressions resolve as they would outside the macro.
+fn plain(constructor: &str) -> String {
+    format!("::pretty_simple::Doc::{}", constructor)
+}
+
+fn hcat(parts: Vec<String>) -> String {
+    parts
+        .into_iter()
+        .reduce(|lhs, rhs| {
+            format!(
+                "match ({}, {}) {{ (lhs, rhs) => {{ use ::pretty_simple::template::{{\
+                 JoinDocs as _, JoinPlainLeft as _, JoinPlainRight as _}}; \
+                 (&&&::pretty_simple::template::Join(lhs, rhs)).join() }} }}",
+                lhs, rhs
+            )
+        })
+        .unwrap_or_else(|| plain("nil()"))
+}
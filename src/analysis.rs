@@ -0,0 +1,448 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use crate::{Annotation, Doc, DocFn, DocInner, Str, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Sharing Analysis
+// -------------------------------------------------------------------------------------------------
+
+/// A summary of how much of a document is built from structurally identical subtrees that are
+/// allocated separately. See [`Doc::sharing_report`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SharingReport {
+    /// Distinct node allocations reachable from the document.
+    pub nodes: usize,
+    /// Structurally distinct subtrees among those nodes.
+    pub distinct: usize,
+    /// Nodes that would be unnecessary if identical subtrees were shared (`nodes - distinct`).
+    pub redundant_nodes: usize,
+    /// Text bytes held by those redundant nodes.
+    pub redundant_text_bytes: usize,
+}
+
+impl SharingReport {
+    /// A rough estimate of the bytes [`Doc::intern`] would save.
+    pub fn estimated_savings(&self) -> usize {
+        // Each node is an `Rc` allocation: two reference counts plus the node itself (here, an
+        // unannotated one).
+        let node_size = std::mem::size_of::<DocInner<()>>() + 2 * std::mem::size_of::<usize>();
+        self.redundant_nodes * node_size + self.redundant_text_bytes
+    }
+}
+
+/// The structure of a node, with children replaced by their canonical ids. Closures and
+/// annotations can't be compared, so closure nodes are only identical if they share the same
+/// closure, and annotated nodes only if they are the same node.
+#[derive(PartialEq, Eq, Hash)]
+enum Shape {
+    Empty,
+    Text(Str),
+    Line,
+    LineBreak,
+    Fail,
+    Concat(usize, usize),
+    Nest(Width, usize),
+    Alt(usize, usize),
+    FlatAlt(usize, usize),
+    Nesting(*const ()),
+    Column(*const ()),
+    Depth(*const ()),
+    PageWidth(*const ()),
+    Context(*const ()),
+    Memo(usize),
+    Flat(usize),
+    Expand(usize),
+    Prefix(String, usize),
+    Narrow(Width, usize),
+    Hint(Width, usize),
+    Priority(u8, usize),
+    MapText(*const (), usize),
+    Elide(u8, usize, usize),
+    Annotate(*const ()),
+    EndAnnotation(Width),
+}
+
+/// Assigns every node reachable from a document a canonical id, such that two nodes have the same
+/// id exactly when they are structurally identical.
+struct Canonicalizer<A> {
+    ids: HashMap<*const DocInner<A>, usize>,
+    shapes: HashMap<Shape, usize>,
+}
+
+impl<A: Clone> Canonicalizer<A> {
+    fn new() -> Self {
+        Canonicalizer {
+            ids: HashMap::new(),
+            shapes: HashMap::new(),
+        }
+    }
+
+    fn id(&self, doc: &Doc<A>) -> usize {
+        self.ids[&Rc::as_ptr(&doc.0)]
+    }
+
+    /// Visit every node reachable from `root` (children before parents, each node once), calling
+    /// `visit` with the node, its canonical id, and whether that id is new.
+    fn run(&mut self, root: &Doc<A>, mut visit: impl FnMut(&mut Self, &Doc<A>, usize, bool)) {
+        let mut stack = vec![(root.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if self.ids.contains_key(&Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            if !children_done {
+                let children = children(&doc);
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let shape = match &*doc.0 {
+                DocInner::Empty => Shape::Empty,
+                DocInner::Text(s) => Shape::Text(s.clone()),
+                DocInner::Line => Shape::Line,
+                DocInner::LineBreak => Shape::LineBreak,
+                DocInner::Fail => Shape::Fail,
+                DocInner::Concat(x, y) => Shape::Concat(self.id(x), self.id(y)),
+                DocInner::Nest(i, x) => Shape::Nest(*i, self.id(x)),
+                DocInner::Alt(x, y) => Shape::Alt(self.id(x), self.id(y)),
+                DocInner::FlatAlt(x, y) => Shape::FlatAlt(self.id(x), self.id(y)),
+                DocInner::Nesting(f) => Shape::Nesting(fn_ptr(f)),
+                DocInner::Column(f) => Shape::Column(fn_ptr(f)),
+                DocInner::Depth(f) => Shape::Depth(fn_ptr(f)),
+                DocInner::PageWidth(f) => Shape::PageWidth(fn_ptr(f)),
+                DocInner::Context(f) => Shape::Context(Rc::as_ptr(f) as *const ()),
+                DocInner::Memo(x, _) => Shape::Memo(self.id(x)),
+                DocInner::Flat(x) => Shape::Flat(self.id(x)),
+                DocInner::Expand(x) => Shape::Expand(self.id(x)),
+                DocInner::Prefix(s, x) => Shape::Prefix(s.clone(), self.id(x)),
+                DocInner::Narrow(n, x) => Shape::Narrow(*n, self.id(x)),
+                DocInner::Hint(w, x) => Shape::Hint(*w, self.id(x)),
+                DocInner::Priority(p, x) => Shape::Priority(*p, self.id(x)),
+                DocInner::MapText(f, x) => Shape::MapText(Rc::as_ptr(f) as *const (), self.id(x)),
+                DocInner::Elide(n, x, y) => Shape::Elide(*n, self.id(x), self.id(y)),
+                DocInner::Annotate(..) => Shape::Annotate(Rc::as_ptr(&doc.0) as *const ()),
+                DocInner::EndAnnotation(w) => Shape::EndAnnotation(*w),
+            };
+            let next = self.shapes.len();
+            let id = *self.shapes.entry(shape).or_insert(next);
+            self.ids.insert(Rc::as_ptr(&doc.0), id);
+            visit(self, &doc, id, id == next);
+        }
+    }
+}
+
+pub(crate) fn children<A>(doc: &Doc<A>) -> Vec<Doc<A>> {
+    match &*doc.0 {
+        DocInner::Concat(x, y)
+        | DocInner::Alt(x, y)
+        | DocInner::FlatAlt(x, y)
+        | DocInner::Elide(_, x, y) => vec![x.clone(), y.clone()],
+        DocInner::Nest(_, x)
+        | DocInner::Memo(x, _)
+        | DocInner::Flat(x)
+        | DocInner::Expand(x)
+        | DocInner::Prefix(_, x)
+        | DocInner::Narrow(_, x)
+        | DocInner::Hint(_, x)
+        | DocInner::Priority(_, x)
+        | DocInner::MapText(_, x)
+        | DocInner::Annotate(_, x) => vec![x.clone()],
+        DocInner::Empty
+        | DocInner::Text(_)
+        | DocInner::Line
+        | DocInner::LineBreak
+        | DocInner::Fail
+        | DocInner::Nesting(_)
+        | DocInner::Column(_)
+        | DocInner::Depth(_)
+        | DocInner::PageWidth(_)
+        | DocInner::Context(_)
+        | DocInner::EndAnnotation(_) => vec![],
+    }
+}
+
+fn fn_ptr<A>(f: &DocFn<A>) -> *const () {
+    Rc::as_ptr(f) as *const ()
+}
+
+impl<A: Annotation> Doc<A> {
+    /// Report how many nodes of this document duplicate other, structurally identical subtrees
+    /// (e.g., the same boilerplate built thousands of times), and how much sharing them would
+    /// save. Use [`Doc::intern`] to perform that sharing.
+    pub fn sharing_report(&self) -> SharingReport {
+        let mut report = SharingReport::default();
+        Canonicalizer::new().run(self, |_, doc, _, is_new| {
+            report.nodes += 1;
+            if is_new {
+                report.distinct += 1;
+            } else {
+                report.redundant_nodes += 1;
+                if let DocInner::Text(s) = &*doc.0 {
+                    report.redundant_text_bytes += s.len();
+                }
+            }
+        });
+        report
+    }
+
+    /// Rebuild this document so that structurally identical subtrees are shared, keeping one
+    /// allocation per distinct subtree. The result renders identically.
+    pub fn intern(&self) -> Doc<A> {
+        let mut canonical: Vec<Doc<A>> = vec![];
+        let mut canonicalizer = Canonicalizer::new();
+        canonicalizer.run(self, |canon, doc, id, is_new| {
+            if !is_new {
+                return;
+            }
+            let child = |d: &Doc<A>| canonical[canon.id(d)].clone();
+            let same = |d: &Doc<A>, c: &Doc<A>| Rc::ptr_eq(&d.0, &c.0);
+            // Reuse the node itself unless one of its children has a canonical replacement.
+            let node = match &*doc.0 {
+                DocInner::Concat(x, y) | DocInner::Alt(x, y) => {
+                    let (cx, cy) = (child(x), child(y));
+                    if same(x, &cx) && same(y, &cy) {
+                        doc.clone()
+                    } else if matches!(&*doc.0, DocInner::Concat(..)) {
+                        DocInner::Concat(cx, cy).into_doc()
+                    } else {
+                        DocInner::Alt(cx, cy).into_doc()
+                    }
+                }
+                DocInner::FlatAlt(x, y) => {
+                    let (cx, cy) = (child(x), child(y));
+                    if same(x, &cx) && same(y, &cy) {
+                        doc.clone()
+                    } else {
+                        DocInner::FlatAlt(cx, cy).into_doc()
+                    }
+                }
+                DocInner::Nest(i, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Nest(*i, cx).into_doc()
+                    }
+                }
+                DocInner::Memo(x, _) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        cx.memoize()
+                    }
+                }
+                DocInner::Flat(x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Flat(cx).into_doc()
+                    }
+                }
+                DocInner::Expand(x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Expand(cx).into_doc()
+                    }
+                }
+                DocInner::Prefix(s, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Prefix(s.clone(), cx).into_doc()
+                    }
+                }
+                DocInner::Narrow(n, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Narrow(*n, cx).into_doc()
+                    }
+                }
+                DocInner::Hint(w, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Hint(*w, cx).into_doc()
+                    }
+                }
+                DocInner::Priority(p, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Priority(*p, cx).into_doc()
+                    }
+                }
+                DocInner::MapText(f, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::MapText(f.clone(), cx).into_doc()
+                    }
+                }
+                DocInner::Elide(n, x, y) => {
+                    let (cx, cy) = (child(x), child(y));
+                    if same(x, &cx) && same(y, &cy) {
+                        doc.clone()
+                    } else {
+                        DocInner::Elide(*n, cx, cy).into_doc()
+                    }
+                }
+                DocInner::Annotate(a, x) => {
+                    let cx = child(x);
+                    if same(x, &cx) {
+                        doc.clone()
+                    } else {
+                        DocInner::Annotate(a.clone(), cx).into_doc()
+                    }
+                }
+                _ => doc.clone(),
+            };
+            debug_assert_eq!(canonical.len(), id);
+            canonical.push(node);
+        });
+        canonical[canonicalizer.id(self)].clone()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Statistics
+// -------------------------------------------------------------------------------------------------
+
+/// The size and shape of a document. See [`Doc::stats`].
+///
+/// Shared subtrees are counted once, as they're allocated once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DocStats {
+    /// Distinct node allocations reachable from the document.
+    pub nodes: usize,
+    /// The number of nodes on the longest path from the root to a leaf.
+    pub max_depth: usize,
+    /// Bytes of text held by those nodes.
+    pub text_bytes: usize,
+    /// Choices between alternative layouts, such as groups.
+    pub alternatives: usize,
+}
+
+impl<A: Annotation> Doc<A> {
+    /// Measure this document, to find the parts of a printer that generate pathological ones:
+    /// huge node counts, deep nesting, or many alternatives (each of which costs lookahead).
+    /// Documents built by closures (e.g., [`Doc::column`]) aren't built until layout, so they
+    /// count as single nodes.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::sep(["a", "bc"].map(Doc::text));
+    /// let stats = doc.stats();
+    /// assert_eq!(stats.text_bytes, 3);
+    /// assert_eq!(stats.alternatives, 1);
+    /// assert_eq!(stats.max_depth, 5);
+    /// ```
+    pub fn stats(&self) -> DocStats {
+        let mut stats = DocStats::default();
+        let mut depths: HashMap<*const DocInner<A>, usize> = HashMap::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if depths.contains_key(&Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            let children = children(&doc);
+            if !children_done {
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            stats.nodes += 1;
+            match &*doc.0 {
+                DocInner::Text(s) => stats.text_bytes += s.len(),
+                DocInner::Alt(..) => stats.alternatives += 1,
+                _ => (),
+            }
+            let depth = children
+                .iter()
+                .map(|child| depths[&Rc::as_ptr(&child.0)])
+                .max()
+                .unwrap_or(0)
+                + 1;
+            depths.insert(Rc::as_ptr(&doc.0), depth);
+        }
+        stats.max_depth = depths[&Rc::as_ptr(&self.0)];
+        stats
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Validation
+// -------------------------------------------------------------------------------------------------
+
+/// A misuse of the document combinators found by [`Doc::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// A text document contains a line break, which layout can't see: the lines after it are
+    /// neither indented nor measured. Use [`Doc::lines`] or [`Doc::text_sanitized`] instead.
+    NewlineInText { text: String },
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::NewlineInText { text } => {
+                write!(f, "text contains a line break: {:?}", text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl<A: Annotation> Doc<A> {
+    /// Check the document for misuses that make layouts go wrong without failing, reporting the
+    /// first one found. Documents built by closures (e.g., [`Doc::column`]) aren't built until
+    /// layout, so they aren't checked.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::hsep([Doc::text("ok"), Doc::text("two\nlines")]);
+    /// assert_eq!(
+    ///     doc.validate().unwrap_err().to_string(),
+    ///     r#"text contains a line break: "two\nlines""#
+    /// );
+    /// assert!(<Doc>::lines("two\nlines").validate().is_ok());
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![self.clone()];
+        while let Some(doc) = stack.pop() {
+            if !seen.insert(Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            if let DocInner::Text(s) = &*doc.0 {
+                if s.contains('\n') {
+                    return Err(ValidationError::NewlineInText {
+                        text: s.to_string(),
+                    });
+                }
+            }
+            stack.extend(children(&doc).into_iter().rev());
+        }
+        Ok(())
+    }
+}
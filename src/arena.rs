@@ -0,0 +1,336 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{borrow::Cow, collections::VecDeque};
+
+use crate::{Annotation, Doc, LeafDoc, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Document Arenas
+// -------------------------------------------------------------------------------------------------
+
+/// A builder for large documents, whose nodes are stored side by side in one growing buffer and
+/// referred to by [`ArenaDoc`] handles. Handles are plain indices, so combining them costs no
+/// allocation or reference counting, and the buffer can be cleared and reused for the next
+/// document.
+///
+/// [`DocArena::build`] makes a [`Doc`] of the nodes a handle reaches, in one pass. Seeing the
+/// whole document first, it makes one text node of each run of text between line breaks (and
+/// other structure), and leaves out nesting and groups around text with no line breaks, so the
+/// result has far fewer nodes, each its own allocation, than the same document built directly.
+/// Nodes that end up unused are never turned into documents.
+///
+/// The arena offers the basic combinators; [`DocArena::import`] brings in any other document.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let mut arena: DocArena = DocArena::new();
+/// let mut lines = vec![];
+/// for (name, value) in [("x", "1"), ("y", "2")] {
+///     let tokens = [arena.text("let "), arena.text(name), arena.text(" = "), arena.text(value)];
+///     let statement = tokens.into_iter().reduce(|x, y| arena.concat(x, y)).unwrap();
+///     let semicolon = arena.text(";");
+///     lines.push(arena.concat(statement, semicolon));
+/// }
+/// let line = arena.line();
+/// let first = arena.concat(lines[0], line);
+/// let body = arena.concat(first, lines[1]);
+///
+/// let doc = arena.build(body);
+/// // Two lines of text, the line break, and the two concatenations joining them.
+/// assert_eq!(doc.stats().nodes, 5);
+/// assert_eq!(doc.render(80), "let x = 1;\nlet y = 2;");
+/// ```
+pub struct DocArena<A = ()> {
+    nodes: Vec<Node<A>>,
+}
+
+/// A document in a [`DocArena`]. Handles are only meaningful to the arena that made them, until
+/// it's cleared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArenaDoc(u32);
+
+// Children are always added before their parents, so they have smaller indices.
+enum Node<A> {
+    Doc(Doc<A>),
+    Text(Cow<'static, str>),
+    Concat(u32, u32),
+    Nest(Width, u32),
+    Group(u32),
+    Alt(u32, u32),
+    Annotate(A, u32),
+}
+
+impl<A> Node<A> {
+    fn children(&self) -> [Option<u32>; 2] {
+        match self {
+            Node::Doc(_) | Node::Text(_) => [None, None],
+            Node::Nest(_, x) | Node::Group(x) | Node::Annotate(_, x) => [Some(*x), None],
+            Node::Concat(x, y) | Node::Alt(x, y) => [Some(*x), Some(*y)],
+        }
+    }
+}
+
+impl<A: Annotation> Default for DocArena<A> {
+    fn default() -> Self {
+        DocArena::new()
+    }
+}
+
+impl<A: Annotation> DocArena<A> {
+    /// An empty arena.
+    pub fn new() -> Self {
+        DocArena { nodes: vec![] }
+    }
+
+    /// An empty arena with room for `nodes` nodes.
+    pub fn with_capacity(nodes: usize) -> Self {
+        DocArena {
+            nodes: Vec::with_capacity(nodes),
+        }
+    }
+
+    /// How many nodes the arena holds.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the arena holds no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Remove every node, keeping the buffer for the next document. Handles made before are
+    /// no longer meaningful.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    fn push(&mut self, node: Node<A>) -> ArenaDoc {
+        let id = u32::try_from(self.nodes.len()).expect("at most u32::MAX nodes in an arena");
+        self.nodes.push(node);
+        ArenaDoc(id)
+    }
+
+    /// An existing document, e.g., one made with a combinator the arena doesn't offer.
+    pub fn import(&mut self, doc: Doc<A>) -> ArenaDoc {
+        self.push(Node::Doc(doc))
+    }
+
+    /// See [`Doc::nil`].
+    pub fn nil(&mut self) -> ArenaDoc {
+        self.text("")
+    }
+
+    /// See [`Doc::text`]. String literals aren't copied.
+    pub fn text<S: Into<Cow<'static, str>>>(&mut self, text: S) -> ArenaDoc {
+        self.push(Node::Text(text.into()))
+    }
+
+    /// See [`Doc::space`].
+    pub fn space(&mut self) -> ArenaDoc {
+        self.text(" ")
+    }
+
+    /// See [`Doc::line`].
+    pub fn line(&mut self) -> ArenaDoc {
+        self.import(Doc::<A>::line())
+    }
+
+    /// See [`Doc::softline`].
+    pub fn softline(&mut self) -> ArenaDoc {
+        self.import(Doc::<A>::softline())
+    }
+
+    /// See [`Doc::line_break`].
+    pub fn line_break(&mut self) -> ArenaDoc {
+        self.import(Doc::<A>::line_break())
+    }
+
+    /// See [`Doc::concat`].
+    pub fn concat(&mut self, x: ArenaDoc, y: ArenaDoc) -> ArenaDoc {
+        self.push(Node::Concat(x.0, y.0))
+    }
+
+    /// See [`Doc::nest`].
+    pub fn nest(&mut self, x: ArenaDoc, depth: Width) -> ArenaDoc {
+        self.push(Node::Nest(depth, x.0))
+    }
+
+    /// See [`Doc::group`].
+    pub fn group(&mut self, x: ArenaDoc) -> ArenaDoc {
+        self.push(Node::Group(x.0))
+    }
+
+    /// See [`Doc::alt`].
+    pub fn alt(&mut self, x: ArenaDoc, y: ArenaDoc) -> ArenaDoc {
+        self.push(Node::Alt(x.0, y.0))
+    }
+
+    /// See [`Doc::annotate`].
+    pub fn annotate(&mut self, annotation: A, x: ArenaDoc) -> ArenaDoc {
+        self.push(Node::Annotate(annotation, x.0))
+    }
+
+    /// The document `root` refers to. Nodes reachable from `root` more than once (because a
+    /// handle was used more than once) are shared in the result.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `root` was made by another arena with more nodes.
+    pub fn build(&self, root: ArenaDoc) -> Doc<A> {
+        let root = root.0 as usize;
+        assert!(root < self.nodes.len(), "the document is from this arena");
+        // Children come before their parents, so one pass down from the root counts the uses of
+        // the nodes it reaches, and one pass back up builds them, children first.
+        let mut uses = vec![0u32; root + 1];
+        uses[root] = 1;
+        for id in (0..=root).rev() {
+            if uses[id] > 0 {
+                for child in self.nodes[id].children().into_iter().flatten() {
+                    uses[child as usize] = uses[child as usize].saturating_add(1);
+                }
+            }
+        }
+        let mut built: Vec<Option<Run<A>>> = (0..=root).map(|_| None).collect();
+        for id in (0..=root).filter(|id| uses[*id] > 0) {
+            let mut child = |x: &u32| {
+                match uses[*x as usize] {
+                    1 => built[*x as usize].take(),
+                    _ => built[*x as usize].clone(),
+                }
+                .expect("children are built first")
+            };
+            let run = match &self.nodes[id] {
+                Node::Doc(doc) => Run::doc(doc.clone()),
+                Node::Text(text) => Run::text(text.clone()),
+                Node::Concat(x, y) => {
+                    let x = child(x);
+                    x.append(child(y))
+                }
+                Node::Nest(depth, x) => child(x).wrap(|doc| doc.nest(*depth)),
+                Node::Group(x) => child(x).wrap(Doc::group),
+                Node::Alt(x, y) => {
+                    let x = child(x).into_doc();
+                    Run::doc(x.alt(child(y).into_doc()))
+                }
+                Node::Annotate(a, x) => Run::doc(Doc::annotate(a.clone(), child(x).into_doc())),
+            };
+            // Shared nodes are built once, and their parents share the result.
+            built[id] = Some(match uses[id] {
+                1 => run,
+                _ => Run {
+                    line_free: run.line_free,
+                    ..Run::doc(run.into_doc())
+                },
+            });
+        }
+        built[root]
+            .take()
+            .expect("the root is built last")
+            .into_doc()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Building
+// -------------------------------------------------------------------------------------------------
+
+// A document being built: a sequence of text and finished documents, with the text merged into
+// as few nodes as possible once the sequence is finished.
+struct Run<A> {
+    parts: VecDeque<Part<A>>,
+    line_free: bool,
+}
+
+enum Part<A> {
+    Text(Cow<'static, str>),
+    Doc(Doc<A>),
+}
+
+impl<A: Annotation> Clone for Run<A> {
+    fn clone(&self) -> Self {
+        let parts = self.parts.iter().map(|part| match part {
+            Part::Text(text) => Part::Text(text.clone()),
+            Part::Doc(doc) => Part::Doc(doc.clone()),
+        });
+        Run {
+            parts: parts.collect(),
+            line_free: self.line_free,
+        }
+    }
+}
+
+impl<A: Annotation> Run<A> {
+    fn text(text: Cow<'static, str>) -> Self {
+        let parts = if text.is_empty() {
+            VecDeque::new()
+        } else {
+            VecDeque::from([Part::Text(text)])
+        };
+        Run {
+            parts,
+            line_free: true,
+        }
+    }
+
+    fn doc(doc: Doc<A>) -> Self {
+        Run {
+            parts: VecDeque::from([Part::Doc(doc)]),
+            line_free: false,
+        }
+    }
+
+    // The shorter sequence is moved into the longer, so building a chain of concatenations
+    // moves each part at most a logarithmic number of times.
+    fn append(mut self, mut other: Run<A>) -> Self {
+        if self.parts.len() >= other.parts.len() {
+            self.parts.append(&mut other.parts);
+        } else {
+            while let Some(part) = self.parts.pop_back() {
+                other.parts.push_front(part);
+            }
+            self.parts = other.parts;
+        }
+        self.line_free &= other.line_free;
+        self
+    }
+
+    // Text without line breaks is laid out the same, nested or grouped or not.
+    fn wrap(self, f: impl FnOnce(Doc<A>) -> Doc<A>) -> Self {
+        if self.line_free {
+            self
+        } else {
+            Run::doc(f(self.into_doc()))
+        }
+    }
+
+    fn into_doc(self) -> Doc<A> {
+        let mut docs = vec![];
+        let mut texts = vec![];
+        for part in self.parts {
+            match part {
+                Part::Text(text) => texts.push(text),
+                Part::Doc(doc) => {
+                    docs.extend(merge_text(&mut texts));
+                    docs.push(doc);
+                }
+            }
+        }
+        docs.extend(merge_text(&mut texts));
+        Doc::hcat(docs)
+    }
+}
+
+fn merge_text<A: Annotation>(texts: &mut Vec<Cow<'static, str>>) -> Option<Doc<A>> {
+    match texts.len() {
+        0 => None,
+        1 => Some(match texts.pop().unwrap() {
+            Cow::Borrowed(text) => Doc::<A>::static_text(text),
+            Cow::Owned(text) => Doc::<A>::text(text),
+        }),
+        _ => Some(Doc::<A>::text(texts.drain(..).collect::<String>())),
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::rc::Rc;
+
+use crate::OutputPosition;
+
+// -------------------------------------------------------------------------------------------------
+// Delimiter Checking
+// -------------------------------------------------------------------------------------------------
+
+type SkipFn = Rc<dyn Fn(&str) -> Option<usize>>;
+
+/// Checks that the delimiters in rendered output are balanced, to catch printers that produce
+/// syntactically invalid code.
+///
+/// By default the pairs are `()`, `[]`, and `{}`. Delimiters inside string literals or comments
+/// should be ignored, which the checker learns about through [`Delimiters::skip`].
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// // Skip string literals.
+/// let check = Delimiters::new().skip(|rest| {
+///     let body = rest.strip_prefix('"')?;
+///     Some(body.find('"').map_or(rest.len(), |end| end + 2))
+/// });
+/// assert!(check.check("f(\"(\", [1, 2])").is_ok());
+///
+/// let err = check.check("f(x]\n").unwrap_err();
+/// assert_eq!(err.to_string(), "1:4: found `]`, expected `)` to close `(` at 1:2");
+/// ```
+#[derive(Clone)]
+pub struct Delimiters {
+    pairs: Vec<(char, char)>,
+    skips: Vec<SkipFn>,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}')],
+            skips: vec![],
+        }
+    }
+}
+
+impl Delimiters {
+    /// A checker for `()`, `[]`, and `{}`.
+    pub fn new() -> Self {
+        Delimiters::default()
+    }
+
+    /// Check these pairs of opening and closing delimiters instead.
+    pub fn pairs(mut self, pairs: impl IntoIterator<Item = (char, char)>) -> Self {
+        self.pairs = pairs.into_iter().collect();
+        self
+    }
+
+    /// Ignore parts of the output, such as string literals and comments. At each position, `skip`
+    /// is called with the rest of the output, and returns how many bytes to skip from there (or
+    /// `None` to check the position as usual).
+    pub fn skip(mut self, skip: impl Fn(&str) -> Option<usize> + 'static) -> Self {
+        self.skips.push(Rc::new(skip));
+        self
+    }
+
+    /// Check `output`, reporting the first delimiter that doesn't match.
+    pub fn check(&self, output: &str) -> Result<(), DelimiterError> {
+        let mut open: Vec<(char, OutputPosition)> = vec![];
+        let mut position = OutputPosition::default();
+        let mut offset = 0;
+        while let Some(ch) = output[offset..].chars().next() {
+            let rest = &output[offset..];
+            if let Some(skipped) = self.skips.iter().find_map(|skip| skip(rest)) {
+                // Skip whole characters, and at least one so that checking always progresses.
+                let end = (offset + skipped.max(1)).min(output.len());
+                let end = (end..=output.len())
+                    .find(|end| output.is_char_boundary(*end))
+                    .unwrap_or(output.len());
+                advance(&mut position, &output[offset..end]);
+                offset = end;
+                continue;
+            }
+            if self.pairs.iter().any(|(opening, _)| *opening == ch) {
+                open.push((ch, position));
+            } else if let Some((opening, _)) = self.pairs.iter().find(|(_, closing)| *closing == ch)
+            {
+                match open.pop() {
+                    Some((found, _)) if found == *opening => (),
+                    unmatched => {
+                        return Err(DelimiterError::Mismatched {
+                            found: ch,
+                            position,
+                            open: unmatched.map(|(ch, position)| {
+                                let closing = self.closing(ch);
+                                (ch, closing, position)
+                            }),
+                        })
+                    }
+                }
+            }
+            advance(&mut position, &rest[..ch.len_utf8()]);
+            offset += ch.len_utf8();
+        }
+        match open.pop() {
+            Some((delimiter, position)) => Err(DelimiterError::Unclosed {
+                delimiter,
+                position,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn closing(&self, opening: char) -> char {
+        self.pairs
+            .iter()
+            .find(|(open, _)| *open == opening)
+            .map_or(opening, |(_, close)| *close)
+    }
+}
+
+fn advance(position: &mut OutputPosition, text: &str) {
+    match text.rfind('\n') {
+        Some(last) => {
+            position.line += text.matches('\n').count();
+            position.column = text.len() - last - 1;
+        }
+        None => position.column += text.len(),
+    }
+}
+
+/// A delimiter mismatch found by [`Delimiters::check`]. Positions are displayed starting from
+/// line and column 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DelimiterError {
+    /// A closing delimiter that doesn't close the innermost open one. `open` is that delimiter,
+    /// the delimiter that would close it, and where it was opened, if any was open.
+    Mismatched {
+        found: char,
+        position: OutputPosition,
+        open: Option<(char, char, OutputPosition)>,
+    },
+    /// An opening delimiter that is never closed.
+    Unclosed {
+        delimiter: char,
+        position: OutputPosition,
+    },
+}
+
+impl DelimiterError {
+    /// Where the problem was found.
+    pub fn position(&self) -> OutputPosition {
+        match self {
+            DelimiterError::Mismatched { position, .. }
+            | DelimiterError::Unclosed { position, .. } => *position,
+        }
+    }
+}
+
+struct OneBased(OutputPosition);
+
+impl std::fmt::Display for OneBased {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.0.line + 1, self.0.column + 1)
+    }
+}
+
+impl std::fmt::Display for DelimiterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DelimiterError::Mismatched {
+                found,
+                position,
+                open: Some((open, close, opened)),
+            } => write!(
+                f,
+                "{}: found `{}`, expected `{}` to close `{}` at {}",
+                OneBased(*position),
+                found,
+                close,
+                open,
+                OneBased(*opened)
+            ),
+            DelimiterError::Mismatched {
+                found,
+                position,
+                open: None,
+            } => write!(
+                f,
+                "{}: found `{}` with nothing to close",
+                OneBased(*position),
+                found
+            ),
+            DelimiterError::Unclosed {
+                delimiter,
+                position,
+            } => write!(
+                f,
+                "{}: `{}` is never closed",
+                OneBased(*position),
+                delimiter
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DelimiterError {}
@@ -0,0 +1,138 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! A JSON printer: arrays and objects stay on one line if they fit, and otherwise put one entry
+//! per line.
+
+use std::io::{self, Write};
+
+use crate::{Doc, NumberFormat, Width};
+
+// -------------------------------------------------------------------------------------------------
+// JSON AST
+// -------------------------------------------------------------------------------------------------
+
+/// A JSON value. Objects keep their fields in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// A string value.
+    pub fn string<S: Into<String>>(s: S) -> Self {
+        Json::String(s.into())
+    }
+
+    /// An object with the given fields, in order.
+    pub fn object<S: Into<String>>(fields: Vec<(S, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// JSON to Doc
+// -------------------------------------------------------------------------------------------------
+
+/// Print `j`, breaking arrays and objects that don't fit.
+pub fn json_doc_pretty(j: &Json) -> Doc {
+    json_doc_with(j, &NumberFormat::new())
+}
+
+/// Like [`json_doc_pretty`], printing numbers according to `numbers`.
+pub fn json_doc_with(j: &Json, numbers: &NumberFormat) -> Doc {
+    match j {
+        Json::Null => Doc::text("null"),
+        Json::Bool(b) => Doc::text(b.to_string()),
+        Json::Int(n) => numbers.int(*n),
+        Json::Float(f) => numbers.float(*f),
+        Json::String(s) => Doc::text(escape_string(s)),
+        Json::Array(items) => bracketed("[", items.iter().map(|j| json_doc_with(j, numbers)), "]"),
+        Json::Object(fields) => bracketed(
+            "{",
+            fields.iter().map(|(k, v)| {
+                Doc::text(escape_string(k))
+                    .concat(Doc::text(":"))
+                    .concat_space(json_doc_with(v, numbers))
+            }),
+            "}",
+        ),
+    }
+}
+
+/// Print `j` on a single line.
+pub fn json_doc_compact(j: &Json) -> Doc {
+    json_doc_pretty(j).flatten()
+}
+
+/// `open a, b, c close` if it fits, otherwise one entry per line with a 2-space indent.
+fn bracketed(open: &str, entries: impl Iterator<Item = Doc>, close: &str) -> Doc {
+    let entries: Vec<Doc> = entries.collect();
+    if entries.is_empty() {
+        return Doc::text(open).concat(Doc::text(close));
+    }
+    let flat = Doc::text(open)
+        .concat(Doc::intersperse(
+            entries.iter().cloned(),
+            Doc::comma().concat(Doc::space()),
+        ))
+        .concat(Doc::text(close));
+    let broken = Doc::text(open)
+        .concat(
+            Doc::line()
+                .concat(Doc::intersperse(entries, Doc::comma().concat(Doc::line())))
+                .nest(2),
+        )
+        .concat(Doc::line())
+        .concat(Doc::text(close));
+    Doc::alt(flat, broken)
+}
+
+fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// -------------------------------------------------------------------------------------------------
+// JSON Lines
+// -------------------------------------------------------------------------------------------------
+
+/// Stream `records` to `out` as JSON Lines (NDJSON): one compact document per line.
+///
+/// If `pretty_oversized` is set, records whose compact form is wider than `width` are instead
+/// rendered in pretty (multi-line) form. Note that such output is no longer strict NDJSON.
+pub fn write_json_lines<'a, W: Write>(
+    out: &mut W,
+    records: impl IntoIterator<Item = &'a Json>,
+    width: Width,
+    pretty_oversized: bool,
+) -> io::Result<()> {
+    for record in records {
+        let compact = json_doc_compact(record).render(width);
+        if pretty_oversized && compact.len() > width as usize {
+            writeln!(out, "{}", json_doc_pretty(record).render(width))?;
+        } else {
+            writeln!(out, "{}", compact)?;
+        }
+    }
+    out.flush()
+}
@@ -0,0 +1,98 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Complete printers for a few small languages, to import or copy as a starting point. Each has
+//! a matching binary under `examples/` that prints a sample document:
+//!
+//! ```text
+//! cargo run --example json -- --width 30
+//! cargo run --example lambda -- --width 20 --style compact
+//! ```
+
+pub mod json;
+pub mod lambda;
+pub mod xml;
+
+use crate::{Doc, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Command-Line Options
+// -------------------------------------------------------------------------------------------------
+
+/// How the example binaries print their documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PrintStyle {
+    /// Break lines to fit the width.
+    #[default]
+    Pretty,
+    /// Print everything on one line.
+    Compact,
+}
+
+/// The command-line options of the example binaries: `[--width N] [--style pretty|compact]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Args {
+    pub width: Width,
+    pub style: PrintStyle,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            width: 80,
+            style: PrintStyle::Pretty,
+        }
+    }
+}
+
+impl Args {
+    pub const USAGE: &'static str = "usage: [--width N] [--style pretty|compact]";
+
+    /// Parse the options from `args`, which excludes the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut result = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let mut next_value = || {
+                value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for `{}`", flag))
+            };
+            match flag.as_str() {
+                "--width" => {
+                    let width = next_value()?;
+                    result.width = width
+                        .parse()
+                        .map_err(|_| format!("invalid width `{}`", width))?;
+                }
+                "--style" => {
+                    result.style = match next_value()?.as_str() {
+                        "pretty" => PrintStyle::Pretty,
+                        "compact" => PrintStyle::Compact,
+                        other => return Err(format!("unknown style `{}`", other)),
+                    };
+                }
+                _ => return Err(format!("unknown option `{}`", flag)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Parse the process's command-line options.
+    pub fn from_env() -> Result<Args, String> {
+        Args::parse(std::env::args().skip(1))
+    }
+
+    /// Render `doc` as the options ask.
+    pub fn render(&self, doc: Doc) -> String {
+        match self.style {
+            PrintStyle::Pretty => doc.render(self.width),
+            PrintStyle::Compact => doc.flatten().render(self.width),
+        }
+    }
+}
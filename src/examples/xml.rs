@@ -0,0 +1,419 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! An XML printer in the style of Wadler's "A prettier printer", with a small parser for
+//! round-trip checks.
+
+use crate::{Doc, Width};
+
+// -------------------------------------------------------------------------------------------------
+// XML AST a la Wadler
+// -------------------------------------------------------------------------------------------------
+
+/// An XML element or text node.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum XML {
+    Element {
+        name: String,
+        attrs: Vec<Attribute>,
+        body: Vec<XML>,
+    },
+    Text(String),
+}
+
+/// A `name="value"` attribute. The value is unescaped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub value: String,
+}
+
+impl Attribute {
+    /// An attribute with the given name and (unescaped) value.
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl XML {
+    /// A text node. The text is unescaped.
+    pub fn text<S: Into<String>>(s: S) -> Self {
+        XML::Text(s.into())
+    }
+
+    /// An element with the given attributes and children.
+    pub fn element<N: Into<String>>(name: N, attrs: Vec<Attribute>, body: Vec<XML>) -> Self {
+        XML::Element {
+            name: name.into(),
+            attrs,
+            body,
+        }
+    }
+
+    /// Convenience: `XML::elem("a", [("href","/")], [XML::text("home")])`
+    pub fn elem(name: &str, attrs: Vec<(&str, &str)>, children: Vec<XML>) -> Self {
+        let name = name.into();
+        let attrs = attrs
+            .into_iter()
+            .map(|p| {
+                let (n, v) = p;
+                Attribute::new(n, v)
+            })
+            .collect();
+        let body = children.into_iter().collect();
+        XML::Element { name, attrs, body }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// XML to Doc
+// -------------------------------------------------------------------------------------------------
+
+/// Print `x`, putting the children of elements that don't fit on their own lines. Elements
+/// with mixed content (text alongside elements) are printed exactly as given.
+pub fn xml_doc_pretty(x: &XML) -> Doc {
+    match x {
+        XML::Text(s) => Doc::text(escape_text(s)),
+
+        XML::Element { name, attrs, body } => {
+            let open_head = Doc::langle()
+                .concat(Doc::text(name.clone()))
+                .concat(attrs_doc(attrs));
+
+            if body.is_empty() {
+                // <tag .../>
+                return open_head.concat_space(Doc::text("/>"));
+            }
+
+            let open = open_head.clone().concat(Doc::rangle());
+            let close = Doc::text("</".to_string())
+                .concat(Doc::text(name.clone()))
+                .concat(Doc::rangle());
+
+            // Mixed content is whitespace-sensitive, so children are emitted exactly as given.
+            if body.iter().any(|entry| matches!(entry, XML::Text(_))) {
+                return open
+                    .concat(Doc::hcat(body.iter().map(xml_doc_pretty)))
+                    .concat(close);
+            }
+
+            // Soft separator between children: space when flat, newline when broken
+            let kids_soft = Doc::sep(body.iter().map(xml_doc_pretty));
+
+            // Inline: no leading/trailing softlines → no stray spaces
+            let inline = open
+                .clone()
+                .concat(kids_soft.clone().flatten()) // children separated by spaces
+                .concat(close.clone());
+
+            // Block: one child per line, indented. No .group() on the kids.
+            let kids_vertical = body
+                .iter()
+                .map(xml_doc_pretty)
+                .reduce(|a, b| a.concat(Doc::line()).concat(b))
+                .unwrap_or_else(Doc::nil);
+
+            let block = open
+                .concat(Doc::line())
+                .concat(kids_vertical.indent(4))
+                .concat(Doc::line())
+                .concat(close);
+
+            // Choose: first if it fits, otherwise the vertical one.
+            Doc::alt(inline, block)
+        }
+    }
+}
+
+/// Print `x` on a single line.
+pub fn xml_doc_compact(x: &XML) -> Doc {
+    xml_doc_pretty(x).flatten()
+}
+
+// -------------------------------------------------------------------------------------------------
+// Attributes
+// -------------------------------------------------------------------------------------------------
+
+fn attrs_doc(attrs: &[Attribute]) -> Doc {
+    if attrs.is_empty() {
+        return Doc::nil();
+    }
+    let parts = attrs.iter().map(|a| {
+        Doc::text(&a.name)
+            .concat(Doc::text("=\""))
+            .concat(Doc::text(escape_attr(&a.value)))
+            .concat(Doc::text("\""))
+    });
+    // Leading space before first attribute, then space-separated list.
+    Doc::text(" ").concat(Doc::hsep(parts))
+}
+
+fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn escape_attr(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+// -------------------------------------------------------------------------------------------------
+// Parsing
+// -------------------------------------------------------------------------------------------------
+
+/// Parse a (small) subset of XML: elements, attributes, text, and the predefined and numeric
+/// entities. Declarations (`<?...?>`) and comments are skipped.
+pub fn parse_xml(input: &str) -> Result<XML, String> {
+    let mut parser = Parser { input, pos: 0 };
+    parser.skip_misc();
+    let root = parser.element()?;
+    parser.skip_misc();
+    if parser.pos != input.len() {
+        return Err(format!("trailing content at byte {}", parser.pos));
+    }
+    Ok(normalize(root))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}` at byte {}", s, self.pos))
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        let trimmed = self.rest().trim_start();
+        self.pos = self.input.len() - trimmed.len();
+    }
+
+    fn skip_until(&mut self, end: &str) -> Result<(), String> {
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len();
+                Ok(())
+            }
+            None => Err(format!("unterminated construct, expected `{}`", end)),
+        }
+    }
+
+    fn skip_misc(&mut self) {
+        loop {
+            self.skip_ws();
+            let skipped = if self.rest().starts_with("<?") {
+                self.skip_until("?>")
+            } else if self.rest().starts_with("<!--") {
+                self.skip_until("-->")
+            } else {
+                break;
+            };
+            if skipped.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(format!("expected a name at byte {}", self.pos));
+        }
+        let name = self.rest()[..len].to_string();
+        self.pos += len;
+        Ok(name)
+    }
+
+    fn element(&mut self) -> Result<XML, String> {
+        self.expect("<")?;
+        let name = self.name()?;
+        let mut attrs = vec![];
+        loop {
+            self.skip_ws();
+            if self.eat("/>") {
+                return Ok(XML::element(name, attrs, vec![]));
+            }
+            if self.eat(">") {
+                break;
+            }
+            let attr_name = self.name()?;
+            self.skip_ws();
+            self.expect("=")?;
+            self.skip_ws();
+            let quote = if self.eat("\"") {
+                "\""
+            } else {
+                self.expect("'")?;
+                "'"
+            };
+            let len = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| "unterminated attribute value".to_string())?;
+            let value = unescape(&self.rest()[..len])?;
+            self.pos += len + 1;
+            attrs.push(Attribute::new(attr_name, value));
+        }
+
+        let mut body = vec![];
+        loop {
+            if self.eat("</") {
+                let close = self.name()?;
+                if close != name {
+                    return Err(format!("mismatched `</{}>` for `<{}>`", close, name));
+                }
+                self.skip_ws();
+                self.expect(">")?;
+                return Ok(XML::element(name, attrs, body));
+            }
+            if self.rest().starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if self.rest().starts_with('<') {
+                body.push(self.element()?);
+            } else if self.rest().is_empty() {
+                return Err(format!("unclosed element `<{}>`", name));
+            } else {
+                let len = self.rest().find('<').unwrap_or(self.rest().len());
+                body.push(XML::Text(unescape(&self.rest()[..len])?));
+                self.pos += len;
+            }
+        }
+    }
+}
+
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('&') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let end = rest
+            .find(';')
+            .ok_or_else(|| format!("unterminated entity in `{}`", s))?;
+        let entity = &rest[..end];
+        let ch = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| format!("unknown entity `&{};`", entity))?
+            }
+        };
+        out.push(ch);
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Canonicalize the parts of a document that printing may legitimately change: adjacent text
+/// nodes are merged, empty text is dropped, and whitespace-only text is dropped from elements
+/// whose content is not mixed (i.e., that contain no other text).
+pub fn normalize(x: XML) -> XML {
+    match x {
+        XML::Text(s) => XML::Text(s),
+        XML::Element { name, attrs, body } => {
+            let mut merged: Vec<XML> = vec![];
+            for child in body.into_iter().map(normalize) {
+                match (merged.last_mut(), child) {
+                    (_, XML::Text(s)) if s.is_empty() => (),
+                    (Some(XML::Text(prev)), XML::Text(s)) => prev.push_str(&s),
+                    (_, child) => merged.push(child),
+                }
+            }
+            let mixed = merged
+                .iter()
+                .any(|c| matches!(c, XML::Text(s) if !s.trim().is_empty()));
+            if !mixed {
+                merged.retain(|c| !matches!(c, XML::Text(_)));
+            }
+            XML::Element {
+                name,
+                attrs,
+                body: merged,
+            }
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Round-Tripping
+// -------------------------------------------------------------------------------------------------
+
+/// Check that printing `x` (both pretty, at `width`, and compact) and parsing the result yields
+/// the same document as `x`, up to [`normalize`].
+pub fn check_roundtrip(x: &XML, width: Width) -> Result<(), String> {
+    let expected = normalize(x.clone());
+    for printed in [
+        xml_doc_pretty(x).render(width),
+        xml_doc_compact(x).render(width),
+    ] {
+        let reparsed = parse_xml(&printed)?;
+        if reparsed != expected {
+            return Err(format!(
+                "printing changed the document:\n{}\nexpected: {:?}\nfound:    {:?}",
+                printed, expected, reparsed
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check `parse → print → parse` equivalence for an XML source string.
+pub fn check_source_roundtrip(src: &str, width: Width) -> Result<(), String> {
+    check_roundtrip(&parse_xml(src)?, width)
+}
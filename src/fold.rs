@@ -0,0 +1,200 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{analysis::children, Annotation, Doc, DocInner, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Document Nodes
+// -------------------------------------------------------------------------------------------------
+
+/// One node of a document, with its children, for tools that analyze or transform documents.
+/// See [`Doc::node`] and [`Doc::fold`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DocNode<'a, A> {
+    /// See [`Doc::nil`].
+    Nil,
+    /// See [`Doc::text`].
+    Text(&'a str),
+    /// See [`Doc::line`].
+    Line,
+    /// See [`Doc::line_break`].
+    LineBreak,
+    /// See [`Doc::fail`].
+    Fail,
+    /// See [`Doc::concat`].
+    Concat(&'a Doc<A>, &'a Doc<A>),
+    /// See [`Doc::nest`].
+    Nest(Width, &'a Doc<A>),
+    /// See [`Doc::alt`].
+    Alt(&'a Doc<A>, &'a Doc<A>),
+    /// The first document, or the second when laid out flat. See [`Doc::flat_alt`].
+    FlatAlt(&'a Doc<A>, &'a Doc<A>),
+    /// A document built during layout from where it's laid out, e.g., by [`Doc::column`].
+    Dynamic,
+    /// See [`Doc::memoize`].
+    Memo(&'a Doc<A>),
+    /// A document laid out without line breaks. See [`Doc::no_break`].
+    Flat(&'a Doc<A>),
+    /// See [`Doc::expand`].
+    Expand(&'a Doc<A>),
+    /// A document whose lines after the first start with the given text. See
+    /// [`Doc::hang_with`].
+    Prefix(&'a str, &'a Doc<A>),
+    /// A document laid out as though the page were this many columns narrower.
+    Narrow(Width, &'a Doc<A>),
+    /// A document whose flat width is known. See [`Doc::group_break_hint`].
+    Hint(Width, &'a Doc<A>),
+    /// See [`Doc::break_priority`].
+    Priority(u8, &'a Doc<A>),
+    /// A document whose text is rewritten as it's laid out. See [`Doc::map_text`].
+    MapText(&'a Doc<A>),
+    /// A document of some importance, and its summary. See [`Doc::elidable`].
+    Elide(u8, &'a Doc<A>, &'a Doc<A>),
+    /// See [`Doc::annotate`].
+    Annotate(&'a A, &'a Doc<A>),
+}
+
+impl<A> Clone for DocNode<'_, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for DocNode<'_, A> {}
+
+impl<A: Annotation> Doc<A> {
+    /// The top node of this document.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::text("x").nest(2);
+    /// match doc.node() {
+    ///     DocNode::Nest(2, inner) => assert!(matches!(inner.node(), DocNode::Text("x"))),
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn node(&self) -> DocNode<'_, A> {
+        match &*self.0 {
+            DocInner::Empty | DocInner::EndAnnotation(_) => DocNode::Nil,
+            DocInner::Text(s) => DocNode::Text(s),
+            DocInner::Line => DocNode::Line,
+            DocInner::LineBreak => DocNode::LineBreak,
+            DocInner::Fail => DocNode::Fail,
+            DocInner::Concat(x, y) => DocNode::Concat(x, y),
+            DocInner::Nest(i, x) => DocNode::Nest(*i, x),
+            DocInner::Alt(x, y) => DocNode::Alt(x, y),
+            DocInner::FlatAlt(x, y) => DocNode::FlatAlt(x, y),
+            DocInner::Nesting(_)
+            | DocInner::Column(_)
+            | DocInner::Depth(_)
+            | DocInner::PageWidth(_)
+            | DocInner::Context(_) => DocNode::Dynamic,
+            DocInner::Memo(x, _) => DocNode::Memo(x),
+            DocInner::Flat(x) => DocNode::Flat(x),
+            DocInner::Expand(x) => DocNode::Expand(x),
+            DocInner::Prefix(s, x) => DocNode::Prefix(s, x),
+            DocInner::Narrow(n, x) => DocNode::Narrow(*n, x),
+            DocInner::Hint(w, x) => DocNode::Hint(*w, x),
+            DocInner::Priority(p, x) => DocNode::Priority(*p, x),
+            DocInner::MapText(_, x) => DocNode::MapText(x),
+            DocInner::Elide(n, x, y) => DocNode::Elide(*n, x, y),
+            DocInner::Annotate(a, x) => DocNode::Annotate(a, x),
+        }
+    }
+
+    /// The top node of this document with its children replaced by `children`, in the order
+    /// [`Doc::node`] lists them. Leaves are returned as they are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are fewer `children` than the node has.
+    pub fn with_children(&self, children: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        use DocInner as DI;
+
+        let mut children = children.into_iter();
+        let mut child = || {
+            children
+                .next()
+                .expect("a child for each of the node's children")
+        };
+        match &*self.0 {
+            DI::Concat(..) => DI::Concat(child(), child()).into_doc(),
+            DI::Alt(..) => DI::Alt(child(), child()).into_doc(),
+            DI::FlatAlt(..) => DI::FlatAlt(child(), child()).into_doc(),
+            DI::Elide(n, ..) => DI::Elide(*n, child(), child()).into_doc(),
+            DI::Nest(i, _) => DI::Nest(*i, child()).into_doc(),
+            DI::Memo(..) => child().memoize(),
+            DI::Flat(_) => DI::Flat(child()).into_doc(),
+            DI::Expand(_) => DI::Expand(child()).into_doc(),
+            DI::Prefix(s, _) => DI::Prefix(s.clone(), child()).into_doc(),
+            DI::Narrow(n, _) => DI::Narrow(*n, child()).into_doc(),
+            DI::Hint(w, _) => DI::Hint(*w, child()).into_doc(),
+            DI::Priority(p, _) => DI::Priority(*p, child()).into_doc(),
+            DI::MapText(f, _) => DI::MapText(f.clone(), child()).into_doc(),
+            DI::Annotate(a, _) => DI::Annotate(a.clone(), child()).into_doc(),
+            DI::Empty
+            | DI::Text(_)
+            | DI::Line
+            | DI::LineBreak
+            | DI::Fail
+            | DI::Nesting(_)
+            | DI::Column(_)
+            | DI::Depth(_)
+            | DI::PageWidth(_)
+            | DI::Context(_)
+            | DI::EndAnnotation(_) => self.clone(),
+        }
+    }
+
+    /// Fold the document bottom-up: `f` is called with each node and the results for its
+    /// children (in the order [`Doc::node`] lists them), and the result for the whole document
+    /// is returned. Each shared subtree is folded once, and deep documents don't recurse.
+    ///
+    /// Rebuilding nodes with [`Doc::with_children`] makes this a transformation, too.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::sep(["let", "x"].map(Doc::text));
+    /// let texts = doc.fold(|doc, children: Vec<usize>| {
+    ///     children.iter().sum::<usize>() + matches!(doc.node(), DocNode::Text(_)) as usize
+    /// });
+    /// assert_eq!(texts, 4); // Two in each layout.
+    ///
+    /// let shouted = doc.fold(|doc, children| match doc.node() {
+    ///     DocNode::Text(s) => Doc::text(s.to_uppercase()),
+    ///     _ => doc.with_children(children),
+    /// });
+    /// assert_eq!(shouted.render(80), "LET X");
+    /// ```
+    pub fn fold<T: Clone>(&self, mut f: impl FnMut(&Doc<A>, Vec<T>) -> T) -> T {
+        let mut folded: HashMap<*const DocInner<A>, T> = HashMap::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if folded.contains_key(&Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            let children = children(&doc);
+            if !children_done {
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let results = children
+                .iter()
+                .map(|child| folded[&Rc::as_ptr(&child.0)].clone())
+                .collect();
+            let result = f(&doc, results);
+            folded.insert(Rc::as_ptr(&doc.0), result);
+        }
+        folded
+            .remove(&Rc::as_ptr(&self.0))
+            .expect("the root is folded last")
+    }
+}
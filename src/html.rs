@@ -0,0 +1,142 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{Annotation, Color, Doc, Layout, Markup, RenderOptions, Style, Width};
+
+// -------------------------------------------------------------------------------------------------
+// HTML Classes
+// -------------------------------------------------------------------------------------------------
+
+/// An annotation that can be rendered as an HTML class, for [`Doc::render_html`].
+pub trait HtmlClass: Annotation {
+    /// The class (or space-separated classes) for annotated regions, or `None` to leave them
+    /// unwrapped.
+    fn html_class(&self) -> Option<String>;
+}
+
+impl HtmlClass for String {
+    fn html_class(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl HtmlClass for &'static str {
+    fn html_class(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+/// Styles become classes such as `bold`, `fg-red`, `bg-fixed-208`, or `fg-rgb-ff8800`, for a
+/// style sheet to define.
+impl HtmlClass for Style {
+    fn html_class(&self) -> Option<String> {
+        let color = |prefix: &str, color: Color| match color {
+            Color::Fixed(n) => format!("{}-fixed-{}", prefix, n),
+            Color::Rgb(r, g, b) => format!("{}-rgb-{:02x}{:02x}{:02x}", prefix, r, g, b),
+            named => format!("{}-{}", prefix, format!("{:?}", named).to_lowercase()),
+        };
+        let mut classes = vec![];
+        for (on, class) in [
+            (self.bold, "bold"),
+            (self.italic, "italic"),
+            (self.underline, "underline"),
+        ] {
+            if on {
+                classes.push(class.to_string());
+            }
+        }
+        classes.extend(self.fg.map(|c| color("fg", c)));
+        classes.extend(self.bg.map(|c| color("bg", c)));
+        (!classes.is_empty()).then(|| classes.join(" "))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// HTML Rendering
+// -------------------------------------------------------------------------------------------------
+
+fn escape(text: &str, output: &mut String) {
+    for ch in text.chars() {
+        match ch {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(ch),
+        }
+    }
+}
+
+/// Whether each open annotated region was wrapped in a `<span>`.
+#[derive(Default)]
+struct Html {
+    spans: Vec<bool>,
+}
+
+impl<A: HtmlClass> Markup<A> for Html {
+    fn text(&mut self, text: &str, output: &mut String) {
+        escape(text, output);
+    }
+
+    fn push(&mut self, annotation: &A, output: &mut String) {
+        let class = annotation.html_class();
+        if let Some(class) = &class {
+            output.push_str("<span class=\"");
+            escape(class, output);
+            output.push_str("\">");
+        }
+        self.spans.push(class.is_some());
+    }
+
+    fn pop(&mut self, output: &mut String) {
+        if self.spans.pop() == Some(true) {
+            output.push_str("</span>");
+        }
+    }
+}
+
+impl<A: HtmlClass> Doc<A> {
+    /// Render the document at the given `width` as HTML: text is escaped, and annotated regions
+    /// are wrapped in `<span class="...">` (see [`HtmlClass`]). Line breaks and indentation are
+    /// kept as-is, so the result belongs inside a `<pre>` element.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc = Doc::annotate("kw", Doc::<&str>::text("if")).concat(Doc::<&str>::text(" a < b"));
+    /// assert_eq!(doc.render_html(80), "<span class=\"kw\">if</span> a &lt; b");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_html(&self, width: Width) -> String {
+        self.render_html_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document as HTML using the given [`RenderOptions`]. See [`Doc::render_html`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_html_with(&self, options: &RenderOptions) -> String {
+        self.clone().layout_with(options).to_html()
+    }
+}
+
+impl<A: HtmlClass> Layout<A> {
+    /// Paint the layout as HTML. See [`Doc::render_html`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn to_html(&self) -> String {
+        let mut output = String::new();
+        if let Err(err) = self.paint(&mut output, &mut Html::default()) {
+            panic!("failed to render document: {}", err)
+        }
+        output
+    }
+}
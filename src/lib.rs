@@ -1,11 +1,69 @@
 // Copyright 2025 Cameron Swords
 // SPDX-License-Identifier: Apache-2.0
 
-use std::rc::Rc;
+use std::{
+    any::{Any, TypeId},
+    borrow::Cow,
+    cell::{Cell, OnceCell, RefCell},
+    collections::{BTreeSet, HashMap, VecDeque},
+    rc::Rc,
+};
 
-use once_cell::unsync::Lazy;
+pub use analysis::{DocStats, SharingReport, ValidationError};
+pub use arena::{ArenaDoc, DocArena};
+pub use delimiters::{DelimiterError, Delimiters};
+pub use fold::DocNode;
+pub use html::HtmlClass;
+pub use markdown::{Markdown, MarkdownStyle};
+pub use number::{NumberFormat, Radix};
+/// # Examples
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let cond = Doc::text("x");
+/// let body = Doc::text("y");
+/// let doc = doc_template!("{[}if {cond} {{{>}{~}{body}{<}{~}}}{]}");
+///
+/// assert_eq!(doc.render(80), "if x { y }");
+/// ```
+///
+/// Annotated holes make an annotated document:
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let keyword = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("fn"));
+/// let doc = doc_template!("{keyword} main() {{}}");
+///
+/// assert_eq!(
+///     doc.render_html(80),
+///     "<span class=\"keyword\">fn</span> main() {}"
+/// );
+/// ```
+pub use pretty_simple_macros::doc_template;
+pub use source_map::{OutputMap, OutputPosition, OutputSpan};
+pub use style::{Color, ColorChoice, Style};
+pub use sync::SyncDoc;
+pub use theme::{Semantic, Theme};
+
+// Lets `doc_template!` expansions name `::pretty_simple` from within this crate, too.
+extern crate self as pretty_simple;
 
+mod analysis;
+mod arena;
+mod delimiters;
+pub mod examples;
+mod fold;
+mod html;
+mod markdown;
+mod number;
+mod source_map;
+mod style;
+mod sync;
+#[doc(hidden)]
+pub mod template;
 mod tests;
+mod theme;
+mod transform;
 
 // -------------------------------------------------------------------------------------------------
 // Main Trait
@@ -14,11 +72,17 @@ mod tests;
 pub trait ToDoc {
     /// Convert the type into a `Doc`.
     fn to_doc(&self) -> Doc;
-    fn render(&self, width: i16) -> String {
+    fn render(&self, width: Width) -> String {
         self.to_doc().render(width)
     }
 }
 
+impl ToDoc for Doc {
+    fn to_doc(&self) -> Doc {
+        self.clone()
+    }
+}
+
 // -------------------------------------------------------------------------------------------------
 // Helpers
 // -------------------------------------------------------------------------------------------------
@@ -65,167 +129,710 @@ where
 // Documents
 // -------------------------------------------------------------------------------------------------
 
-pub struct Doc(Rc<DocInner>);
+/// Page widths, columns, and nesting levels, in the units layout measures text in (bytes).
+pub type Width = i32;
 
-type DocFn = Rc<dyn Fn(i16) -> Doc + 'static>;
+/// A document, optionally carrying annotations of type `A` (see [`Doc::annotate`]).
+pub struct Doc<A = ()>(Rc<DocInner<A>>);
 
-enum DocInner {
-    Empty,
-    Text(String),
-    Line, // soft line break
-    Concat(Doc, Doc),
-    Nest(i16, Doc),
-    Alt(Doc, Doc),
-    Nesting(DocFn),
-    Column(DocFn),
+type DocFn<A> = Rc<dyn Fn(Width) -> Doc<A> + 'static>;
+
+type ContextFn<A> = Rc<dyn Fn(&LayoutContext<'_>) -> Doc<A> + 'static>;
+
+type TextFn = Rc<dyn Fn(&str) -> String + 'static>;
+
+/// The text of a `Text` node: a string of its own, or a slice of a shared source (see
+/// [`Doc::text_slice`]). Compared and hashed as the text it holds.
+#[derive(Clone)]
+enum Str {
+    Cow(Cow<'static, str>),
+    Slice(Rc<str>, std::ops::Range<usize>),
 }
 
-// This is a bit of an absue of notation, but it will make our lives a touch simpler.
-impl DocInner {
-    fn into_doc(self) -> Doc {
-        Doc(Rc::new(self))
+impl Str {
+    // The text as a render event holds it: only a slice is copied.
+    fn to_cow(&self) -> Cow<'static, str> {
+        match self {
+            Str::Cow(s) => s.clone(),
+            Str::Slice(..) => Cow::Owned((**self).to_owned()),
+        }
     }
 }
 
-impl Clone for Doc {
-    fn clone(&self) -> Self {
-        Doc(Rc::clone(&self.0))
+impl std::ops::Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Str::Cow(s) => s,
+            Str::Slice(source, range) => &source[range.clone()],
+        }
     }
 }
-// -----------------------------------------------
-// Thread Locals
-// -----------------------------------------------
 
-thread_local! {
-    static NIL_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Empty));
-    static SPACE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text(" ".to_string())));
-    static COMMA_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text(",".to_string())));
-    static LINE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Line));
-    static SOFTLINE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Alt(Doc::space(), Doc::line())));
-    static SOFTLINE_EMPTY_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Alt(Doc::nil(), Doc::line())));
-    static LPAREN_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("(".to_string())));
-    static RPAREN_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text(")".to_string())));
-    static LANGLE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("<".to_string())));
-    static RANGLE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text(">".to_string())));
-    static LBRACKET_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("[".to_string())));
-    static RBRACKET_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("]".to_string())));
-    static LBRACE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("{".to_string())));
-    static RBRACE_INNER: Lazy<Rc<DocInner>> = Lazy::new(|| Rc::new(DocInner::Text("}".to_string())));
+impl std::fmt::Display for Str {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&**self, f)
+    }
 }
 
-impl Doc {
-    // -------------------------------------------
-    // Core Constructors
-    // -------------------------------------------
+impl std::fmt::Debug for Str {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&**self, f)
+    }
+}
 
-    /// The empty document.
-    ///
-    /// Renders to nothing and acts as the identity element for [`Doc::concat`].
-    pub fn nil() -> Doc {
-        NIL_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        **self == **other
     }
+}
 
-    /// A single ASCII space as a document (`" "`).
-    pub fn space() -> Doc {
-        SPACE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+impl Eq for Str {}
+
+impl std::hash::Hash for Str {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
     }
+}
 
-    /// A single ASCII comma as a document (`","`).
-    pub fn comma() -> Doc {
-        COMMA_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+enum DocInner<A> {
+    Empty,
+    Text(Str),
+    Line,      // soft line break
+    LineBreak, // a line break that is empty when flattened
+    Fail,      // never fits
+    Concat(Doc<A>, Doc<A>),
+    Nest(Width, Doc<A>),
+    Alt(Doc<A>, Doc<A>),
+    FlatAlt(Doc<A>, Doc<A>), // the first, or the second when laid out flat
+    Nesting(DocFn<A>),
+    Column(DocFn<A>),
+    Depth(DocFn<A>), // laid out one delimiter level deeper
+    PageWidth(DocFn<A>),
+    Context(ContextFn<A>), // built from where it's laid out
+    Memo(Doc<A>, MemoCache<A>),
+    Flat(Doc<A>),              // lay out without line breaks
+    Expand(Doc<A>),            // lay out broken, even if it would fit flat
+    Prefix(String, Doc<A>),    // start every line after the first with the given text
+    Narrow(Width, Doc<A>),     // lay out as though the page were narrower
+    Hint(Width, Doc<A>),       // the width of the document when laid out flat
+    Priority(u8, Doc<A>),      // breaks of lower priority are taken first
+    MapText(TextFn, Doc<A>),   // text is rewritten as it's laid out
+    Elide(u8, Doc<A>, Doc<A>), // a document of some importance, and a summary of it
+    Annotate(A, Doc<A>),
+    // Only on layout work lists, where an annotated document ends, with the columns its closing
+    // markup takes.
+    EndAnnotation(Width),
+}
+
+/// One step of laying out an expanded document broken: a choice becomes its last alternative, and
+/// other documents pass the expansion on to the documents in them, so choices nested in
+/// concatenations, annotations, and the like break too.
+fn expanded<A: Annotation>(doc: &Doc<A>) -> Doc<A> {
+    use DocInner as DI;
+
+    let expand = |doc: &Doc<A>| doc.clone().expand();
+    let inner = match &*doc.0 {
+        DI::Alt(_, broken) => {
+            let mut doc = broken;
+            while let DI::Alt(_, broken) = &*doc.0 {
+                doc = broken;
+            }
+            return doc.clone();
+        }
+        DI::Concat(x, y) => DI::Concat(expand(x), expand(y)),
+        DI::Nest(j, x) => DI::Nest(*j, expand(x)),
+        DI::FlatAlt(broken, flat) => DI::FlatAlt(expand(broken), flat.clone()),
+        DI::Nesting(f) => {
+            let f = f.clone();
+            DI::Nesting(Rc::new(move |i| f(i).expand()))
+        }
+        DI::Column(f) => {
+            let f = f.clone();
+            DI::Column(Rc::new(move |c| f(c).expand()))
+        }
+        DI::Depth(f) => {
+            let f = f.clone();
+            DI::Depth(Rc::new(move |d| f(d).expand()))
+        }
+        DI::PageWidth(f) => {
+            let f = f.clone();
+            DI::PageWidth(Rc::new(move |w| f(w).expand()))
+        }
+        DI::Context(f) => {
+            let f = f.clone();
+            DI::Context(Rc::new(move |context: &LayoutContext<'_>| {
+                f(context).expand()
+            }))
+        }
+        DI::Memo(x, _) => return expand(x),
+        DI::Prefix(text, x) => DI::Prefix(text.clone(), expand(x)),
+        DI::Narrow(columns, x) => DI::Narrow(*columns, expand(x)),
+        DI::Hint(width, x) => DI::Hint(*width, expand(x)),
+        DI::Priority(priority, x) => DI::Priority(*priority, expand(x)),
+        DI::MapText(f, x) => DI::MapText(f.clone(), expand(x)),
+        DI::Elide(importance, x, summary) => DI::Elide(*importance, expand(x), expand(summary)),
+        DI::Annotate(annotation, x) => DI::Annotate(annotation.clone(), expand(x)),
+        // Flat documents stay flat, and the rest have no choices in them.
+        DI::Empty
+        | DI::Text(_)
+        | DI::Line
+        | DI::LineBreak
+        | DI::Fail
+        | DI::Flat(_)
+        | DI::Expand(_)
+        | DI::EndAnnotation(_) => return doc.clone(),
+    };
+    inner.into_doc()
+}
+
+/// The layouts of a memoized subtree, keyed by `(config, indent, column, flat, scope)`.
+type MemoCache<A> = RefCell<HashMap<(LayoutConfig, Width, Width, bool, Scope), MemoLayout<A>>>;
+
+/// A cached layout: its events (unbalanced, each with the line prefix that follows it), the
+/// column it ends at, and the importance of each elidable region it lays out in full.
+type MemoLayout<A> = (Rc<[Replayed<A>]>, Width, BTreeSet<u8>);
+
+/// An event of a cached layout, and the line prefix that follows it if it's a line break.
+type Replayed<A> = (RenderEvent<A>, Option<RenderEvent<A>>);
+
+// This is a bit of an absue of notation, but it will make our lives a touch simpler.
+impl<A> DocInner<A> {
+    fn into_doc(self) -> Doc<A> {
+        Doc(Rc::new(self))
     }
+}
 
-    /// A hard line break.
-    ///
-    /// When rendered, this always breaks the line and sets the cursor to the current
-    /// indentation level tracked by nesting/indentation combinators.
-    pub fn line() -> Doc {
-        LINE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+impl<A> Clone for Doc<A> {
+    fn clone(&self) -> Self {
+        Doc(Rc::clone(&self.0))
     }
+}
 
-    /// A soft line break that becomes a space if the layout fits the given width,
-    /// or a newline otherwise.
-    ///
-    /// This is equivalent to `Alt(space, line)` in Wadler/Leijen pretty‑printing.
-    pub fn softline() -> Doc {
-        SOFTLINE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+// Dropping the last reference to a deep document would otherwise recurse once per level, so we
+// detach children onto an explicit stack and drop them from there.
+impl<A> Drop for Doc<A> {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        take_children(self, &mut stack);
+        while let Some(mut doc) = stack.pop() {
+            take_children(&mut doc, &mut stack);
+        }
     }
+}
 
-    /// A soft line break that becomes empty if the layout fits, or a newline
-    /// otherwise.
-    ///
-    /// Useful for optional separators (e.g., trailing commas off).
-    pub fn softline_empty() -> Doc {
-        SOFTLINE_EMPTY_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+fn take_children<A>(doc: &mut Doc<A>, stack: &mut Vec<Doc<A>>) {
+    let Some(inner) = Rc::get_mut(&mut doc.0) else {
+        return;
+    };
+    match std::mem::replace(inner, DocInner::Empty) {
+        DocInner::Concat(x, y)
+        | DocInner::Alt(x, y)
+        | DocInner::FlatAlt(x, y)
+        | DocInner::Elide(_, x, y) => {
+            stack.push(x);
+            stack.push(y);
+        }
+        DocInner::Nest(_, x)
+        | DocInner::Memo(x, _)
+        | DocInner::Flat(x)
+        | DocInner::Expand(x)
+        | DocInner::Prefix(_, x)
+        | DocInner::Narrow(_, x)
+        | DocInner::Hint(_, x)
+        | DocInner::Priority(_, x)
+        | DocInner::MapText(_, x)
+        | DocInner::Annotate(_, x) => stack.push(x),
+        DocInner::Empty
+        | DocInner::Text(_)
+        | DocInner::Line
+        | DocInner::LineBreak
+        | DocInner::Fail
+        | DocInner::Nesting(_)
+        | DocInner::Column(_)
+        | DocInner::Depth(_)
+        | DocInner::PageWidth(_)
+        | DocInner::Context(_)
+        | DocInner::EndAnnotation(_) => (),
     }
+}
 
-    /// Construct a document from raw text.
-    ///
-    /// The string is inserted verbatim; it will not contain line breaks unless
-    /// they are present in the string itself (which generally should be avoided
-    /// in pretty‑printing docs).
-    pub fn text<S: Into<String>>(str: S) -> Doc {
-        DocInner::Text(str.into()).into_doc()
+/// The structure of the document, for diagnosing layouts: `Concat(Text("a"), Nest(4, Line))`.
+/// Documents built by closures show as `Column(<fn>)` and the like, and shared subtrees are shown
+/// wherever they occur. `{:#?}` puts each child on its own line.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let doc: Doc = Doc::text("f(").concat(Doc::softline_empty().nest(4));
+/// assert_eq!(
+///     format!("{:?}", doc),
+///     r#"Concat(Text("f("), Nest(4, Alt(Empty, Line)))"#
+/// );
+/// ```
+impl<A: std::fmt::Debug> std::fmt::Debug for Doc<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        enum Piece<'a, A> {
+            Doc(&'a Doc<A>, usize),
+            Str(String),
+        }
+
+        let pretty = f.alternate();
+        let indent = |depth: usize| "    ".repeat(depth);
+        // Written without recursion, so deep documents are fine.
+        let mut stack = vec![Piece::Doc(self, 0)];
+        while let Some(piece) = stack.pop() {
+            let (doc, depth) = match piece {
+                Piece::Str(s) => {
+                    f.write_str(&s)?;
+                    continue;
+                }
+                Piece::Doc(doc, depth) => (doc, depth),
+            };
+            let (name, fields, children): (&str, Vec<String>, Vec<&Doc<A>>) = match &*doc.0 {
+                DocInner::Empty => ("Empty", vec![], vec![]),
+                DocInner::Text(s) => ("Text", vec![format!("{:?}", s)], vec![]),
+                DocInner::Line => ("Line", vec![], vec![]),
+                DocInner::LineBreak => ("LineBreak", vec![], vec![]),
+                DocInner::Fail => ("Fail", vec![], vec![]),
+                DocInner::Concat(x, y) => ("Concat", vec![], vec![x, y]),
+                DocInner::Nest(i, x) => ("Nest", vec![i.to_string()], vec![x]),
+                DocInner::Alt(x, y) => ("Alt", vec![], vec![x, y]),
+                DocInner::FlatAlt(x, y) => ("FlatAlt", vec![], vec![x, y]),
+                DocInner::Nesting(_) => ("Nesting", vec!["<fn>".to_string()], vec![]),
+                DocInner::Column(_) => ("Column", vec!["<fn>".to_string()], vec![]),
+                DocInner::Depth(_) => ("Depth", vec!["<fn>".to_string()], vec![]),
+                DocInner::PageWidth(_) => ("PageWidth", vec!["<fn>".to_string()], vec![]),
+                DocInner::Context(_) => ("Context", vec!["<fn>".to_string()], vec![]),
+                DocInner::Memo(x, _) => ("Memo", vec![], vec![x]),
+                DocInner::Flat(x) => ("Flat", vec![], vec![x]),
+                DocInner::Expand(x) => ("Expand", vec![], vec![x]),
+                DocInner::Prefix(s, x) => ("Prefix", vec![format!("{:?}", s)], vec![x]),
+                DocInner::Narrow(n, x) => ("Narrow", vec![n.to_string()], vec![x]),
+                DocInner::Hint(w, x) => ("Hint", vec![w.to_string()], vec![x]),
+                DocInner::Priority(p, x) => ("Priority", vec![p.to_string()], vec![x]),
+                DocInner::MapText(_, x) => ("MapText", vec!["<fn>".to_string()], vec![x]),
+                DocInner::Elide(n, x, y) => ("Elide", vec![n.to_string()], vec![x, y]),
+                DocInner::Annotate(a, x) => ("Annotate", vec![format!("{:?}", a)], vec![x]),
+                DocInner::EndAnnotation(_) => ("EndAnnotation", vec![], vec![]),
+            };
+            f.write_str(name)?;
+            if children.is_empty() {
+                if !fields.is_empty() {
+                    write!(f, "({})", fields.join(", "))?;
+                }
+                continue;
+            }
+            let mut pieces = vec![];
+            if pretty {
+                pieces.push(Piece::Str("(\n".to_string()));
+                for field in fields {
+                    pieces.push(Piece::Str(format!("{}{},\n", indent(depth + 1), field)));
+                }
+                for child in children {
+                    pieces.push(Piece::Str(indent(depth + 1)));
+                    pieces.push(Piece::Doc(child, depth + 1));
+                    pieces.push(Piece::Str(",\n".to_string()));
+                }
+                pieces.push(Piece::Str(format!("{})", indent(depth))));
+            } else {
+                pieces.push(Piece::Str("(".to_string()));
+                for field in fields {
+                    pieces.push(Piece::Str(format!("{}, ", field)));
+                }
+                for (i, child) in children.into_iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Piece::Str(", ".to_string()));
+                    }
+                    pieces.push(Piece::Doc(child, depth + 1));
+                }
+                pieces.push(Piece::Str(")".to_string()));
+            }
+            stack.extend(pieces.into_iter().rev());
+        }
+        Ok(())
+    }
+}
+// -----------------------------------------------
+// Thread Locals
+// -----------------------------------------------
+
+/// The shared constant documents for one annotation type.
+struct Constants<A> {
+    nil: Rc<DocInner<A>>,
+    space: Rc<DocInner<A>>,
+    comma: Rc<DocInner<A>>,
+    line: Rc<DocInner<A>>,
+    softline: Rc<DocInner<A>>,
+    softline_empty: Rc<DocInner<A>>,
+    line_break: Rc<DocInner<A>>,
+    lparen: Rc<DocInner<A>>,
+    rparen: Rc<DocInner<A>>,
+    langle: Rc<DocInner<A>>,
+    rangle: Rc<DocInner<A>>,
+    lbracket: Rc<DocInner<A>>,
+    rbracket: Rc<DocInner<A>>,
+    lbrace: Rc<DocInner<A>>,
+    rbrace: Rc<DocInner<A>>,
+    dot: Rc<DocInner<A>>,
+    colon: Rc<DocInner<A>>,
+    semicolon: Rc<DocInner<A>>,
+    equals: Rc<DocInner<A>>,
+    arrow: Rc<DocInner<A>>,
+    fat_arrow: Rc<DocInner<A>>,
+}
+
+impl<A> Constants<A> {
+    fn new() -> Self {
+        let text = |s: &'static str| Rc::new(DocInner::Text(Str::Cow(Cow::Borrowed(s))));
+        let nil = Rc::new(DocInner::Empty);
+        let space = text(" ");
+        let line = Rc::new(DocInner::Line);
+        Constants {
+            softline: Rc::new(DocInner::Alt(Doc(space.clone()), Doc(line.clone()))),
+            softline_empty: Rc::new(DocInner::Alt(Doc(nil.clone()), Doc(line.clone()))),
+            line_break: Rc::new(DocInner::LineBreak),
+            nil,
+            space,
+            comma: text(","),
+            line,
+            lparen: text("("),
+            rparen: text(")"),
+            langle: text("<"),
+            rangle: text(">"),
+            lbracket: text("["),
+            rbracket: text("]"),
+            lbrace: text("{"),
+            rbrace: text("}"),
+            dot: text("."),
+            colon: text(":"),
+            semicolon: text(";"),
+            equals: text("="),
+            arrow: text("->"),
+            fat_arrow: text("=>"),
+        }
     }
+}
+
+thread_local! {
+    // One set of constants per annotation type, created on first use.
+    static CONSTANTS: RefCell<HashMap<TypeId, Box<dyn Any>>> = RefCell::new(HashMap::new());
+    // The renders begun so far (see `Layouter::render`).
+    static RENDERS: Cell<u64> = const { Cell::new(0) };
+}
+
+fn constant<A: 'static>(get: impl FnOnce(&Constants<A>) -> &Rc<DocInner<A>>) -> Doc<A> {
+    CONSTANTS.with(|all| {
+        let mut all = all.borrow_mut();
+        let constants = all
+            .entry(TypeId::of::<A>())
+            .or_insert_with(|| Box::new(Constants::<A>::new()))
+            .downcast_ref::<Constants<A>>()
+            .expect("constants are keyed by their annotation type");
+        Doc(Rc::clone(get(constants)))
+    })
+}
+
+impl<A: Annotation> Doc<A> {
+    // -------------------------------------------
+    // Core Constructors
+    // -------------------------------------------
 
     /// Concatenate two documents without inserting any separator.
-    pub fn concat(self, other: Doc) -> Doc {
+    pub fn concat(self, other: Doc<A>) -> Doc<A> {
         DocInner::Concat(self, other).into_doc()
     }
 
     /// Increase the nesting (indentation) level for all lines that follow a newline
     /// within the given document by `depth` columns.
-    pub fn nest(self, depth: i16) -> Doc {
+    ///
+    /// `depth` may be negative (see [`Doc::dedent`]). Nesting adds up as usual, and lines whose
+    /// nesting ends up below zero start at column 0. Before [`LayoutVersion::V2`], layout still
+    /// measures such lines from their nesting when deciding what fits.
+    pub fn nest(self, depth: Width) -> Doc<A> {
         DocInner::Nest(depth, self).into_doc()
     }
 
     // `<+>` from Haskell
     //
     // Concatenates the two documents with a space between them.
-    pub fn concat_space(self, other: Doc) -> Doc {
-        self.concat(Doc::space()).concat(other)
+    pub fn concat_space(self, other: Doc<A>) -> Doc<A> {
+        self.concat(Doc::<A>::space()).concat(other)
     }
 
     /// Creates an `alt` set, preferring the first one if it fits and devolving to the second if it
     /// does not.
-    pub fn alt(self, other: Doc) -> Doc {
+    pub fn alt(self, other: Doc<A>) -> Doc<A> {
         DocInner::Alt(self, other).into_doc()
     }
 
+    /// The first of `docs` that fits, or else the last: `a.alt(b.alt(c))` for `[a, b, c]`. As
+    /// with [`Doc::alt`], an alternative fits if its output up to the next line break does (see
+    /// [`RenderOptions::fit_policy`] for stricter tests). With no alternatives, this is
+    /// [`Doc::fail`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let sum = || -> [Doc; 3] {
+    ///     let below = |doc| Doc::text("total =").concat(Doc::line().concat(doc).nest(4));
+    ///     [
+    ///         Doc::text("total = a + b + c"),
+    ///         below(Doc::text("a + b + c")),
+    ///         below(Doc::vsep(["a", "+ b", "+ c"].map(Doc::text))),
+    ///     ]
+    /// };
+    /// assert_eq!(Doc::alternatives(sum()).render(80), "total = a + b + c");
+    /// assert_eq!(Doc::alternatives(sum()).render(14), "total =\n    a + b + c");
+    /// // Only the first line of an alternative has to fit, unless lookahead is smart.
+    /// assert_eq!(Doc::alternatives(sum()).render(10), "total =\n    a + b + c");
+    /// let smart = RenderOptions::new(10).smart_lookahead(true);
+    /// assert_eq!(
+    ///     Doc::alternatives(sum()).render_with(&smart),
+    ///     "total =\n    a\n    + b\n    + c"
+    /// );
+    /// ```
+    pub fn alternatives(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        let docs: Vec<Doc<A>> = docs.into_iter().collect();
+        let mut docs = docs.into_iter().rev();
+        match docs.next() {
+            Some(last) => docs.fold(last, |rest, doc| doc.alt(rest)),
+            None => Doc::<A>::fail(),
+        }
+    }
+
     /// Try to render `self` on a single line by first flattening all soft breaks;
     /// if that does not fit within the current width, fall back to the original
     /// (multi‑line) layout.
     ///
     /// This is the standard `group` combinator from pretty‑printing literature.
-    pub fn group(self) -> Doc {
+    pub fn group(self) -> Doc<A> {
         match &*self.0 {
             DocInner::Alt(_, _) => self,
             _ => DocInner::Alt(self.clone().flatten(), self).into_doc(),
         }
     }
 
-    fn flatten(self) -> Doc {
+    /// Like [`Doc::group`], for a document whose width when laid out flat is already known
+    /// (e.g., computed from the AST). Deciding whether the group fits then takes the hint instead
+    /// of measuring the document. The width counts the markup of the annotations within it, as
+    /// given by [`Annotation::extra_width_flat`].
+    ///
+    /// In debug builds, laying out the group flat panics if the hint is wrong.
+    pub fn group_break_hint(self, flat_width: Width) -> Doc<A> {
+        DocInner::Hint(flat_width, self).into_doc().group()
+    }
+
+    /// Tag the line breaks in `self` (typically a [`Doc::softline`] or a group) with a
+    /// priority. When a line must break, breaks of lower priority are taken before those of
+    /// higher priority: deciding whether to break here assumes that any later break on the line
+    /// with a lower priority will be taken, so it only breaks if the text up to that break
+    /// doesn't fit. Untagged breaks are decided as usual.
+    ///
+    /// Tagged breaks measure the first line from the current column, as in
+    /// [`LayoutVersion::V2`], whatever the layout version.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // Break between arguments (priority 1) before breaking inside one (priority 2).
+    /// let arg = |a: &str, b: &str| {
+    ///     <Doc>::text(a)
+    ///         .concat(Doc::softline().break_priority(2))
+    ///         .concat(Doc::text(format!("+ {}", b)))
+    /// };
+    /// let doc = arg("alpha", "beta")
+    ///     .concat(Doc::text(","))
+    ///     .concat(Doc::softline().break_priority(1))
+    ///     .concat(arg("gamma", "delta"));
+    /// assert_eq!(doc.render(20), "alpha + beta,\ngamma + delta");
+    /// ```
+    pub fn break_priority(self, priority: u8) -> Doc<A> {
+        DocInner::Priority(priority, self).into_doc()
+    }
+
+    /// The first of `candidates` whose every line fits (or else the last), committed to as a
+    /// whole, like prettier's `conditionalGroup`. Unlike [`Doc::alternatives`], which checks only
+    /// up to the next line break, a candidate whose later lines overflow is passed over; and
+    /// unlike nested groups, a candidate never breaks partway towards the next one.
+    ///
+    /// Each candidate is laid out as given, so put a one-line form first to try it first.
+    /// Candidates are measured with the render's [`RenderOptions`], and the last line of each
+    /// along with what follows it, up to the next line break.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let args = || ["first", "second"].map(<Doc>::text);
+    /// let call = |width| -> String {
+    ///     let flat = Doc::hsep(args());
+    ///     let hugged = Doc::vsep(args()).align();
+    ///     let spread = Doc::line().concat(Doc::vsep(args())).nest(4).concat(Doc::line());
+    ///     let candidates = [flat, hugged, spread].map(|args| Doc::text("f(").concat(args));
+    ///     Doc::text("let result = ")
+    ///         .concat(Doc::conditional_group(candidates))
+    ///         .concat(Doc::text(")"))
+    ///         .render(width)
+    /// };
+    /// assert_eq!(call(40), "let result = f(first second)");
+    /// assert_eq!(call(24), "let result = f(first\n               second)");
+    /// // Hugging would overflow on the second line.
+    /// assert_eq!(call(20), "let result = f(\n    first\n    second\n)");
+    /// ```
+    pub fn conditional_group(candidates: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        let candidates: Rc<[Doc<A>]> = candidates.into_iter().collect();
+        let rendered: RefCell<Option<RenderedCandidates<A>>> = RefCell::default();
+        Doc::with_context(move |context| {
+            let docs = {
+                let mut rendered = rendered.borrow_mut();
+                match &*rendered {
+                    Some(current) if current.render == context.render => current.docs.clone(),
+                    _ => {
+                        let current = RenderedCandidates::new(context.render, &candidates);
+                        let docs = current.docs.clone();
+                        *rendered = Some(current);
+                        docs
+                    }
+                }
+            };
+            let Some(last) = docs.len().checked_sub(1) else {
+                return Doc::<A>::fail();
+            };
+            // Not borrowed while measuring, as lookahead past a candidate may come back here.
+            let measured = |index: usize| {
+                let key = (
+                    context.config.clone(),
+                    context.indent,
+                    context.column,
+                    context.scope.clone(),
+                    index,
+                );
+                let cached = rendered
+                    .borrow()
+                    .as_ref()
+                    .and_then(|current| current.measured.get(&key).copied());
+                cached.unwrap_or_else(|| {
+                    let measured = context.measure(&docs[index]);
+                    if let Some(current) = &mut *rendered.borrow_mut() {
+                        if current.render == context.render {
+                            current.measured.insert(key, measured);
+                        }
+                    }
+                    measured
+                })
+            };
+            let index = (0..last).find(|&index| {
+                let (lines_fit, end) = measured(index);
+                lines_fit && (context.rest_fits)(end)
+            });
+            docs[index.unwrap_or(last)].clone()
+        })
+    }
+
+    /// Lay out `self` normally, but `flat` instead when laid out flat (e.g., as part of a group
+    /// that fits on one line). Unlike [`Doc::alt`], which of the two is used depends only on
+    /// the enclosing group, not on whether either fits.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // A separator that leads the next line when broken.
+    /// let separator = Doc::line().concat(Doc::text("| ")).flat_alt(Doc::text(" | "));
+    /// let doc: Doc = Doc::hcat([Doc::text("A"), separator, Doc::text("B")]).group();
+    /// assert_eq!(doc.clone().render(80), "A | B");
+    /// assert_eq!(doc.render(3), "A\n| B");
+    /// ```
+    pub fn flat_alt(self, flat: Doc<A>) -> Doc<A> {
+        DocInner::FlatAlt(self, flat).into_doc()
+    }
+
+    /// Lay out `self` broken, even where it would fit on one line: its outermost groups (and
+    /// other choices between layouts) take their last alternative, wherever they sit in
+    /// concatenations, nesting, or annotations, and enclosing groups break too, since they can't
+    /// be laid out flat around it. Choices inside those broken alternatives still choose for
+    /// themselves. For rules like "always put this struct's fields on separate lines".
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let fields = || ["x: 1", "y: 2"].map(Doc::text);
+    /// let call = |args: Doc| Doc::text("f(").concat(args).concat(Doc::text(")")).group();
+    /// assert_eq!(call(Doc::record(fields())).render(80), "f({ x: 1, y: 2 })");
+    /// assert_eq!(
+    ///     call(Doc::record(fields()).expand()).render(80),
+    ///     "f({\n    x: 1,\n    y: 2\n})"
+    /// );
+    /// ```
+    pub fn expand(self) -> Doc<A> {
+        DocInner::Expand(self).into_doc()
+    }
+
+    /// Keep `self` together on one line, even if it overflows: every line break in it becomes a
+    /// space (or nothing, for [`Doc::line_break`]), for atoms such as string literals or URLs
+    /// that must never be split. Breaks around `self` are still taken to make room for it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let parts = ["https:", "", "example.com", "docs"].map(<Doc>::text);
+    /// let url = Doc::intersperse(parts, Doc::text("/").concat(Doc::softline_empty()));
+    /// let doc = Doc::sep([Doc::text("see"), url.no_break()]);
+    /// assert_eq!(doc.render(16), "see\nhttps://example.com/docs");
+    /// ```
+    pub fn no_break(self) -> Doc<A> {
+        self.flatten()
+    }
+
+    /// Rewrite the text of `self` with `f` as it's laid out, e.g., to upper-case keywords or
+    /// substitute locale-specific digits, without rebuilding the document. The structure is kept,
+    /// and layout measures the rewritten text. Nested rewrites apply innermost first.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let keyword = |s: &str| <Doc>::text(s.to_string()).map_text(|s| s.to_uppercase());
+    /// let doc = Doc::hsep([keyword("select"), Doc::text("x"), keyword("from"), Doc::text("t")]);
+    /// assert_eq!(doc.render(80), "SELECT x FROM t");
+    /// ```
+    pub fn map_text(self, f: impl Fn(&str) -> String + 'static) -> Doc<A> {
+        DocInner::MapText(Rc::new(f), self).into_doc()
+    }
+
+    /// Mark `self` as a region of the given `importance` that may be replaced by `summary` when
+    /// rendering to a limited height (see [`Doc::render_elided`]). Less important regions are
+    /// elided first; other renderers always show `self`.
+    pub fn elidable(self, importance: u8, summary: Doc<A>) -> Doc<A> {
+        DocInner::Elide(importance, self, summary).into_doc()
+    }
+
+    /// Lay out `self` on a single line: every line break becomes a space (or nothing, for
+    /// [`Doc::line_break`]) and every `alt` takes its first alternative.
+    fn flatten(self) -> Doc<A> {
         match &*self.0 {
-            DocInner::Empty | DocInner::Text(_) => self,
-            DocInner::Line => Doc::space(),
-            DocInner::Concat(x, y) => {
-                DocInner::Concat(x.clone().flatten(), y.clone().flatten()).into_doc()
-            }
-            DocInner::Nest(_, inner) => inner.clone().flatten(),
-            DocInner::Alt(flat, _) => flat.clone().flatten(),
-            DocInner::Column(f) => {
-                let f = Rc::clone(f);
-                let f = Rc::new(move |i| f(i).flatten());
-                Doc(Rc::new(DocInner::Column(f)))
-            }
-            DocInner::Nesting(f) => {
-                let f = Rc::clone(f);
-                let f = Rc::new(move |i| f(i).flatten());
-                Doc(Rc::new(DocInner::Nesting(f)))
-            }
+            DocInner::Empty | DocInner::Text(_) | DocInner::Flat(_) => self,
+            _ => DocInner::Flat(self).into_doc(),
         }
     }
 
+    /// Cache the layout of this subtree, so that repeated occurrences of it (e.g., a shared
+    /// subtree used in many table cells) are only laid out once per indentation level, starting
+    /// column, render width, and set of enclosing regions (e.g., line prefixes).
+    ///
+    /// To make the cached layout reusable, a memoized subtree chooses between its alternatives
+    /// as though nothing follows it: lookahead stops at the end of the subtree.
+    pub fn memoize(self) -> Doc<A> {
+        DocInner::Memo(self, RefCell::new(HashMap::new())).into_doc()
+    }
+
     /// Create a document whose contents are computed from the **current output column**.
     ///
     /// The closure receives the current cursor column (0‑based) and returns the
@@ -233,14 +840,70 @@ impl Doc {
     /// callable via `Rc`, so capture owned data in it.
     ///
     /// See also [`Doc::nesting`].
-    pub fn column<F>(f: F) -> Doc
+    pub fn column<F>(f: F) -> Doc<A>
     where
-        F: Fn(i16) -> Doc + 'static,
+        F: Fn(Width) -> Doc<A> + 'static,
     {
-        let f: DocFn = Rc::new(f);
+        let f: DocFn<A> = Rc::new(f);
         DocInner::Column(f).into_doc()
     }
 
+    /// Create a document whose contents are computed from the **page width** it's rendered at,
+    /// e.g., for horizontal rules or right-aligned text.
+    ///
+    /// See also [`Doc::column`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let rule: Doc = Doc::page_width(|w| Doc::text("-".repeat(w as usize)));
+    /// assert_eq!(rule.render(10), "----------");
+    /// ```
+    pub fn page_width<F>(f: F) -> Doc<A>
+    where
+        F: Fn(Width) -> Doc<A> + 'static,
+    {
+        let f: DocFn<A> = Rc::new(f);
+        DocInner::PageWidth(f).into_doc()
+    }
+
+    // Like `Doc::column`, but given everything about where the document is laid out, so that it
+    // can lay documents out just as the render does.
+    fn with_context<F>(f: F) -> Doc<A>
+    where
+        F: Fn(&LayoutContext<'_>) -> Doc<A> + 'static,
+    {
+        let f: ContextFn<A> = Rc::new(f);
+        DocInner::Context(f).into_doc()
+    }
+
+    /// Lay out `doc`, then splice in `f` of the number of columns it took up (the column it
+    /// ends at, less the column it starts at). Useful for lining things up after text of varying
+    /// width.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let underlined = |title: &str| -> Doc {
+    ///     Doc::width(Doc::text(title.to_string()), |w| {
+    ///         Doc::line().concat(Doc::text("=".repeat(w as usize)))
+    ///     })
+    /// };
+    /// assert_eq!(underlined("Usage").render(80), "Usage\n=====");
+    /// ```
+    pub fn width<F>(doc: Doc<A>, f: F) -> Doc<A>
+    where
+        F: Fn(Width) -> Doc<A> + 'static,
+    {
+        let f = Rc::new(f);
+        Doc::column(move |start| {
+            let f = f.clone();
+            doc.clone().concat(Doc::column(move |end| f(end - start)))
+        })
+    }
+
     /// Create a document whose contents are computed from the **current nesting level**.
     ///
     /// The closure receives the current indentation level (the `i` tracked by the
@@ -248,14 +911,170 @@ impl Doc {
     /// relative to the current indent.
     ///
     /// See also [`Doc::column`].
-    pub fn nesting<F>(f: F) -> Doc
+    pub fn nesting<F>(f: F) -> Doc<A>
     where
-        F: Fn(i16) -> Doc + 'static,
+        F: Fn(Width) -> Doc<A> + 'static,
     {
-        let f: DocFn = Rc::new(f);
+        let f: DocFn<A> = Rc::new(f);
         DocInner::Nesting(f).into_doc()
     }
 
+    /// A document built by `f` only once layout reaches it (if ever), e.g., for the branches of a
+    /// huge tree that are only shown by an alternative that rarely fits. `f` is called at most
+    /// once, and what it builds is kept for later use.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    /// use std::{cell::Cell, rc::Rc};
+    ///
+    /// let built = Rc::new(Cell::new(false));
+    /// let details = {
+    ///     let built = built.clone();
+    ///     Doc::lazy(move || {
+    ///         built.set(true);
+    ///         Doc::text("(very large)")
+    ///     })
+    /// };
+    /// let doc: Doc = Doc::text("summary").alt(details);
+    /// assert_eq!(doc.render(80), "summary");
+    /// assert!(!built.get());
+    /// ```
+    pub fn lazy<F>(f: F) -> Doc<A>
+    where
+        F: Fn() -> Doc<A> + 'static,
+    {
+        let built = OnceCell::new();
+        Doc::nesting(move |_| built.get_or_init(&f).clone())
+    }
+
+    /// A document for recursive or graph-shaped data, which may contain cycles: `f` lays out a
+    /// `value`, calling the function it's given for each value it refers to. Values more than
+    /// `max_depth` references from `value` are shown as `truncated` instead, so cycles can't
+    /// make layout loop forever. Each referenced value is laid out lazily (see [`Doc::lazy`]).
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // A list whose last node points back to the first.
+    /// let next = [1, 2, 0];
+    /// let doc: Doc = Doc::recursive(0, 3, Doc::text("..."), move |node: usize, recurse| {
+    ///     Doc::text(node.to_string())
+    ///         .concat(Doc::text(" -> "))
+    ///         .concat(recurse(next[node]))
+    /// });
+    /// assert_eq!(doc.render(80), "0 -> 1 -> 2 -> 0 -> ...");
+    /// ```
+    pub fn recursive<T, F>(value: T, max_depth: usize, truncated: Doc<A>, f: F) -> Doc<A>
+    where
+        T: Clone + 'static,
+        F: Fn(T, &dyn Fn(T) -> Doc<A>) -> Doc<A> + 'static,
+    {
+        Doc::unfold(&Rc::new(f), value, max_depth, truncated)
+    }
+
+    // Lay out `value` as `Doc::recursive` does, showing values up to `depth` references away.
+    fn unfold<T, F>(f: &Rc<F>, value: T, depth: usize, truncated: Doc<A>) -> Doc<A>
+    where
+        T: Clone + 'static,
+        F: Fn(T, &dyn Fn(T) -> Doc<A>) -> Doc<A> + 'static,
+    {
+        let recurse = |child: T| {
+            if depth == 0 {
+                return truncated.clone();
+            }
+            let (f, truncated) = (f.clone(), truncated.clone());
+            Doc::lazy(move || Doc::unfold(&f, child.clone(), depth - 1, truncated.clone()))
+        };
+        f(value, &recurse)
+    }
+
+    /// Create a document whose contents are computed from the **current delimiter depth**: the
+    /// number of enclosing documents made with `at_depth`. What the closure returns is itself one
+    /// level deeper.
+    ///
+    /// See [`Doc::rainbow`], which uses this to tell nested delimiters apart.
+    pub fn at_depth<F>(f: F) -> Doc<A>
+    where
+        F: Fn(usize) -> Doc<A> + 'static,
+    {
+        let f: DocFn<A> = Rc::new(move |depth| f(depth as usize));
+        DocInner::Depth(f).into_doc()
+    }
+
+    /// `open`, `body`, and `close`, with the delimiters annotated with `annotate` of their
+    /// depth (see [`Doc::at_depth`]), so that back ends can color matching pairs alike and
+    /// nested pairs differently ("rainbow parentheses").
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let palette = [Color::Yellow, Color::Magenta, Color::Cyan];
+    /// let parens = move |body| {
+    ///     Doc::rainbow(Doc::<Style>::lparen(), body, Doc::<Style>::rparen(), move |depth| {
+    ///         Style::new().fg(palette[depth % palette.len()])
+    ///     })
+    /// };
+    /// let doc = parens(Doc::<Style>::text("f ").concat(parens(Doc::<Style>::text("x"))));
+    /// assert_eq!(
+    ///     doc.render_ansi(80),
+    ///     "\x1b[33m(\x1b[0mf \x1b[35m(\x1b[0mx\x1b[35m)\x1b[0m\x1b[33m)\x1b[0m"
+    /// );
+    /// ```
+    pub fn rainbow<F>(open: Doc<A>, body: Doc<A>, close: Doc<A>, annotate: F) -> Doc<A>
+    where
+        F: Fn(usize) -> A + 'static,
+    {
+        Doc::at_depth(move |depth| {
+            let annotation = annotate(depth);
+            Doc::annotate(annotation.clone(), open.clone())
+                .concat(body.clone())
+                .concat(Doc::annotate(annotation, close.clone()))
+        })
+    }
+
+    /// Annotate `doc` with `annotation`, e.g., a color, a source span, or a semantic tag.
+    ///
+    /// Annotations don't affect layout, and are carried through [`Doc::group`] and friends.
+    /// Rendering to a string ignores them, while [`Doc::layout`] reports each annotated region
+    /// with [`RenderEvent::PushAnnotation`] and [`RenderEvent::PopAnnotation`] events so other
+    /// back ends can style it.
+    ///
+    /// Regions never span a line break in those events: the regions open at a break are closed
+    /// before it and reopened once the next line's indentation and prefixes (see
+    /// [`Doc::quoted_block`]) have been written.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// #[derive(Clone, Debug, PartialEq)]
+    /// enum Style {
+    ///     Keyword,
+    /// }
+    ///
+    /// impl Annotation for Style {}
+    ///
+    /// let doc = Doc::annotate(Style::Keyword, Doc::<Style>::text("fn"))
+    ///     .concat_space(Doc::<Style>::text("main"));
+    /// let events: Vec<RenderEvent<Style>> = doc.layout(80).collect();
+    /// assert_eq!(
+    ///     events,
+    ///     vec![
+    ///         RenderEvent::PushAnnotation(Style::Keyword),
+    ///         RenderEvent::Text("fn".into()),
+    ///         RenderEvent::PopAnnotation,
+    ///         RenderEvent::Text(" ".into()),
+    ///         RenderEvent::Text("main".into()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn annotate(annotation: A, doc: Doc<A>) -> Doc<A> {
+        DocInner::Annotate(annotation, doc).into_doc()
+    }
+
     // -------------------------------------------
     // Helpers
     // -------------------------------------------
@@ -264,10 +1083,11 @@ impl Doc {
     /// `concat_f`.
     ///
     /// This is a generalized form of [`hcat`](Self::hcat), [`hsep`](Self::hsep),
-    /// and [`vsep`](Self::vsep). Returns [`Doc::nil()`] for an empty iterator.
-    pub fn concat_with<F>(docs: impl IntoIterator<Item = Doc>, concat_f: F) -> Doc
+    /// and [`vsep`](Self::vsep). Returns [`Doc::nil()`] for an empty iterator. Documents are
+    /// combined from the left, as in `concat_f(concat_f(a, b), c)`.
+    pub fn concat_with<F>(docs: impl IntoIterator<Item = Doc<A>>, concat_f: F) -> Doc<A>
     where
-        F: Fn(Doc, Doc) -> Doc,
+        F: Fn(Doc<A>, Doc<A>) -> Doc<A>,
     {
         let mut iter = docs.into_iter();
         if let Some(first) = iter.next() {
@@ -277,23 +1097,95 @@ impl Doc {
             }
             output
         } else {
-            Doc::nil()
+            Doc::<A>::nil()
         }
     }
 
-    /// A convenience for “hanging” indentation: `self.nest(i).align()`.
-    ///
-    /// Subsequent lines align under the first character after an `i`‑space indent.
-    pub fn hang(self, i: i16) -> Doc {
-        self.nest(i).align()
+    // Fold `docs` with an associative `concat_f` by combining pairs in rounds, so the result is
+    // a balanced tree whose depth grows with the logarithm of the number of documents.
+    fn concat_balanced<F>(docs: impl IntoIterator<Item = Doc<A>>, concat_f: F) -> Doc<A>
+    where
+        F: Fn(Doc<A>, Doc<A>) -> Doc<A>,
+    {
+        let mut docs: Vec<Doc<A>> = docs.into_iter().collect();
+        while docs.len() > 1 {
+            let mut pairs = docs.into_iter();
+            let mut combined = Vec::with_capacity(pairs.len().div_ceil(2));
+            while let Some(x) = pairs.next() {
+                combined.push(match pairs.next() {
+                    Some(y) => concat_f(x, y),
+                    None => x,
+                });
+            }
+            docs = combined;
+        }
+        docs.pop().unwrap_or_else(Doc::<A>::nil)
     }
 
-    /// Indent `self` by `i` spaces, and use a hanging layout so subsequent lines
-    /// align under the first non‑space character.
+    /// Decrease the nesting level for the lines of `self` by `columns`: `self.nest(-columns)`.
+    /// Lines can't be dedented past column 0.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // Labels sit two columns left of the statements around them.
+    /// let label: Doc = Doc::line().concat(Doc::text("done:")).dedent(2);
+    /// let body = Doc::text("x = 1;").concat(label).concat(Doc::line()).concat(Doc::text("y;"));
+    /// let doc = Doc::text("{").concat(Doc::line().concat(body).nest(4));
+    /// assert_eq!(doc.render(80), "{\n    x = 1;\n  done:\n    y;");
+    /// let past_margin: Doc = Doc::text("a").concat(Doc::line()).dedent(8);
+    /// assert_eq!(past_margin.concat(Doc::text("b")).render(80), "a\nb");
+    /// ```
+    pub fn dedent(self, columns: Width) -> Doc<A> {
+        self.nest(columns.saturating_neg())
+    }
+
+    /// A convenience for “hanging” indentation: `self.nest(i).align()`.
+    ///
+    /// Subsequent lines align under the first character after an `i`‑space indent.
+    pub fn hang(self, i: Width) -> Doc<A> {
+        self.nest(i).align()
+    }
+
+    /// Indent `self` by `i` spaces, and use a hanging layout so subsequent lines
+    /// align under the first non‑space character.
     ///
     /// Equivalent to `Doc::spaces(i).concat(self).hang(i)`.
-    pub fn indent(self, i: i16) -> Doc {
-        Doc::spaces(i).concat(self).hang(i)
+    pub fn indent(self, i: Width) -> Doc<A> {
+        Doc::<A>::spaces(i).concat(self).hang(i)
+    }
+
+    /// Like [`Doc::hang`], but start each subsequent line with `fill` rather than spaces, at the
+    /// column `self` starts at. `self`'s own nesting is relative to the end of `fill`, so fills
+    /// nest, as in tree-style output:
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let node = |label: &str, children: Vec<Doc>| -> Doc {
+    ///     let count = children.len();
+    ///     let children = children.into_iter().enumerate().map(|(i, child)| {
+    ///         let last = i + 1 == count;
+    ///         let (connector, fill) = if last { ("`-- ", "    ") } else { ("|-- ", "|   ") };
+    ///         Doc::text(connector).concat(child).hang_with(fill)
+    ///     });
+    ///     Doc::vsep(std::iter::once(Doc::text(label)).chain(children))
+    /// };
+    /// let bin = node("bin", vec![node("main.rs", vec![])]);
+    /// let tree = node("src", vec![bin, node("lib.rs", vec![])]);
+    /// assert_eq!(tree.render(80), "src\n|-- bin\n|   `-- main.rs\n`-- lib.rs");
+    /// ```
+    pub fn hang_with<S: Into<String>>(self, fill: S) -> Doc<A> {
+        DocInner::Prefix(fill.into(), self).into_doc().align()
+    }
+
+    /// Like [`Doc::indent`], but indent every line of `self` with `fill` rather than spaces (e.g.,
+    /// `"// "` or `"|   "`). Equivalent to `Doc::text(fill).concat(self).hang_with(fill)`.
+    pub fn indent_with<S: Into<String>>(self, fill: S) -> Doc<A> {
+        let fill = fill.into();
+        Doc::<A>::text(fill.clone()).concat(self).hang_with(fill)
     }
 
     /// Align subsequent lines to the current column.
@@ -304,7 +1196,7 @@ impl Doc {
     ///      wraps across lines
     /// ```
     /// Internally implemented via [`Doc::column`] and [`Doc::nesting`].
-    pub fn align(self) -> Doc {
+    pub fn align(self) -> Doc<A> {
         // Move an owned clone into the closures so they’re 'static.
         Doc::column({
             let base = self.clone();
@@ -315,180 +1207,671 @@ impl Doc {
         })
     }
 
-    /// Produce `i` spaces as a document (`" ".repeat(i)`), with fast paths for 0 and 1.
-    pub fn spaces(i: i16) -> Doc {
-        match i {
-            0 => Doc::nil(),
-            1 => Doc::space(),
-            n => Doc::text(" ".repeat(n as usize)),
-        }
+    /// Pad `self` with trailing spaces so it ends at least `width` columns after the column it
+    /// starts at, e.g., for fixed-width fields in log lines. Wider documents are left as-is. The
+    /// padding is worked out during layout, so it counts when deciding whether groups fit.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let level = |s: &str| -> Doc { Doc::text(s.to_string()).with_min_width(6) };
+    /// let line = |lvl: &str, msg: &str| level(lvl).concat(Doc::text(msg.to_string()));
+    /// let log = Doc::vsep([line("INFO", "started"), line("WARN", "slow"), line("ERROR!!", "oops")]);
+    /// assert_eq!(log.render(80), "INFO  started\nWARN  slow\nERROR!!oops");
+    /// ```
+    pub fn with_min_width(self, width: Width) -> Doc<A> {
+        Doc::width(self, move |w| Doc::<A>::spaces((width - w).max(0)))
+    }
+
+    /// Lay out `self` on one line, padded with spaces on the right to at least `width` columns:
+    /// a left-aligned field of fixed-width tabular output.
+    pub fn pad_right(self, width: Width) -> Doc<A> {
+        self.flatten().with_min_width(width)
+    }
+
+    /// Lay out `self` on one line, padded with spaces on the left to at least `width` columns:
+    /// a right-aligned field of fixed-width tabular output, such as line numbers.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let row = |n: usize, bytes: &str| -> Doc {
+    ///     Doc::text(n.to_string())
+    ///         .pad_left(4)
+    ///         .concat(Doc::text(" | "))
+    ///         .concat(Doc::text(bytes.to_string()).pad_right(6))
+    ///         .concat(Doc::text("|"))
+    /// };
+    /// let dump = Doc::vsep([row(0, "cafe"), row(16, "f00d01")]);
+    /// assert_eq!(dump.render(80), "   0 | cafe  |\n  16 | f00d01|");
+    /// ```
+    pub fn pad_left(self, width: Width) -> Doc<A> {
+        let doc = self.flatten();
+        Doc::with_context(move |context| {
+            let used = context.flat_width(&doc);
+            Doc::<A>::spaces((width - used).max(0)).concat(doc.clone())
+        })
+    }
+
+    /// Lay out `self` on one line, flush against the right margin of the page, after at least
+    /// one space: e.g., for type annotations or byte counts at the ends of listing lines.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let entry = |name: &str, size: usize| -> Doc {
+    ///     Doc::text(name.to_string()).concat(Doc::text(format!("{size} B")).flush_right())
+    /// };
+    /// let listing = Doc::vsep([entry("Cargo.toml", 512), entry("src/lib.rs", 20480)]);
+    /// assert_eq!(listing.render(22), "Cargo.toml       512 B\nsrc/lib.rs     20480 B");
+    /// ```
+    pub fn flush_right(self) -> Doc<A> {
+        let doc = self.flatten();
+        Doc::page_width(move |page| {
+            let doc = doc.clone();
+            Doc::column(move |column| {
+                Doc::<A>::space().concat(doc.clone().pad_left(page - column - 1))
+            })
+        })
+    }
+
+    /// Like [`Doc::with_min_width`], but if `self` is already wider than `width`, break the line
+    /// after it instead, and carry on `width` columns past the current nesting. This lines up
+    /// the second column of declarations even when some of the first are too long.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let decl = |name: &str, ty: &str| -> Doc {
+    ///     Doc::text(name.to_string()).fill_break(6).concat(Doc::text(format!(":: {ty}")))
+    /// };
+    /// let decls = Doc::vsep([
+    ///     decl("empty", "Doc"),
+    ///     decl("nest", "Int -> Doc"),
+    ///     decl("linebreak", "Doc"),
+    /// ]);
+    /// assert_eq!(
+    ///     decls.render(80),
+    ///     "empty :: Doc\nnest  :: Int -> Doc\nlinebreak\n      :: Doc"
+    /// );
+    /// ```
+    pub fn fill_break(self, width: Width) -> Doc<A> {
+        Doc::width(self, move |w| match width - w {
+            padding if padding < 0 => Doc::<A>::line().nest(width),
+            padding => Doc::<A>::spaces(padding),
+        })
     }
 
     /// Horizontally separate an iterator of documents with single spaces.
     ///
     /// Equivalent to interspersing [`Doc::space()`] and concatenating.
-    pub fn hsep(docs: impl IntoIterator<Item = Doc>) -> Doc {
-        Doc::concat_with(docs, |x, y| x.concat_space(y))
+    pub fn hsep(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::concat_balanced(docs, |x, y| x.concat_space(y))
     }
 
     /// Vertically separate an iterator of documents with hard newlines.
     ///
     /// Equivalent to interspersing [`Doc::line()`] and concatenating.
-    pub fn vsep(docs: impl IntoIterator<Item = Doc>) -> Doc {
-        Doc::concat_with(docs, |x, y| x.concat(Doc::line()).concat(y))
+    pub fn vsep(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::concat_balanced(docs, |x, y| x.concat(Doc::<A>::line()).concat(y))
+    }
+
+    /// Separate documents with exactly one blank line, leaving out those that render to nothing
+    /// (such as [`Doc::nil`]), so that optional items don't leave extra blank lines behind. This
+    /// is the top-level shape of most generated files.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let imports: Option<Doc> = None;
+    /// let doc = Doc::paragraphs([
+    ///     Doc::text("// generated"),
+    ///     imports.unwrap_or_else(Doc::nil),
+    ///     Doc::text("fn main() {}"),
+    /// ]);
+    /// assert_eq!(doc.render(80), "// generated\n\nfn main() {}");
+    /// ```
+    pub fn paragraphs(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        let docs = docs.into_iter().filter(|doc| !doc.is_nil());
+        Doc::concat_balanced(docs, |x, y| {
+            x.concat(Doc::<A>::line())
+                .concat(Doc::<A>::line())
+                .concat(y)
+        })
+    }
+
+    // Whether the document renders to nothing however it's laid out.
+    fn is_nil(&self) -> bool {
+        let mut stack = vec![self];
+        while let Some(doc) = stack.pop() {
+            match &*doc.0 {
+                DocInner::Empty => (),
+                DocInner::Text(s) if s.is_empty() => (),
+                DocInner::Concat(x, y) | DocInner::FlatAlt(x, y) => stack.extend([x, y]),
+                DocInner::Nest(_, x)
+                | DocInner::Memo(x, _)
+                | DocInner::Flat(x)
+                | DocInner::Expand(x)
+                | DocInner::Narrow(_, x)
+                | DocInner::Hint(_, x)
+                | DocInner::Priority(_, x) => stack.push(x),
+                _ => return false,
+            }
+        }
+        true
     }
 
     // Tries laying the elements out with spaces, or vertically if they do not fit.
-    pub fn sep(docs: impl IntoIterator<Item = Doc>) -> Doc {
+    pub fn sep(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
         Doc::vsep(docs).group()
     }
 
-    /// Concatenate an iterator of documents without separators (left‑associative).
-    pub fn hcat(docs: impl IntoIterator<Item = Doc>) -> Doc {
-        Doc::concat_with(docs, |x, y| x.concat(y))
+    /// Concatenate an iterator of documents without separators.
+    pub fn hcat(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::concat_balanced(docs, |x, y| x.concat(y))
     }
 
-    /// Concatenate `docs`, inserting `separator` between each adjacent pair.
+    /// `n` copies of `self`, concatenated: for rules, padding, and generated fixtures.
     ///
-    /// Returns [`Doc::nil()`] if `docs` is empty.
-    pub fn intersperse(docs: impl IntoIterator<Item = Doc>, separator: Doc) -> Doc {
-        let mut iter = docs.into_iter();
-        if let Some(first) = iter.next() {
-            let mut output = first;
-            for next in iter {
-                output = output.concat(separator.clone()).concat(next);
+    /// The copies share a balanced tree of `O(log n)` nodes, so even large counts are cheap to
+    /// build and don't make layout recurse deeply.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let rule: Doc = Doc::text("-").repeat(10);
+    /// assert_eq!(rule.render(80), "----------");
+    /// ```
+    pub fn repeat(self, n: usize) -> Doc<A> {
+        let mut result: Option<Doc<A>> = None;
+        let (mut power, mut n) = (self, n);
+        while n > 0 {
+            if n & 1 == 1 {
+                result = Some(match result {
+                    Some(result) => result.concat(power.clone()),
+                    None => power.clone(),
+                });
             }
-            output
-        } else {
-            Doc::nil()
+            n >>= 1;
+            if n > 0 {
+                power = power.clone().concat(power);
+            }
+        }
+        result.unwrap_or_else(Doc::<A>::nil)
+    }
+
+    /// Vertically separate documents with [`Doc::line_break`]s, which, unlike the breaks of
+    /// [`Doc::vsep`], leave nothing between the documents when flattened.
+    pub fn vcat(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::concat_balanced(docs, |x, y| x.concat(Doc::<A>::line_break()).concat(y))
+    }
+
+    /// Concatenate documents on one line if they fit, and otherwise one per line: the grouped
+    /// [`Doc::vcat`], as [`Doc::sep`] is the grouped [`Doc::vsep`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let chain = || ["items", ".iter()", ".map(f)", ".collect()"].map(Doc::text);
+    /// assert_eq!(<Doc>::cat(chain()).render(80), "items.iter().map(f).collect()");
+    /// assert_eq!(
+    ///     <Doc>::cat(chain()).render(20),
+    ///     "items\n.iter()\n.map(f)\n.collect()"
+    /// );
+    /// ```
+    pub fn cat(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::vcat(docs).group()
+    }
+
+    /// Like [`Doc::sep`], but separating the documents with `flat` when they fit on one line,
+    /// and with `broken` (which should include its line break) otherwise. This makes styles such
+    /// as leading commas direct to write.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let fields = || ["alpha", "beta", "gamma"].map(<Doc>::text);
+    /// let comma_first = |width| -> String {
+    ///     let broken = Doc::line().concat(Doc::text(", "));
+    ///     Doc::sep_with(fields(), Doc::text(", "), broken).render(width)
+    /// };
+    /// assert_eq!(comma_first(80), "alpha, beta, gamma");
+    /// assert_eq!(comma_first(10), "alpha\n, beta\n, gamma");
+    /// ```
+    pub fn sep_with(
+        docs: impl IntoIterator<Item = Doc<A>>,
+        flat: Doc<A>,
+        broken: Doc<A>,
+    ) -> Doc<A> {
+        Doc::intersperse(docs, broken.flat_alt(flat)).group()
+    }
+
+    /// Concatenate `docs`, inserting `separator` between each adjacent pair.
+    ///
+    /// Returns [`Doc::nil()`] if `docs` is empty. The result is a balanced tree, so long lists
+    /// don't nest deeply.
+    pub fn intersperse(docs: impl IntoIterator<Item = Doc<A>>, separator: Doc<A>) -> Doc<A> {
+        Doc::concat_balanced(docs, |x, y| x.concat(separator.clone()).concat(y))
+    }
+
+    /// Append `separator` to every document but the last, leaving the choice of how to join
+    /// them (e.g., [`Doc::sep`], [`Doc::fill`], or [`Doc::vsep`]) to the caller.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let items: Vec<Doc> = Doc::punctuate(Doc::comma(), ["a", "b", "c"].map(Doc::text));
+    /// assert_eq!(Doc::fill(&items).render(80), "a, b, c");
+    /// assert_eq!(Doc::vsep(items).render(80), "a,\nb,\nc");
+    /// ```
+    pub fn punctuate(separator: Doc<A>, docs: impl IntoIterator<Item = Doc<A>>) -> Vec<Doc<A>> {
+        let mut docs: Vec<Doc<A>> = docs.into_iter().collect();
+        let last = docs.len().saturating_sub(1);
+        for doc in &mut docs[..last] {
+            *doc = doc.clone().concat(separator.clone());
         }
+        docs
     }
 
     /// Surround `self` with `(` and `)` (parentheses).
-    pub fn parens(self) -> Doc {
+    pub fn parens(self) -> Doc<A> {
         Self::lparen().concat(self).concat(Self::rparen())
     }
 
     /// Surround `self` with `<` and `>` (angle brackets).
-    pub fn angles(self) -> Doc {
+    pub fn angles(self) -> Doc<A> {
         Self::langle().concat(self).concat(Self::rangle())
     }
 
     /// Surround `self` with `[` and `]` (square brackets).
-    pub fn brackets(self) -> Doc {
+    pub fn brackets(self) -> Doc<A> {
         Self::lbracket().concat(self).concat(Self::rbracket())
     }
 
     /// Surround `self` with `{` and `}` (curly braces).
-    pub fn braces(self) -> Doc {
+    pub fn braces(self) -> Doc<A> {
         Self::lbrace().concat(self).concat(Self::rbrace())
     }
 
-    /// Render `self` as a typical block:
+    /// Surround `self` with `left` and `right`.
+    pub fn enclose(self, left: Doc<A>, right: Doc<A>) -> Doc<A> {
+        left.concat(self).concat(right)
+    }
+
+    /// Surround `self` with single quotes (`'`).
+    pub fn squotes(self) -> Doc<A> {
+        self.enclose(Doc::<A>::static_text("'"), Doc::<A>::static_text("'"))
+    }
+
+    /// Surround `self` with double quotes (`"`).
+    pub fn dquotes(self) -> Doc<A> {
+        self.enclose(Doc::<A>::static_text("\""), Doc::<A>::static_text("\""))
+    }
+
+    /// Surround `self` with backticks (`` ` ``).
+    pub fn backticks(self) -> Doc<A> {
+        self.enclose(Doc::<A>::static_text("`"), Doc::<A>::static_text("`"))
+    }
+
+    /// A comma-separated list in square brackets: `[a, b, c]` if it fits, and otherwise one
+    /// element per line, indented by 4.
     ///
-    /// ```text
-    /// {start}
-    ///     {self (indented, grouped)}
-    /// {end}
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let items = || ["alpha", "beta", "gamma"].map(Doc::text);
+    /// assert_eq!(<Doc>::list(items()).render(80), "[alpha, beta, gamma]");
+    /// assert_eq!(<Doc>::list(items()).render(10), "[\n    alpha,\n    beta,\n    gamma\n]");
+    /// assert_eq!(<Doc>::record(items()).render(80), "{ alpha, beta, gamma }");
     /// ```
+    pub fn list(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::comma_separated(
+            Doc::<A>::lbracket(),
+            docs,
+            Doc::<A>::rbracket(),
+            Doc::<A>::softline_empty(),
+        )
+    }
+
+    /// A [`Doc::list`] that is laid out on one line only if it has at most `max_flat` elements
+    /// (and fits), for style rules like "arrays of more than three elements are always
+    /// multi-line". Longer lists break, along with the groups around them (see [`Doc::expand`]).
     ///
-    /// Uses a 4‑space indent and inserts newlines before and after the block body.
-    pub fn block(self, start: Doc, end: Doc) -> Doc {
-        start
-            .concat(Doc::line())
-            .concat(self.indent(4).group())
-            .concat(Doc::line())
-            .concat(end)
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let items = |n| (1..=n).map(|i| Doc::text(i.to_string()));
+    /// assert_eq!(<Doc>::list_flat_at_most(items(3), 3).render(80), "[1, 2, 3]");
+    /// assert_eq!(
+    ///     <Doc>::list_flat_at_most(items(4), 3).render(80),
+    ///     "[\n    1,\n    2,\n    3,\n    4\n]"
+    /// );
+    /// ```
+    pub fn list_flat_at_most(docs: impl IntoIterator<Item = Doc<A>>, max_flat: usize) -> Doc<A> {
+        let docs: Vec<Doc<A>> = docs.into_iter().collect();
+        let too_long = docs.len() > max_flat;
+        let list = Doc::list(docs);
+        if too_long {
+            list.expand()
+        } else {
+            list
+        }
     }
 
-    /// Fill a la Wadler
-    /// This
-    pub fn fill(xs: &[Doc]) -> Doc {
-        Self::fill_core(xs, 0, false)
+    /// A comma-separated tuple in parentheses: `(a, b, c)` if it fits, and otherwise one element
+    /// per line, indented by 4. See [`Doc::list`].
+    pub fn tupled(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::comma_separated(
+            Doc::<A>::lparen(),
+            docs,
+            Doc::<A>::rparen(),
+            Doc::<A>::nil(),
+        )
     }
 
-    /// `head_flat` means: treat xs[i] as already flattened (because caller passed `flatten y : zs`)
-    fn fill_core(xs: &[Doc], i: usize, head_flat: bool) -> Doc {
-        if i >= xs.len() {
-            return Doc::nil();
-        }
-        let n = xs.len() - i;
-        if n == 1 {
-            let mut d = xs[i].clone();
-            if head_flat {
-                d = d.flatten();
-            }
-            return d;
-        }
+    /// Comma-separated fields in braces: `{ a, b, c }` if it fits, and otherwise one field per
+    /// line, indented by 4. See [`Doc::list`].
+    pub fn record(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::comma_separated(
+            Doc::<A>::lbrace(),
+            docs,
+            Doc::<A>::rbrace(),
+            Doc::<A>::space(),
+        )
+    }
 
-        // We have at least two: x = xs[i], y = xs[i+1]
-        let x = xs[i].clone();
-        let y_is_head = i + 1; // head of the recursive tail
+    /// `open`, then `docs` separated by `separator`, then `close`: `open a, b, c close` if it
+    /// fits, and otherwise one entry per line, with each separator where `placement` says.
+    /// Empty sequences print as just the delimiters.
+    ///
+    /// Trailing separators nest the entries by 4, as [`Doc::list`] does. Leading separators line
+    /// up under `open`, Haskell style, so that the entries stay aligned wherever the list starts.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let items = || ["alpha", "beta", "gamma"].map(Doc::text);
+    /// let list = |placement| -> Doc {
+    ///     Doc::enclose_sep(Doc::lbracket(), items(), Doc::comma(), Doc::rbracket(), placement)
+    /// };
+    /// assert_eq!(list(SeparatorPlacement::Leading).render(80), "[alpha, beta, gamma]");
+    /// assert_eq!(
+    ///     list(SeparatorPlacement::Trailing).render(10),
+    ///     "[\n    alpha,\n    beta,\n    gamma\n]"
+    /// );
+    /// assert_eq!(
+    ///     list(SeparatorPlacement::Leading).render(10),
+    ///     "[ alpha\n, beta\n, gamma\n]"
+    /// );
+    /// ```
+    pub fn enclose_sep(
+        open: Doc<A>,
+        docs: impl IntoIterator<Item = Doc<A>>,
+        separator: Doc<A>,
+        close: Doc<A>,
+        placement: SeparatorPlacement,
+    ) -> Doc<A> {
+        Doc::separated(open, docs, separator, close, Doc::<A>::nil(), placement)
+    }
 
-        // Left branch: (flatten x <+> fill (flatten y : zs))
-        // If the current head is already flattened, don't double-flatten.
-        let x_flat = if head_flat {
-            x.clone()
-        } else {
-            x.clone().flatten()
-        };
-        let left = x_flat
-            .concat(Doc::space())
-            // Next level's head (y) must be treated as already flattened
-            .concat(Self::fill_core(xs, y_is_head, true));
+    /// Comma-separated entries without delimiters: `a, b, c` if they fit, and otherwise one
+    /// entry per line, each followed by a comma (including the last, as rustfmt does). Nest the
+    /// list and put line breaks around it to lay out arguments or fields.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let args = || ["alpha", "beta", "gamma"].map(<Doc>::text);
+    /// let call = |width| -> String {
+    ///     let args = Doc::line_break().concat(Doc::comma_list(args())).nest(4);
+    ///     Doc::text("f(")
+    ///         .concat(args)
+    ///         .concat(Doc::line_break())
+    ///         .concat(Doc::text(")"))
+    ///         .group()
+    ///         .render(width)
+    /// };
+    /// assert_eq!(call(80), "f(alpha, beta, gamma)");
+    /// assert_eq!(call(10), "f(\n    alpha,\n    beta,\n    gamma,\n)");
+    /// ```
+    pub fn comma_list(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        let docs: Vec<Doc<A>> = docs.into_iter().collect();
+        if docs.is_empty() {
+            return Doc::<A>::nil();
+        }
+        let trailing = Doc::<A>::comma().flat_alt(Doc::<A>::nil());
+        Doc::intersperse(docs, Doc::<A>::comma().concat(Doc::<A>::line()))
+            .concat(trailing)
+            .group()
+    }
 
-        // Right branch: (x </> fill (y : zs))
-        // If head_flat is true, x is already flattened; use it as-is.
-        let x_for_right = if head_flat { x } else { xs[i].clone() };
-        let right = x_for_right
-            .concat(Doc::line())
-            .concat(Self::fill_core(xs, y_is_head, false));
+    // `open`, then `docs` separated by commas, then `close`, with `padding` inside the delimiters
+    // when on one line. See [`Doc::enclose_sep`].
+    fn comma_separated(
+        open: Doc<A>,
+        docs: impl IntoIterator<Item = Doc<A>>,
+        close: Doc<A>,
+        padding: Doc<A>,
+    ) -> Doc<A> {
+        let trailing = SeparatorPlacement::Trailing;
+        Doc::separated(open, docs, Doc::<A>::comma(), close, padding, trailing)
+    }
 
-        left.alt(right)
+    fn separated(
+        open: Doc<A>,
+        docs: impl IntoIterator<Item = Doc<A>>,
+        separator: Doc<A>,
+        close: Doc<A>,
+        padding: Doc<A>,
+        placement: SeparatorPlacement,
+    ) -> Doc<A> {
+        let docs: Vec<Doc<A>> = docs.into_iter().collect();
+        if docs.is_empty() {
+            return open.concat(close);
+        }
+        let flat = open
+            .clone()
+            .concat(padding.clone())
+            .concat(Doc::intersperse(
+                docs.iter().cloned(),
+                separator.clone().concat(Doc::<A>::space()),
+            ))
+            .concat(padding)
+            .concat(close.clone());
+        let broken = match placement {
+            SeparatorPlacement::Trailing => open
+                .concat(
+                    Doc::<A>::line()
+                        .concat(Doc::intersperse(docs, separator.concat(Doc::<A>::line())))
+                        .nest(4),
+                )
+                .concat(Doc::<A>::line())
+                .concat(close),
+            SeparatorPlacement::Leading => open
+                .concat(Doc::<A>::space())
+                .concat(Doc::intersperse(
+                    docs,
+                    Doc::<A>::line().concat(separator).concat(Doc::<A>::space()),
+                ))
+                .concat(Doc::<A>::line())
+                .concat(close)
+                .align(),
+        };
+        flat.flatten().alt(broken)
     }
 
-    // -------------------------------------------
-    // Constant Constructors
-    // -------------------------------------------
+    /// Render `self` as a typical block:
+    ///
+    /// ```text
+    /// {start}
+    ///     {self (indented, grouped)}
+    /// {end}
+    /// ```
+    ///
+    /// Uses a 4‑space indent and inserts newlines before and after the block body. See
+    /// [`Doc::block_with`] for other styles.
+    pub fn block(self, start: Doc<A>, end: Doc<A>) -> Doc<A> {
+        self.block_with(start, end, &BlockStyle::new())
+    }
 
-    /// The `<` document.
-    pub fn lparen() -> Doc {
-        LPAREN_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Render `self` as a block laid out as `style` says. With [`BraceStyle::Allman`], `start`
+    /// goes on a line of its own, so what precedes the block shouldn't end in a space.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let body: Doc = Doc::text("x = 1;");
+    /// let style = BlockStyle::new().indent(2).padding(true);
+    /// let doc = Doc::text("if x ").concat(body.block_with(Doc::lbrace(), Doc::rbrace(), &style));
+    /// assert_eq!(doc.render(80), "if x {\n\n  x = 1;\n\n}");
+    /// ```
+    pub fn block_with(self, start: Doc<A>, end: Doc<A>, style: &BlockStyle) -> Doc<A> {
+        let open = match style.braces {
+            BraceStyle::KAndR => start,
+            BraceStyle::Allman => Doc::<A>::line().concat(start),
+        };
+        let mut separator = Doc::<A>::line();
+        if style.padding {
+            separator = separator.concat(Doc::<A>::line());
+        }
+        open.concat(separator.clone())
+            .concat(self.indent(style.indent).group())
+            .concat(separator)
+            .concat(end)
     }
 
-    /// The `>` document.
-    pub fn rparen() -> Doc {
-        RPAREN_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Quote `doc` email-style: every line it renders to starts with `prefix` (e.g., `"> "`), and
+    /// it is laid out as though the page were narrower by the width of `prefix`. Quoted blocks
+    /// nest, so quoting a quote yields `"> > "`.
+    ///
+    /// Continuation lines place `prefix` at the indentation the block starts at, and `doc`'s own
+    /// nesting is relative to the end of `prefix`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let words: [Doc; 3] = ["quoting", "is", "fun"].map(Doc::text);
+    /// let reply = Doc::quoted_block("> ", Doc::sep(words));
+    /// let doc = Doc::quoted_block("> ", Doc::vsep([Doc::text("earlier:"), reply]));
+    /// assert_eq!(doc.render(14), "> earlier:\n> > quoting\n> > is\n> > fun");
+    /// ```
+    pub fn quoted_block<S: Into<String>>(prefix: S, doc: Doc<A>) -> Doc<A> {
+        let prefix = prefix.into();
+        doc.prefix_lines_with(prefix.clone(), prefix)
     }
 
-    /// The `<` document.
-    pub fn langle() -> Doc {
-        LANGLE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Start every line `self` renders to with `prefix` (e.g., `"// "` or `"# "`), as
+    /// [`Doc::quoted_block`] does. The prefix is plain text, since the renderer writes it after
+    /// each line break that `self`'s layout chooses.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let words = "prefixes follow the lines that layout picks".split(' ').map(<Doc>::text);
+    /// let comment = Doc::sep(words).prefix_lines("// ");
+    /// assert_eq!(
+    ///     comment.render(24),
+    ///     "// prefixes\n// follow\n// the\n// lines\n// that\n// layout\n// picks"
+    /// );
+    /// ```
+    pub fn prefix_lines<S: Into<String>>(self, prefix: S) -> Doc<A> {
+        Doc::quoted_block(prefix, self)
     }
 
-    /// The `>` document.
-    pub fn rangle() -> Doc {
-        RANGLE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Like [`Doc::prefix_lines`], but start the first line with `first` and the rest with
+    /// `rest`, as doc comments such as `/** ` and ` * ` do. `self` is laid out as though the
+    /// page were narrower by the longer of the two.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let summary: Doc = Doc::reflow("Render the document at the given width.");
+    /// let comment = summary
+    ///     .prefix_lines_with("/** ", " * ")
+    ///     .concat(Doc::line())
+    ///     .concat(Doc::text(" */"));
+    /// let options = RenderOptions::new(24).algorithm(LayoutAlgorithm::Oppen);
+    /// assert_eq!(
+    ///     comment.render_with(&options),
+    ///     "/** Render the document\n * at the given width.\n */"
+    /// );
+    /// ```
+    pub fn prefix_lines_with<S, T>(self, first: S, rest: T) -> Doc<A>
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        let (first, rest) = (first.into(), rest.into());
+        let columns = first.len().max(rest.len()) as Width;
+        let body = DocInner::Prefix(rest, DocInner::Narrow(columns, self).into_doc());
+        Doc::<A>::text(first).concat(body.into_doc())
     }
 
-    /// The `[` document.
-    pub fn lbracket() -> Doc {
-        LBRACKET_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Fill a la Wadler
+    /// This
+    pub fn fill(xs: &[Doc<A>]) -> Doc<A> {
+        Doc::fill_by(xs, Doc::<A>::space())
     }
 
-    /// The `]` document.
-    pub fn rbracket() -> Doc {
-        RBRACKET_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Pack as many of `docs` onto each line as fit, separated by spaces, then break and carry on
+    /// on the next line. See [`Doc::fill`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let words = || ["a", "b", "c"].map(Doc::text);
+    /// assert_eq!(<Doc>::fill_sep(words()).render(80), "a b c");
+    /// assert_eq!(<Doc>::fill_cat(words()).render(80), "abc");
+    /// ```
+    pub fn fill_sep(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::fill(&docs.into_iter().collect::<Vec<_>>())
     }
 
-    /// The `{` document.
-    pub fn lbrace() -> Doc {
-        LBRACE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    /// Like [`Doc::fill_sep`], but without spaces between the documents on a line.
+    pub fn fill_cat(docs: impl IntoIterator<Item = Doc<A>>) -> Doc<A> {
+        Doc::fill_by(&docs.into_iter().collect::<Vec<_>>(), Doc::<A>::nil())
     }
 
-    /// The `}` document.
-    pub fn rbrace() -> Doc {
-        RBRACE_INNER.with(|lazy| Doc(Rc::clone(lazy)))
+    // A fill whose items are separated by `separator` on a line.
+    fn fill_by(xs: &[Doc<A>], separator: Doc<A>) -> Doc<A> {
+        // Built back to front so that long inputs don't recurse. `tail_flat` is the fill of the
+        // remaining items with its head flattened (because it follows a separator), and
+        // `tail_broken` is the fill of the remaining items as-is (because it follows a line
+        // break).
+        let Some((last, init)) = xs.split_last() else {
+            return Doc::<A>::nil();
+        };
+        let mut tail_flat = last.clone().flatten();
+        let mut tail_broken = last.clone();
+        for x in init.iter().rev() {
+            // (x <+> fill (flatten y : zs)) <|> (x </> fill (y : zs)), where the left branch
+            // flattens x unless the caller already did.
+            let right = x.clone().concat(Doc::<A>::line()).concat(tail_broken);
+            let left = |x: Doc<A>| x.concat(separator.clone()).concat(tail_flat.clone());
+            let next_flat = left(x.clone()).alt(right.clone());
+            let next_broken = left(x.clone().flatten()).alt(right);
+            tail_flat = next_flat;
+            tail_broken = next_broken;
+        }
+        tail_broken
     }
 
     // -------------------------------------------
@@ -500,149 +1883,3592 @@ impl Doc {
     /// Soft breaks choose between space/newline based on whether the flattened
     /// alternative fits within the remaining width; hard breaks always break.
     /// The algorithm is a variant of Wadler/Leijen pretty‑printing.
-    pub fn render(self, width: i16) -> String {
-        let rendered = self.best(width);
-        let output = rendered.render();
-        // std::mem::forget(rendered);
-        output.unwrap()
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails; see [`Doc::try_render`] for a non-panicking variant.
+    pub fn render(self, width: Width) -> String {
+        self.render_with(&RenderOptions::new(width))
     }
 
-    fn best(self, width: i16) -> Render {
-        use DocInner as DI;
+    /// Render the document to a `String` using the given [`RenderOptions`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails; see [`Doc::try_render_with`] for a non-panicking variant.
+    pub fn render_with(self, options: &RenderOptions) -> String {
+        match self.try_render_with(options) {
+            Ok(output) => output,
+            Err(err) => panic!("failed to render document: {}", err),
+        }
+    }
+
+    /// Render the document to a `String` using the given maximum line `width`, reporting any
+    /// failure as a [`RenderError`] instead of panicking.
+    pub fn try_render(self, width: Width) -> Result<String, RenderError> {
+        self.try_render_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document to a `String` using the given [`RenderOptions`], reporting any
+    /// failure as a [`RenderError`] instead of panicking.
+    pub fn try_render_with(self, options: &RenderOptions) -> Result<String, RenderError> {
+        let mut output = String::new();
+        self.render_to_string(options, &mut output)?;
+        Ok(output)
+    }
+
+    /// Render the document at the given `width`, appending the output to `output`.
+    ///
+    /// This lets hot loops reuse one buffer (clearing it between renders) instead of allocating
+    /// a fresh `String` for every render.
+    pub fn render_into(&self, width: Width, output: &mut String) {
+        self.render_into_with(&RenderOptions::new(width), output)
+    }
 
-        enum Cons {
-            Cell { head: (i16, Doc), tail: Rc<Cons> },
-            Nil,
+    /// Render the document using the given [`RenderOptions`], appending the output to `output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_into_with(&self, options: &RenderOptions, output: &mut String) {
+        if let Err(err) = self.clone().render_to_string(options, output) {
+            panic!("failed to render document: {}", err)
         }
+    }
 
-        fn cons(head: (i16, Doc), tail: Rc<Cons>) -> Rc<Cons> {
-            Rc::new(Cons::Cell { head, tail })
+    /// Render the document using the given [`RenderOptions`], and report how much work that took
+    /// (see [`RenderMetrics`]), e.g., to export metrics or flag pathological documents.
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_with_metrics(self, options: &RenderOptions) -> (String, RenderMetrics) {
+        let start = std::time::Instant::now();
+        let mut layouter = Layouter::new(self, options.config());
+        let mut parts = Parts::default();
+        parts.extend(layouter.by_ref());
+        let mut output = String::new();
+        if let Err(err) = paint(&parts, options, &mut output, &mut ()) {
+            panic!("failed to render document: {}", err)
         }
+        let mut metrics = layouter.metrics;
+        metrics.elapsed = start.elapsed();
+        (output, metrics)
+    }
 
-        // A non-allocating, non-recursive "does it fit?" that peeks ahead.
-        // Returns false if we'd exceed `remaining` or hit a hard Line.
-        fn fits(mut remaining: i16, mut cursor: i16, mut docs: Rc<Cons>) -> bool {
-            while let Cons::Cell {
-                head: (i, doc),
-                tail,
-            } = &*docs
-            {
-                match &*doc.0 {
-                    DI::Line => return true,
-                    DI::Empty => {
-                        docs = tail.clone();
-                    }
-                    DI::Text(s) => {
-                        let s_len = s.len() as i16;
-                        if s_len > remaining {
-                            return false;
-                        };
-                        remaining -= s_len;
-                        let Some(new_cursor) = cursor.checked_add(s_len) else {
-                            return false;
-                        };
-                        cursor = new_cursor;
-                        docs = tail.clone();
-                    }
-                    DI::Concat(x, y) => {
-                        docs = cons((*i, x.clone()), cons((*i, y.clone()), tail.clone()));
-                    }
-                    DI::Nest(j, inner) => {
-                        docs = cons((i + j, inner.clone()), tail.clone());
-                    }
-                    DI::Alt(flat, _doc2) => {
-                        docs = cons((*i, flat.clone()), tail.clone());
-                    }
-                    DI::Column(f) => {
-                        docs = cons((*i, f(cursor)), tail.clone());
-                    }
-                    DI::Nesting(f) => {
-                        docs = cons((*i, f(*i)), tail.clone());
-                    }
+    /// The size of the document rendered at the given `width`, without building the output:
+    /// e.g., to decide where to place a tooltip or panel before rendering into it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::sep(["first", "second", "third"].map(Doc::text));
+    /// let size = doc.measure(12);
+    /// assert_eq!((size.lines, size.max_line_width, size.chars), (3, 6, 18));
+    /// assert_eq!(doc.measure(80).lines, 1);
+    /// ```
+    pub fn measure(&self, width: Width) -> Dimensions {
+        self.measure_with(&RenderOptions::new(width))
+    }
+
+    /// The size of the document rendered using the given [`RenderOptions`]. See
+    /// [`Doc::measure`].
+    pub fn measure_with(&self, options: &RenderOptions) -> Dimensions {
+        // Only the current line is kept, to measure it once it's finished.
+        let mut dimensions = Dimensions::default();
+        let metrics = TextMetrics {
+            version: options.version.max(LayoutVersion::V3),
+            ..options.config().metrics()
+        };
+        let mut measure = |line: RenderedLine<'_>| {
+            dimensions.lines += 1;
+            let width = metrics.width_of(line.text).max(0) as usize;
+            dimensions.max_line_width = dimensions.max_line_width.max(width);
+            dimensions.chars += line.text.chars().count();
+        };
+        let mut painter = Painter::new(Cow::Borrowed(options), (), String::new());
+        for event in Layouter::new(self.clone(), options.config()).plain() {
+            match event {
+                RenderEvent::Newline { indent } => {
+                    measure(painter.end_line());
+                    painter.clear();
+                    painter.start_line(indent);
                 }
+                event => painter.event(event),
             }
-            true
         }
+        measure(painter.end_line());
+        dimensions.chars += dimensions.lines - 1;
+        dimensions
+    }
 
-        let mut docs = cons((0, self), Rc::new(Cons::Nil));
-        let mut cursor = 0i16;
-        let mut out: Vec<RenderPart> = vec![];
+    /// Render the document at the given `width`, streaming the output to `writer` a line at a
+    /// time.
+    ///
+    /// If writing fails, the [`RenderError::Io`] reports how much output was written in full
+    /// beforehand (see [`RenderPosition`]), so callers can resume or report partial output.
+    pub fn write_to<W: std::io::Write>(
+        &self,
+        width: Width,
+        writer: &mut W,
+    ) -> Result<(), RenderError>
+    where
+        A: std::fmt::Debug,
+    {
+        self.write_to_with(&RenderOptions::new(width), writer)
+    }
 
-        while let Cons::Cell { head, tail } = &*docs {
-            let (indent, doc) = head;
-            match &*doc.0 {
-                DI::Empty => {
-                    docs = tail.clone();
-                }
-                DI::Text(s) => {
-                    out.push(RenderPart::Text(s.to_string()));
-                    cursor = cursor.saturating_add(s.len() as i16);
-                    docs = tail.clone();
-                }
-                DI::Concat(x, y) => {
-                    docs = cons(
-                        (*indent, x.clone()),
-                        cons((*indent, y.clone()), tail.clone()),
-                    );
-                }
-                DI::Nest(j, inner) => {
-                    docs = cons((indent + j, inner.clone()), tail.clone());
-                }
-                DI::Line => {
-                    out.push(RenderPart::Line(*indent));
-                    cursor = *indent;
-                    docs = tail.clone();
-                }
-                DI::Alt(flat, alt) => {
-                    let flat = cons((*indent, flat.clone()), tail.clone());
-                    if fits(width, cursor, flat.clone()) {
-                        docs = flat;
-                    } else {
-                        docs = cons((*indent, alt.clone()), tail.clone());
-                    }
-                }
-                DI::Column(f) => {
-                    docs = cons((*indent, f(cursor)), tail.clone());
+    /// Render the document using the given [`RenderOptions`], streaming the output to `writer`.
+    /// See [`Doc::write_to`].
+    pub fn write_to_with<W: std::io::Write>(
+        &self,
+        options: &RenderOptions,
+        writer: &mut W,
+    ) -> Result<(), RenderError>
+    where
+        A: std::fmt::Debug,
+    {
+        // `written` and `flushed` describe the output up to the end of the last line written in
+        // full.
+        let mut written = RenderPosition::default();
+        let mut flushed: Vec<A> = vec![];
+        let mut flush = |line: &str, open: &[A]| {
+            if let Err(error) = writer.write_all(line.as_bytes()) {
+                let open_annotations = flushed.iter().map(|a| format!("{:?}", a)).collect();
+                return Err(RenderError::Io {
+                    error,
+                    position: RenderPosition {
+                        open_annotations,
+                        ..written
+                    },
+                });
+            }
+            written.bytes += line.len();
+            written.lines += line.ends_with('\n') as usize;
+            flushed.clear();
+            flushed.extend_from_slice(open);
+            Ok(())
+        };
+        let mut open = vec![];
+        let mut painter = Painter::new(Cow::Borrowed(options), (), String::new());
+        for event in Layouter::new(self.clone(), options.config()).unbalanced() {
+            match event {
+                RenderEvent::Newline { indent } => {
+                    painter.end_line();
+                    painter.output.push('\n');
+                    flush(&painter.output, &open)?;
+                    painter.clear();
+                    painter.start_line(indent);
                 }
-                DI::Nesting(f) => {
-                    docs = cons((*indent, f(*indent)), tail.clone());
+                RenderEvent::PushAnnotation(a) => open.push(a),
+                RenderEvent::PopAnnotation => {
+                    open.pop();
                 }
+                event => painter.event(event),
             }
         }
-
-        Render(out)
+        flush(&painter.finish(), &open)
     }
-}
 
-// -------------------------------------------------------------------------------------------------
-// Rendering
-// -------------------------------------------------------------------------------------------------
+    /// Render the document like [`Doc::render`], skipping annotations entirely rather than
+    /// tracking and then dropping them. The output is the same.
+    pub fn render_plain(self, width: Width) -> String {
+        self.render_plain_with(&RenderOptions::new(width))
+    }
 
-enum RenderPart {
-    Line(i16),
-    Text(String),
-}
+    /// Render the document like [`Doc::render_with`], skipping annotations entirely. See
+    /// [`Doc::render_plain`].
+    pub fn render_plain_with(self, options: &RenderOptions) -> String {
+        let layouter = &mut Layouter::new(self, options.config()).plain();
+        Doc::write_plain(layouter, options)
+    }
 
-struct Render(Vec<RenderPart>);
+    /// Render the document at the given `width` in at most `max_height` lines, for dashboards and
+    /// other fixed-size panes. While the output is too tall, the least important regions (see
+    /// [`Doc::elidable`]) are replaced by their summaries, a level at a time. Lines past
+    /// `max_height` are cut off if that isn't enough.
+    ///
+    /// Like [`Doc::render_plain`], this ignores annotations.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let details: Doc = Doc::vsep(["pid 4242", "uptime 3d"].map(Doc::text));
+    /// let doc = Doc::vsep([
+    ///     Doc::text("web: running"),
+    ///     details.elidable(1, Doc::text("(2 more)")).indent(2),
+    ///     Doc::text("db: stopped"),
+    /// ]);
+    /// assert_eq!(doc.clone().render_elided(80, 4), doc.clone().render(80));
+    /// assert_eq!(doc.clone().render_elided(80, 3), "web: running\n  (2 more)\ndb: stopped");
+    /// assert_eq!(doc.render_elided(80, 2), "web: running\n  (2 more)");
+    /// ```
+    pub fn render_elided(self, width: Width, max_height: usize) -> String {
+        self.render_elided_with(&RenderOptions::new(width), max_height)
+    }
 
-impl Render {
-    fn render(&self) -> Result<String, std::fmt::Error> {
-        use std::fmt::Write;
-        let renders = &self.0;
-        let mut output = String::new();
-        for render in renders.iter() {
-            match render {
-                RenderPart::Line(i) => {
-                    writeln!(&mut output)?;
-                    for _n in 0..*i {
-                        write!(&mut output, " ")?;
-                    }
-                }
-                RenderPart::Text(s) => {
-                    write!(&mut output, "{}", s)?;
+    /// Render the document using the given [`RenderOptions`] in at most `max_height` lines. See
+    /// [`Doc::render_elided`].
+    pub fn render_elided_with(self, options: &RenderOptions, max_height: usize) -> String {
+        let mut config = options.config();
+        loop {
+            let layouter = &mut Layouter::new(self.clone(), config.clone()).plain();
+            let output = Doc::write_plain(layouter, options);
+            let height = output.split('\n').count();
+            match layouter.importances.first() {
+                Some(least) if height > max_height => config.elide = Some(*least),
+                _ => {
+                    let lines: Vec<&str> = output.split('\n').take(max_height).collect();
+                    return lines.join("\n");
                 }
             }
         }
-        Ok(output)
+    }
+
+    // Write out the events of a plain layout.
+    fn write_plain(layouter: &mut Layouter<A>, options: &RenderOptions) -> String {
+        let mut painter = Painter::new(Cow::Borrowed(options), (), String::new());
+        for event in layouter {
+            painter.event(event);
+        }
+        painter.finish()
+    }
+
+    // Consumes `self` so that nodes can be freed as layout proceeds.
+    fn render_to_string(
+        self,
+        options: &RenderOptions,
+        output: &mut String,
+    ) -> Result<(), RenderError> {
+        self.render_marked_up(options, output, &mut ())
+    }
+
+    // Like `render_to_string`, but lets `markup` write annotations into the output.
+    fn render_marked_up(
+        self,
+        options: &RenderOptions,
+        output: &mut String,
+        markup: &mut impl Markup<A>,
+    ) -> Result<(), RenderError> {
+        self.layout_with(options).paint(output, markup)?;
+        Ok(())
+    }
+
+    /// Lay the document out at the given maximum line `width`, producing a [`Layout`] rather
+    /// than a `String`. The layout is computed lazily, as a stream of [`RenderEvent`]s, and can
+    /// be painted into any number of outputs without being recomputed. To consume the events
+    /// once without keeping them, use [`Doc::layout_events`].
+    ///
+    /// This is the same layout [`Doc::render`] uses, and lets other back ends (terminals, GUIs,
+    /// HTML, ...) consume it directly.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc = Doc::text("a").concat(Doc::line().concat(Doc::text("b")).nest(2));
+    /// let events: Vec<RenderEvent> = doc.layout(80).collect();
+    /// assert_eq!(
+    ///     events,
+    ///     vec![
+    ///         RenderEvent::Text("a".into()),
+    ///         RenderEvent::Newline { indent: 2 },
+    ///         RenderEvent::Text("b".into()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn layout(self, width: Width) -> Layout<A> {
+        self.layout_with(&RenderOptions::new(width))
+    }
+
+    /// Lay the document out using the given [`RenderOptions`]. See [`Doc::layout`].
+    pub fn layout_with(self, options: &RenderOptions) -> Layout<A> {
+        Layout {
+            options: options.clone(),
+            layouter: RefCell::new(Some(Layouter::new(self, options.config()))),
+            parts: RefCell::default(),
+            next: Cursor::default(),
+        }
+    }
+
+    /// Lay the document out at the given maximum line `width`, streaming its [`RenderEvent`]s
+    /// as layout decides them. Unlike a [`Layout`], the events aren't kept, so memory use stays
+    /// flat however long the output is.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::vsep((0..1000).map(|i| Doc::text(i.to_string())));
+    /// let breaks = doc
+    ///     .layout_events(80)
+    ///     .filter(|event| matches!(event, RenderEvent::Newline { .. }));
+    /// assert_eq!(breaks.count(), 999);
+    /// ```
+    pub fn layout_events(self, width: Width) -> LayoutEvents<A> {
+        self.layout_events_with(&RenderOptions::new(width))
+    }
+
+    /// Lay the document out using the given [`RenderOptions`], streaming its events. See
+    /// [`Doc::layout_events`].
+    pub fn layout_events_with(self, options: &RenderOptions) -> LayoutEvents<A> {
+        LayoutEvents(Layouter::new(self, options.config()))
+    }
+
+    /// Render the document at the given `width` as a lazy sequence of lines (without their
+    /// trailing newlines), so callers can prefix, number, or paginate output line by line.
+    ///
+    /// Yields the same lines as `render(width).split('\n')`.
+    pub fn render_lines(&self, width: Width) -> impl Iterator<Item = String> {
+        self.render_lines_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document using the given [`RenderOptions`] as a lazy sequence of lines. See
+    /// [`Doc::render_lines`].
+    pub fn render_lines_with(&self, options: &RenderOptions) -> impl Iterator<Item = String> {
+        let mut events = Layouter::new(self.clone(), options.config()).plain();
+        let mut painter = Some(Painter::new(Cow::Owned(options.clone()), (), String::new()));
+        std::iter::from_fn(move || {
+            let current = painter.as_mut()?;
+            for event in events.by_ref() {
+                match event {
+                    RenderEvent::Newline { indent } => {
+                        let line = current.end_line().text.to_string();
+                        current.clear();
+                        current.start_line(indent);
+                        return Some(line);
+                    }
+                    event => current.event(event),
+                }
+            }
+            painter.take().map(Painter::finish)
+        })
+    }
+
+    /// Render the document at the given `width`, passing each laid-out line through `f` before
+    /// it's emitted, e.g., to number lines, add prefixes, highlight, or truncate them. `f`
+    /// returns the text that replaces the line (see [`RenderedLine`]).
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::text("fn main() {")
+    ///     .concat(Doc::line().concat(Doc::text("run();")).nest(4))
+    ///     .concat(Doc::line())
+    ///     .concat(Doc::text("}"));
+    /// let numbered = doc.each_line(80, |line| format!("{:>2} | {}", line.index + 1, line.text));
+    /// assert_eq!(numbered, " 1 | fn main() {\n 2 |     run();\n 3 | }");
+    /// ```
+    pub fn each_line<F>(self, width: Width, f: F) -> String
+    where
+        F: FnMut(RenderedLine<'_>) -> String,
+    {
+        self.each_line_with(&RenderOptions::new(width), f)
+    }
+
+    /// Render the document using the given [`RenderOptions`], passing each laid-out line through
+    /// `f`. See [`Doc::each_line`].
+    pub fn each_line_with<F>(self, options: &RenderOptions, mut f: F) -> String
+    where
+        F: FnMut(RenderedLine<'_>) -> String,
+    {
+        let mut output = String::new();
+        let mut painter = Painter::new(Cow::Borrowed(options), (), String::new());
+        for event in Layouter::new(self, options.config()).plain() {
+            match event {
+                RenderEvent::Newline { indent } => {
+                    output.push_str(&f(painter.end_line()));
+                    output.push('\n');
+                    painter.clear();
+                    painter.start_line(indent);
+                }
+                event => painter.event(event),
+            }
+        }
+        output.push_str(&f(painter.end_line()));
+        output
+    }
+
+    /// The smallest width, at most `max`, at which this document lays out in as few lines as it
+    /// does at `max` ("shrink to fit"). Useful for sizing popups and tooltips to their content.
+    ///
+    /// Found by binary search over widths, on the assumption that widening the layout never
+    /// adds lines.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::sep([Doc::text("hello"), Doc::text("world")]);
+    /// assert_eq!(doc.natural_width(80), 11);
+    /// ```
+    pub fn natural_width(&self, max: Width) -> Width {
+        let target = self.line_count(max);
+        let (mut lo, mut hi) = (0, max.max(0));
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.line_count(mid) <= target {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        hi
+    }
+
+    fn line_count(&self, width: Width) -> usize {
+        let breaks = Layouter::new(self.clone(), LayoutConfig::new(width))
+            .filter(|event| matches!(event, RenderEvent::Newline { .. }))
+            .count();
+        breaks + 1
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Leaf Constructors
+// -------------------------------------------------------------------------------------------------
+
+// Documents without annotations are the common case, so the constructors that take no other
+// document build plain `Doc`s, and their annotation type needs no spelling out.
+impl Doc {
+    /// The empty document.
+    ///
+    /// Renders to nothing and acts as the identity element for [`Doc::concat`].
+    pub fn nil() -> Doc {
+        LeafDoc::nil()
+    }
+
+    /// A single ASCII space as a document (`" "`).
+    pub fn space() -> Doc {
+        LeafDoc::space()
+    }
+
+    /// A single ASCII comma as a document (`","`).
+    pub fn comma() -> Doc {
+        LeafDoc::comma()
+    }
+
+    /// A hard line break.
+    ///
+    /// When rendered, this always breaks the line and sets the cursor to the current
+    /// indentation level tracked by nesting/indentation combinators.
+    pub fn line() -> Doc {
+        LeafDoc::line()
+    }
+
+    /// A soft line break that becomes a space if the layout fits the given width,
+    /// or a newline otherwise.
+    ///
+    /// This is equivalent to `Alt(space, line)` in Wadler/Leijen pretty‑printing.
+    pub fn softline() -> Doc {
+        LeafDoc::softline()
+    }
+
+    /// A soft line break that becomes empty if the layout fits, or a newline
+    /// otherwise.
+    ///
+    /// Useful for optional separators (e.g., trailing commas off).
+    pub fn softline_empty() -> Doc {
+        LeafDoc::softline_empty()
+    }
+
+    /// A line break that becomes empty, rather than a space, when its group is laid out flat.
+    ///
+    /// See [`Doc::vcat`].
+    pub fn line_break() -> Doc {
+        LeafDoc::line_break()
+    }
+
+    /// Construct a document from raw text.
+    ///
+    /// The string is inserted verbatim; it will not contain line breaks unless
+    /// they are present in the string itself (which generally should be avoided
+    /// in pretty‑printing docs).
+    ///
+    /// The text is copied into the document; use [`Doc::static_text`] for string literals, which
+    /// needn't be.
+    pub fn text<S: Into<String>>(str: S) -> Doc {
+        LeafDoc::text(str)
+    }
+
+    /// Construct a document from static text, such as a string literal, as [`Doc::text`] does,
+    /// but without copying it: the document, and the layouts made from it, refer to `text`
+    /// directly.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let keyword: Doc = Doc::static_text("return");
+    /// assert_eq!(keyword.concat(Doc::text(" x;")).render(80), "return x;");
+    /// ```
+    pub fn static_text(text: &'static str) -> Doc {
+        LeafDoc::static_text(text)
+    }
+
+    /// The text of `source` in `range`, as [`Doc::text`], but referencing `source` rather than
+    /// copying it, so formatters over large inputs can build documents from slices of the input.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds of `source` or doesn't start and end on character
+    /// boundaries, as slicing it would.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::rc::Rc;
+    /// use pretty_simple::*;
+    ///
+    /// let source: Rc<str> = Rc::from("let answer = 42;");
+    /// let tokens = [0..3, 4..10, 11..12, 13..15].map(|range| Doc::text_slice(&source, range));
+    /// let doc: Doc = Doc::hsep(tokens).concat(Doc::text(";"));
+    /// assert_eq!(doc.render(80), "let answer = 42;");
+    /// ```
+    pub fn text_slice(source: &Rc<str>, range: std::ops::Range<usize>) -> Doc {
+        LeafDoc::text_slice(source, range)
+    }
+
+    /// A single character as a document. Common punctuation reuses the cached constant
+    /// documents instead of allocating.
+    pub fn char(c: char) -> Doc {
+        LeafDoc::char(c)
+    }
+
+    /// A document that never fits, so that an alternative containing it is never chosen while
+    /// there's another. Use it to rule out layouts under some condition:
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // Only put the arguments on one line if there are at most two.
+    /// let call = |args: &[&str]| -> Doc {
+    ///     let args = || args.iter().map(|arg| Doc::text(*arg));
+    ///     let flat = if args().len() <= 2 { Doc::hsep(args()) } else { Doc::fail() };
+    ///     let broken = Doc::line().concat(Doc::vsep(args())).nest(2);
+    ///     Doc::text("f").concat(Doc::space().concat(flat).alt(broken))
+    /// };
+    /// assert_eq!(call(&["x", "y"]).render(80), "f x y");
+    /// assert_eq!(call(&["x", "y", "z"]).render(80), "f\n  x\n  y\n  z");
+    /// ```
+    ///
+    /// If every alternative fails, the last is laid out anyway, and `fail` renders as nothing.
+    pub fn fail() -> Doc {
+        LeafDoc::fail()
+    }
+
+    /// Move to the absolute `column`: pad with spaces up to it, or, if the line is already past
+    /// it, break the line and indent the next one to it. Useful for end-of-line comments and
+    /// other columns that line up across the whole output.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let commented = |code: &str, comment: &str| -> Doc {
+    ///     Doc::text(code.to_string())
+    ///         .concat(Doc::align_to(12))
+    ///         .concat(Doc::text(format!("# {comment}")))
+    /// };
+    /// let doc = Doc::vsep([commented("x = 1", "start"), commented("y = x * 10000", "scale")]);
+    /// assert_eq!(doc.render(80), "x = 1       # start\ny = x * 10000\n            # scale");
+    /// ```
+    pub fn align_to(column: Width) -> Doc {
+        LeafDoc::align_to(column)
+    }
+
+    /// Produce `i` spaces as a document (`" ".repeat(i)`), with fast paths for 0 and 1.
+    pub fn spaces(i: Width) -> Doc {
+        LeafDoc::spaces(i)
+    }
+
+    /// Text that may contain newlines: each line of `s` becomes a text document, joined with
+    /// [`Doc::line`]s, so that the lines after the first pick up the current nesting and the
+    /// renderer knows where they start. [`Doc::text`] would write the newlines verbatim instead.
+    ///
+    /// Unlike [`Doc::lines`], the lines are otherwise kept as they are.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::text("note: ").concat(Doc::string("first\nsecond").align());
+    /// assert_eq!(doc.render(80), "note: first\n      second");
+    /// ```
+    pub fn string(s: &str) -> Doc {
+        LeafDoc::string(s)
+    }
+
+    /// Split a multi-line string into text documents separated by hard line breaks, stripping
+    /// the indentation common to all non-blank lines (like `indoc!`).
+    ///
+    /// A leading newline and a trailing whitespace-only line are dropped, so literal templates
+    /// can be written as indented raw strings. Because the breaks are ordinary [`Doc::line`]s,
+    /// the result picks up the indentation of any enclosing [`Doc::nest`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let body: Doc = Doc::lines(
+    ///     "
+    ///     if ready {
+    ///         go();
+    ///     }
+    ///     ",
+    /// );
+    /// let doc = Doc::text("fn run() {")
+    ///     .concat(Doc::line().concat(body).nest(4))
+    ///     .concat(Doc::line())
+    ///     .concat(Doc::text("}"));
+    /// assert_eq!(
+    ///     doc.render(80),
+    ///     "fn run() {\n    if ready {\n        go();\n    }\n}"
+    /// );
+    /// ```
+    pub fn lines(s: &str) -> Doc {
+        LeafDoc::lines(s)
+    }
+
+    /// Splice in output that was already laid out elsewhere (e.g., by an external formatter),
+    /// one string per line. The lines are kept verbatim, including their own indentation, and
+    /// the lines after the first start at the current nesting level, so the fragment stays
+    /// indented with the rest of the document.
+    ///
+    /// The breaks are ordinary [`Doc::line`]s, so keep the fragment out of groups that could lay
+    /// it out flat.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let formatted = vec!["{".to_string(), "  \"a\": 1".to_string(), "}".to_string()];
+    /// let doc: Doc = Doc::text("config:")
+    ///     .concat(Doc::line().concat(Doc::pre_rendered(formatted)).nest(4));
+    /// assert_eq!(doc.render(80), "config:\n    {\n      \"a\": 1\n    }");
+    /// ```
+    pub fn pre_rendered(lines: Vec<String>) -> Doc {
+        LeafDoc::pre_rendered(lines)
+    }
+
+    /// Split `s` into words and join them with [`Doc::softline`]s, so the text reflows to the
+    /// page width. Any run of whitespace in `s` (including line breaks) becomes a single
+    /// separator, and leading and trailing whitespace is dropped.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::words("  a   sloppy\n string ");
+    /// assert_eq!(doc.render(80), "a sloppy string");
+    /// ```
+    pub fn words(s: &str) -> Doc {
+        LeafDoc::words(s)
+    }
+
+    /// Prose that wraps to the page width: each paragraph of `text` (paragraphs are separated by
+    /// blank lines) becomes its words joined with [`Doc::softline`]s, as in [`Doc::words`], and
+    /// the paragraphs are separated by a blank line. Useful for doc comments and help text.
+    ///
+    /// With [`LayoutAlgorithm::Oppen`], lines are filled greedily. The default algorithm instead
+    /// wraps at each space until the rest of the paragraph fits on one line.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let help: Doc = Doc::reflow("Print the document.\n\n  Wraps at the\n  given width.");
+    /// let options = RenderOptions::new(16).algorithm(LayoutAlgorithm::Oppen);
+    /// assert_eq!(
+    ///     help.render_with(&options),
+    ///     "Print the\ndocument.\n\nWraps at the\ngiven width."
+    /// );
+    /// ```
+    pub fn reflow(text: &str) -> Doc {
+        LeafDoc::reflow(text)
+    }
+
+    /// Text that may wrap after any occurrence of one of `breaks` (e.g., `"/"`, `"::"`, or
+    /// `"-"`), without inserting a space, so long paths and URLs can wrap at sensible places
+    /// instead of overflowing. Wrapped lines start at the current nesting.
+    ///
+    /// With [`LayoutAlgorithm::Oppen`], lines are filled greedily. The default algorithm instead
+    /// wraps at each break point until the rest of the text fits on one line.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let path: Doc = Doc::breakable_text("std::collections::HashMap", &["::"]);
+    /// assert_eq!(path.clone().render(80), "std::collections::HashMap");
+    /// let options = RenderOptions::new(20).algorithm(LayoutAlgorithm::Oppen);
+    /// assert_eq!(path.render_with(&options), "std::collections::\nHashMap");
+    /// ```
+    pub fn breakable_text(s: &str, breaks: &[&str]) -> Doc {
+        LeafDoc::breakable_text(s, breaks)
+    }
+
+    /// Construct a document from `s` with its leading and trailing whitespace removed.
+    pub fn text_trimmed<S: AsRef<str>>(s: S) -> Doc {
+        LeafDoc::text_trimmed(s)
+    }
+
+    /// Construct a document from untrusted `s`, escaping its control characters as Rust does
+    /// (`\n`, `\r`, `\t`, and `\u{1b}` and the like), so that stray line breaks, carriage
+    /// returns, and terminal escapes can't corrupt the indentation or the terminal. Use
+    /// [`Doc::lines`] for text whose line breaks are meant.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::text_sanitized("a\r\nb\x1b[31m");
+    /// assert_eq!(doc.render(80), r"a\r\nb\u{1b}[31m");
+    /// ```
+    pub fn text_sanitized<S: AsRef<str>>(s: S) -> Doc {
+        LeafDoc::text_sanitized(s)
+    }
+
+    /// The `<` document.
+    pub fn lparen() -> Doc {
+        LeafDoc::lparen()
+    }
+
+    /// The `>` document.
+    pub fn rparen() -> Doc {
+        LeafDoc::rparen()
+    }
+
+    /// The `<` document.
+    pub fn langle() -> Doc {
+        LeafDoc::langle()
+    }
+
+    /// The `>` document.
+    pub fn rangle() -> Doc {
+        LeafDoc::rangle()
+    }
+
+    /// The `[` document.
+    pub fn lbracket() -> Doc {
+        LeafDoc::lbracket()
+    }
+
+    /// The `]` document.
+    pub fn rbracket() -> Doc {
+        LeafDoc::rbracket()
+    }
+
+    /// The `{` document.
+    pub fn lbrace() -> Doc {
+        LeafDoc::lbrace()
+    }
+
+    /// The `}` document.
+    pub fn rbrace() -> Doc {
+        LeafDoc::rbrace()
+    }
+
+    /// The `.` document.
+    pub fn dot() -> Doc {
+        LeafDoc::dot()
+    }
+
+    /// The `:` document.
+    pub fn colon() -> Doc {
+        LeafDoc::colon()
+    }
+
+    /// The `;` document.
+    pub fn semicolon() -> Doc {
+        LeafDoc::semicolon()
+    }
+
+    /// The `=` document.
+    pub fn equals() -> Doc {
+        LeafDoc::equals()
+    }
+
+    /// The `->` document.
+    pub fn arrow() -> Doc {
+        LeafDoc::arrow()
+    }
+
+    /// The `=>` document.
+    pub fn fat_arrow() -> Doc {
+        LeafDoc::fat_arrow()
+    }
+}
+
+/// The constructors that take no other document, for documents of any annotation type, as in
+/// `Doc::<A>::text`. Called as `Doc::text`, they build plain [`Doc`]s (see [`Doc::text`]).
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let doc = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("let"));
+/// assert_eq!(doc.render(80), "let");
+/// ```
+pub trait LeafDoc: Sized {
+    /// The empty document.
+    fn nil() -> Self;
+
+    /// A single ASCII space as a document (`" "`).
+    fn space() -> Self;
+
+    /// A single ASCII comma as a document (`","`).
+    fn comma() -> Self;
+
+    /// A hard line break.
+    fn line() -> Self;
+
+    /// A soft line break that becomes a space if the layout fits the given width,
+    /// or a newline otherwise.
+    fn softline() -> Self;
+
+    /// A soft line break that becomes empty if the layout fits, or a newline
+    /// otherwise.
+    fn softline_empty() -> Self;
+
+    /// A line break that becomes empty, rather than a space, when its group is laid out flat.
+    fn line_break() -> Self;
+
+    /// Construct a document from raw text.
+    fn text<S: Into<String>>(str: S) -> Self;
+
+    /// Construct a document from static text, such as a string literal, as [`Doc::text`] does,
+    /// but without copying it: the document, and the layouts made from it, refer to `text`
+    /// directly.
+    fn static_text(text: &'static str) -> Self;
+
+    /// The text of `source` in `range`, as [`Doc::text`], but referencing `source` rather than
+    /// copying it, so formatters over large inputs can build documents from slices of the input.
+    fn text_slice(source: &Rc<str>, range: std::ops::Range<usize>) -> Self;
+
+    /// A single character as a document. Common punctuation reuses the cached constant
+    /// documents instead of allocating.
+    fn char(c: char) -> Self;
+
+    /// A document that never fits, so that an alternative containing it is never chosen while
+    /// there's another. Use it to rule out layouts under some condition:
+    fn fail() -> Self;
+
+    /// Move to the absolute `column`: pad with spaces up to it, or, if the line is already past
+    /// it, break the line and indent the next one to it. Useful for end-of-line comments and
+    /// other columns that line up across the whole output.
+    fn align_to(column: Width) -> Self;
+
+    /// Produce `i` spaces as a document (`" ".repeat(i)`), with fast paths for 0 and 1.
+    fn spaces(i: Width) -> Self;
+
+    /// Text that may contain newlines: each line of `s` becomes a text document, joined with
+    /// [`Doc::line`]s, so that the lines after the first pick up the current nesting and the
+    /// renderer knows where they start. [`Doc::text`] would write the newlines verbatim instead.
+    fn string(s: &str) -> Self;
+
+    /// Split a multi-line string into text documents separated by hard line breaks, stripping
+    /// the indentation common to all non-blank lines (like `indoc!`).
+    fn lines(s: &str) -> Self;
+
+    /// Splice in output that was already laid out elsewhere (e.g., by an external formatter),
+    /// one string per line. The lines are kept verbatim, including their own indentation, and
+    /// the lines after the first start at the current nesting level, so the fragment stays
+    /// indented with the rest of the document.
+    fn pre_rendered(lines: Vec<String>) -> Self;
+
+    /// Split `s` into words and join them with [`Doc::softline`]s, so the text reflows to the
+    /// page width. Any run of whitespace in `s` (including line breaks) becomes a single
+    /// separator, and leading and trailing whitespace is dropped.
+    fn words(s: &str) -> Self;
+
+    /// Prose that wraps to the page width: each paragraph of `text` (paragraphs are separated by
+    /// blank lines) becomes its words joined with [`Doc::softline`]s, as in [`Doc::words`], and
+    /// the paragraphs are separated by a blank line. Useful for doc comments and help text.
+    fn reflow(text: &str) -> Self;
+
+    /// Text that may wrap after any occurrence of one of `breaks` (e.g., `"/"`, `"::"`, or
+    /// `"-"`), without inserting a space, so long paths and URLs can wrap at sensible places
+    /// instead of overflowing. Wrapped lines start at the current nesting.
+    fn breakable_text(s: &str, breaks: &[&str]) -> Self;
+
+    /// Construct a document from `s` with its leading and trailing whitespace removed.
+    fn text_trimmed<S: AsRef<str>>(s: S) -> Self;
+
+    /// Construct a document from untrusted `s`, escaping its control characters as Rust does
+    /// (`\n`, `\r`, `\t`, and `\u{1b}` and the like), so that stray line breaks, carriage
+    /// returns, and terminal escapes can't corrupt the indentation or the terminal. Use
+    /// [`Doc::lines`] for text whose line breaks are meant.
+    fn text_sanitized<S: AsRef<str>>(s: S) -> Self;
+
+    /// The `<` document.
+    fn lparen() -> Self;
+
+    /// The `>` document.
+    fn rparen() -> Self;
+
+    /// The `<` document.
+    fn langle() -> Self;
+
+    /// The `>` document.
+    fn rangle() -> Self;
+
+    /// The `[` document.
+    fn lbracket() -> Self;
+
+    /// The `]` document.
+    fn rbracket() -> Self;
+
+    /// The `{` document.
+    fn lbrace() -> Self;
+
+    /// The `}` document.
+    fn rbrace() -> Self;
+
+    /// The `.` document.
+    fn dot() -> Self;
+
+    /// The `:` document.
+    fn colon() -> Self;
+
+    /// The `;` document.
+    fn semicolon() -> Self;
+
+    /// The `=` document.
+    fn equals() -> Self;
+
+    /// The `->` document.
+    fn arrow() -> Self;
+
+    /// The `=>` document.
+    fn fat_arrow() -> Self;
+}
+
+impl<A: Annotation> LeafDoc for Doc<A> {
+    fn nil() -> Doc<A> {
+        constant(|c| &c.nil)
+    }
+
+    fn space() -> Doc<A> {
+        constant(|c| &c.space)
+    }
+
+    fn comma() -> Doc<A> {
+        constant(|c| &c.comma)
+    }
+
+    fn line() -> Doc<A> {
+        constant(|c| &c.line)
+    }
+
+    fn softline() -> Doc<A> {
+        constant(|c| &c.softline)
+    }
+
+    fn softline_empty() -> Doc<A> {
+        constant(|c| &c.softline_empty)
+    }
+
+    fn line_break() -> Doc<A> {
+        constant(|c| &c.line_break)
+    }
+
+    fn text<S: Into<String>>(str: S) -> Doc<A> {
+        DocInner::Text(Str::Cow(Cow::Owned(str.into()))).into_doc()
+    }
+
+    fn static_text(text: &'static str) -> Doc<A> {
+        DocInner::Text(Str::Cow(Cow::Borrowed(text))).into_doc()
+    }
+
+    fn text_slice(source: &Rc<str>, range: std::ops::Range<usize>) -> Doc<A> {
+        assert!(
+            source.get(range.clone()).is_some(),
+            "{range:?} is not a slice of the source"
+        );
+        DocInner::Text(Str::Slice(source.clone(), range)).into_doc()
+    }
+
+    fn char(c: char) -> Doc<A> {
+        match c {
+            ' ' => Doc::<A>::space(),
+            ',' => Doc::<A>::comma(),
+            '.' => Doc::<A>::dot(),
+            ':' => Doc::<A>::colon(),
+            ';' => Doc::<A>::semicolon(),
+            '=' => Doc::<A>::equals(),
+            '(' => Doc::<A>::lparen(),
+            ')' => Doc::<A>::rparen(),
+            '<' => Doc::<A>::langle(),
+            '>' => Doc::<A>::rangle(),
+            '[' => Doc::<A>::lbracket(),
+            ']' => Doc::<A>::rbracket(),
+            '{' => Doc::<A>::lbrace(),
+            '}' => Doc::<A>::rbrace(),
+            c => Doc::<A>::text(c),
+        }
+    }
+
+    fn fail() -> Doc<A> {
+        DocInner::Fail.into_doc()
+    }
+
+    fn align_to(column: Width) -> Doc<A> {
+        Doc::column(move |current| {
+            if current <= column {
+                Doc::<A>::spaces(column - current)
+            } else {
+                Doc::nesting(move |i| Doc::<A>::line().nest(column - i))
+            }
+        })
+    }
+
+    fn spaces(i: Width) -> Doc<A> {
+        match i {
+            0 => Doc::<A>::nil(),
+            1 => Doc::<A>::space(),
+            n => Doc::<A>::text(" ".repeat(n as usize)),
+        }
+    }
+
+    fn string(s: &str) -> Doc<A> {
+        Doc::vsep(s.split('\n').map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.is_empty() {
+                Doc::<A>::nil()
+            } else {
+                Doc::<A>::text(line)
+            }
+        }))
+    }
+
+    fn lines(s: &str) -> Doc<A> {
+        let s = s.strip_prefix('\n').unwrap_or(s);
+        let mut lines: Vec<&str> = s.lines().collect();
+        if lines.last().is_some_and(|line| line.trim().is_empty()) {
+            lines.pop();
+        }
+        let common = lines
+            .iter()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        Doc::vsep(lines.into_iter().map(|line| match line.get(common..) {
+            Some(rest) if !line.trim().is_empty() => Doc::<A>::text(rest),
+            _ => Doc::<A>::nil(),
+        }))
+    }
+
+    fn pre_rendered(lines: Vec<String>) -> Doc<A> {
+        Doc::vsep(lines.into_iter().map(|line| {
+            if line.is_empty() {
+                Doc::<A>::nil()
+            } else {
+                Doc::<A>::text(line)
+            }
+        }))
+    }
+
+    fn words(s: &str) -> Doc<A> {
+        Doc::concat_balanced(s.split_whitespace().map(Doc::<A>::text), |x, y| {
+            x.concat(Doc::<A>::softline()).concat(y)
+        })
+    }
+
+    fn reflow(text: &str) -> Doc<A> {
+        let mut paragraphs = vec![];
+        let mut paragraph = String::new();
+        for line in text.lines().chain([""]) {
+            if !line.trim().is_empty() {
+                paragraph.push_str(line);
+                paragraph.push(' ');
+            } else if !paragraph.is_empty() {
+                paragraphs.push(Doc::<A>::words(&paragraph));
+                paragraph.clear();
+            }
+        }
+        Doc::paragraphs(paragraphs)
+    }
+
+    fn breakable_text(s: &str, breaks: &[&str]) -> Doc<A> {
+        let break_at = |i: usize| {
+            breaks
+                .iter()
+                .filter(|b| !b.is_empty() && s[i..].starts_with(**b))
+                .map(|b| b.len())
+                .max()
+        };
+        let mut pieces = vec![];
+        let mut start = 0;
+        let mut i = 0;
+        while i < s.len() {
+            match break_at(i) {
+                Some(len) => {
+                    i += len;
+                    // A run of separators (e.g., `//`) stays together.
+                    if i < s.len() && break_at(i).is_none() {
+                        pieces.push(&s[start..i]);
+                        start = i;
+                    }
+                }
+                None => i += s[i..].chars().next().map_or(1, char::len_utf8),
+            }
+        }
+        pieces.push(&s[start..]);
+        Doc::concat_balanced(pieces.into_iter().map(Doc::<A>::text), |x, y| {
+            x.concat(Doc::<A>::softline_empty()).concat(y)
+        })
+    }
+
+    fn text_trimmed<S: AsRef<str>>(s: S) -> Doc<A> {
+        Doc::<A>::text(s.as_ref().trim())
+    }
+
+    fn text_sanitized<S: AsRef<str>>(s: S) -> Doc<A> {
+        let s = s.as_ref();
+        if !s.chars().any(char::is_control) {
+            return Doc::<A>::text(s);
+        }
+        let mut sanitized = String::with_capacity(s.len());
+        for ch in s.chars() {
+            if ch.is_control() {
+                sanitized.extend(ch.escape_debug());
+            } else {
+                sanitized.push(ch);
+            }
+        }
+        Doc::<A>::text(sanitized)
+    }
+
+    fn lparen() -> Doc<A> {
+        constant(|c| &c.lparen)
+    }
+
+    fn rparen() -> Doc<A> {
+        constant(|c| &c.rparen)
+    }
+
+    fn langle() -> Doc<A> {
+        constant(|c| &c.langle)
+    }
+
+    fn rangle() -> Doc<A> {
+        constant(|c| &c.rangle)
+    }
+
+    fn lbracket() -> Doc<A> {
+        constant(|c| &c.lbracket)
+    }
+
+    fn rbracket() -> Doc<A> {
+        constant(|c| &c.rbracket)
+    }
+
+    fn lbrace() -> Doc<A> {
+        constant(|c| &c.lbrace)
+    }
+
+    fn rbrace() -> Doc<A> {
+        constant(|c| &c.rbrace)
+    }
+
+    fn dot() -> Doc<A> {
+        constant(|c| &c.dot)
+    }
+
+    fn colon() -> Doc<A> {
+        constant(|c| &c.colon)
+    }
+
+    fn semicolon() -> Doc<A> {
+        constant(|c| &c.semicolon)
+    }
+
+    fn equals() -> Doc<A> {
+        constant(|c| &c.equals)
+    }
+
+    fn arrow() -> Doc<A> {
+        constant(|c| &c.arrow)
+    }
+
+    fn fat_arrow() -> Doc<A> {
+        constant(|c| &c.fat_arrow)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Layout
+// -------------------------------------------------------------------------------------------------
+
+/// A laid-out line, as passed to the function given to [`Doc::each_line`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderedLine<'a> {
+    /// The line's position in the output, starting at zero.
+    pub index: usize,
+    /// The columns of indentation the line starts with.
+    pub indent: Width,
+    /// The whole line, including its indentation (and line number; see
+    /// [`RenderOptions::line_numbers`]).
+    pub text: &'a str,
+    /// The line after its indentation.
+    pub content: &'a str,
+}
+
+/// A single piece of laid-out output. See [`Doc::layout`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderEvent<A = ()> {
+    /// Text to emit verbatim, borrowed from the document where it was built with [`Doc::static_text`].
+    Text(Cow<'static, str>),
+    /// A line break, followed by `indent` columns of indentation.
+    Newline { indent: Width },
+    /// The start of a region annotated with the given annotation (see [`Doc::annotate`]).
+    /// Regions are closed before each `Newline` and reopened after it.
+    PushAnnotation(A),
+    /// The end of the most recently started annotated region.
+    PopAnnotation,
+}
+
+/// The events of a document as layout decides them. See [`Doc::layout_events`].
+pub struct LayoutEvents<A = ()>(Layouter<A>);
+
+impl<A: Annotation> Iterator for LayoutEvents<A> {
+    type Item = RenderEvent<A>;
+
+    fn next(&mut self) -> Option<RenderEvent<A>> {
+        self.0.next()
+    }
+}
+
+/// A laid-out document (see [`Doc::layout`]), which can be painted into any number of outputs
+/// and queried without laying the document out again.
+///
+/// Iterating over a layout yields its [`RenderEvent`]s, laying the document out only as far as
+/// needed. The other methods finish laying it out first.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let keyword = |s: &str| Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text(s.to_string()));
+/// let text = Doc::<Semantic>::text;
+/// let doc = Doc::sep([keyword("let"), text("x"), text("="), text("1")]);
+/// let layout = doc.layout(5);
+/// assert_eq!(layout.line_count(), 4);
+/// assert_eq!(layout.to_string(), "let\nx\n=\n1");
+/// let theme = Theme::plain().keyword(Style::new().bold(true));
+/// assert_eq!(layout.to_ansi(&theme), "\x1b[1mlet\x1b[0m\nx\n=\n1");
+/// ```
+pub struct Layout<A = ()> {
+    options: RenderOptions,
+    // Lays out the rest of the document, until it's done.
+    layouter: RefCell<Option<Layouter<A>>>,
+    // The events laid out so far, shared with clones once the layout is finished.
+    parts: RefCell<Rc<Parts<A>>>,
+    // The next event to yield when iterating.
+    next: Cursor,
+}
+
+impl<A: Annotation> Layout<A> {
+    /// How many lines the layout takes up.
+    pub fn line_count(&self) -> usize {
+        let parts = self.finish();
+        let breaks: usize = parts
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Newlines { count, .. } => *count,
+                _ => 0,
+            })
+            .sum();
+        breaks + 1
+    }
+
+    // Every event, once the document has been laid out in full.
+    fn finish(&self) -> std::cell::Ref<'_, Rc<Parts<A>>> {
+        if let Some(layouter) = self.layouter.borrow_mut().take() {
+            Rc::make_mut(&mut self.parts.borrow_mut()).extend(layouter);
+        }
+        self.parts.borrow()
+    }
+
+    // Write the layout to `output`, letting `markup` write its annotations.
+    fn paint(&self, output: &mut String, markup: &mut impl Markup<A>) -> std::fmt::Result {
+        paint(&self.finish(), &self.options, output, markup)
+    }
+}
+
+/// Cloning finishes the layout, so that the clones share it instead of copying it.
+impl<A: Annotation> Clone for Layout<A> {
+    fn clone(&self) -> Self {
+        let parts = self.finish().clone();
+        Layout {
+            options: self.options.clone(),
+            layouter: RefCell::new(None),
+            parts: RefCell::new(parts),
+            next: self.next,
+        }
+    }
+}
+
+impl<A: Annotation> std::fmt::Display for Layout<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        self.paint(&mut output, &mut ())?;
+        f.write_str(&output)
+    }
+}
+
+impl<A: Annotation> Iterator for Layout<A> {
+    type Item = RenderEvent<A>;
+
+    fn next(&mut self) -> Option<RenderEvent<A>> {
+        loop {
+            if let Some(event) = self.parts.get_mut().get(&mut self.next) {
+                return Some(event);
+            }
+            let layouter = self.layouter.get_mut();
+            match layouter.as_mut()?.next() {
+                Some(event) => Rc::make_mut(self.parts.get_mut()).extend([event]),
+                None => {
+                    *layouter = None;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Laid-out events, stored compactly for large outputs: the text (other than static text) is
+/// kept in one buffer, and a run of line breaks to the same indentation is kept as one part with
+/// a count.
+#[derive(Clone)]
+struct Parts<A> {
+    text: String,
+    parts: Vec<Part<A>>,
+}
+
+#[derive(Clone)]
+enum Part<A> {
+    /// The text from the end of the previous text part up to this offset in the buffer.
+    Text(usize),
+    /// Text borrowed from the document, which needn't be copied into the buffer.
+    Literal(&'static str),
+    /// `count` line breaks, each followed by `indent` columns of indentation.
+    Newlines {
+        indent: Width,
+        count: usize,
+    },
+    PushAnnotation(A),
+    PopAnnotation,
+}
+
+/// A position in [`Parts`]: the part, how many of its line breaks have been read, and where its
+/// text starts.
+#[derive(Clone, Copy, Default)]
+struct Cursor {
+    part: usize,
+    newlines: usize,
+    text: usize,
+}
+
+impl<A> Default for Parts<A> {
+    fn default() -> Self {
+        Parts {
+            text: String::new(),
+            parts: vec![],
+        }
+    }
+}
+
+impl<A> Extend<RenderEvent<A>> for Parts<A> {
+    fn extend<I: IntoIterator<Item = RenderEvent<A>>>(&mut self, events: I) {
+        for event in events {
+            let part = match event {
+                RenderEvent::Text(Cow::Borrowed(s)) => Part::Literal(s),
+                RenderEvent::Text(s) => {
+                    self.text.push_str(&s);
+                    Part::Text(self.text.len())
+                }
+                RenderEvent::Newline { indent } => match self.parts.last_mut() {
+                    Some(Part::Newlines {
+                        indent: last,
+                        count,
+                    }) if *last == indent => {
+                        *count += 1;
+                        continue;
+                    }
+                    _ => Part::Newlines { indent, count: 1 },
+                },
+                RenderEvent::PushAnnotation(a) => Part::PushAnnotation(a),
+                RenderEvent::PopAnnotation => Part::PopAnnotation,
+            };
+            self.parts.push(part);
+        }
+    }
+}
+
+impl<A: Clone> Parts<A> {
+    /// The event at `cursor`, moving the cursor past it. A cursor at the end of a run of line
+    /// breaks stays there, in case more are added to the run.
+    fn get(&self, cursor: &mut Cursor) -> Option<RenderEvent<A>> {
+        loop {
+            let event = match self.parts.get(cursor.part)? {
+                Part::Newlines { indent, count } if cursor.newlines < *count => {
+                    cursor.newlines += 1;
+                    return Some(RenderEvent::Newline { indent: *indent });
+                }
+                Part::Newlines { .. } if cursor.part + 1 < self.parts.len() => {
+                    cursor.newlines = 0;
+                    cursor.part += 1;
+                    continue;
+                }
+                Part::Newlines { .. } => return None,
+                Part::Text(end) => {
+                    let text = self.text[cursor.text..*end].to_string();
+                    cursor.text = *end;
+                    RenderEvent::Text(Cow::Owned(text))
+                }
+                Part::Literal(s) => RenderEvent::Text(Cow::Borrowed(s)),
+                Part::PushAnnotation(a) => RenderEvent::PushAnnotation(a.clone()),
+                Part::PopAnnotation => RenderEvent::PopAnnotation,
+            };
+            cursor.part += 1;
+            return Some(event);
+        }
+    }
+}
+
+/// An item on the layout work list: a document, the indentation to use for its line breaks,
+/// whether it is being laid out flat (i.e., with line breaks as spaces), and the enclosing
+/// prefixed and narrowed regions.
+type Item<A> = (Width, bool, Doc<A>, Scope);
+
+/// Where a document built by `Doc::with_context` is laid out.
+struct LayoutContext<'a> {
+    config: &'a LayoutConfig,
+    indent: Width,
+    column: Width,
+    scope: &'a Scope,
+    // The render this is part of (see `Layouter::render`).
+    render: u64,
+    // Whether what follows, up to its next line break, fits from the given column.
+    rest_fits: &'a dyn Fn(Width) -> bool,
+}
+
+impl LayoutContext<'_> {
+    // The columns `doc` takes up laid out flat from here.
+    fn flat_width<A: Annotation>(&self, doc: &Doc<A>) -> Width {
+        let item = (self.indent, true, doc.clone(), self.scope.clone());
+        let config = self.config.clone();
+        let mut layouter = Layouter::starting_at(item, config, self.column, self.render).plain();
+        layouter.by_ref().for_each(drop);
+        layouter.cursor.saturating_sub(self.column)
+    }
+
+    // Whether every line of `doc`, laid out from here on its own, fits the page, and the column
+    // its last line ends at, counting annotations' markup as the layout does.
+    fn measure<A: Annotation>(&self, doc: &Doc<A>) -> (bool, Width) {
+        let item = (self.indent, false, doc.clone(), self.scope.clone());
+        let (width, metrics) = (self.config.width, self.config.metrics());
+        let layouter = Layouter::starting_at(item, self.config.clone(), self.column, self.render);
+        let mut closes = vec![];
+        let mut end = self.column;
+        for event in layouter.unbalanced() {
+            match event {
+                RenderEvent::Text(s) => end = end.saturating_add(metrics.width_of(&s)),
+                RenderEvent::Newline { indent } if end <= width => end = indent.max(0),
+                RenderEvent::Newline { .. } => return (false, end),
+                RenderEvent::PushAnnotation(annotation) => {
+                    let (open, close) = markup_width(&annotation);
+                    end = end.saturating_add(open);
+                    closes.push(close);
+                }
+                RenderEvent::PopAnnotation => {
+                    end = end.saturating_add(closes.pop().unwrap_or(0));
+                }
+            }
+        }
+        (end <= width, end)
+    }
+}
+
+// The candidates of a `Doc::conditional_group` in one render, memoized afresh for it, and how each
+// measured at each place it was laid out, as lookahead asks again. Kept for a render at a time, so
+// layouts from past renders don't pile up.
+struct RenderedCandidates<A> {
+    render: u64,
+    docs: Rc<[Doc<A>]>,
+    measured: HashMap<CandidateKey, (bool, Width)>,
+}
+
+// A candidate, by its index, and where it's laid out: the configuration, indentation, starting
+// column, and enclosing regions.
+type CandidateKey = (LayoutConfig, Width, Width, Scope, usize);
+
+impl<A: Annotation> RenderedCandidates<A> {
+    fn new(render: u64, candidates: &[Doc<A>]) -> Self {
+        RenderedCandidates {
+            render,
+            docs: candidates.iter().cloned().map(Doc::memoize).collect(),
+            measured: HashMap::new(),
+        }
+    }
+}
+
+// Whether `docs`, laid out from `column` up to its first line break, fits the page, as lookahead
+// measures it.
+fn rest_fits<A: Annotation>(
+    config: &LayoutConfig,
+    indent: Width,
+    docs: &Option<Rc<Cons<A>>>,
+    column: Width,
+    render: u64,
+) -> bool {
+    let width = config.width;
+    let measure = Measure {
+        config,
+        policy: &*config.policy.policy,
+        width,
+        nesting: indent.min(column),
+        oppen: false,
+        continuation: None,
+        elide: config.elide,
+        metrics: config.metrics(),
+        margin: config.margin(),
+        priority: None,
+        render,
+    };
+    let line = LineFit {
+        width,
+        indent,
+        column,
+        first: true,
+        remaining: width.saturating_sub(column),
+    };
+    fits(&measure, line, docs.clone(), &mut 0)
+}
+
+/// The prefixed, narrowed, and delimited regions an item is in, innermost first.
+#[derive(Clone, Default)]
+struct Scope(Option<Rc<ScopeFrame>>);
+
+struct ScopeFrame {
+    region: Region,
+    parent: Scope,
+    // How many of the regions from here out are `Region::Depth`.
+    depth: Width,
+}
+
+enum Region {
+    /// Lines start with `text`, written at `column`.
+    Prefix { column: Width, text: String },
+    /// The page is this many columns narrower.
+    Narrow(Width),
+    /// One delimiter level deeper.
+    Depth,
+    /// Breaks here have this priority (see [`Doc::break_priority`]).
+    Priority(u8),
+    /// Text here is rewritten (see [`Doc::map_text`]).
+    MapText(TextFn),
+}
+
+impl Scope {
+    fn push(&self, region: Region) -> Scope {
+        let depth = self.depth() + matches!(region, Region::Depth) as Width;
+        Scope(Some(Rc::new(ScopeFrame {
+            region,
+            parent: self.clone(),
+            depth,
+        })))
+    }
+
+    fn regions(&self) -> impl Iterator<Item = &Region> {
+        std::iter::successors(self.0.as_deref(), |frame| frame.parent.0.as_deref())
+            .map(|frame| &frame.region)
+    }
+
+    fn depth(&self) -> Width {
+        self.0.as_ref().map_or(0, |frame| frame.depth)
+    }
+
+    // The priority of the innermost tagged region, if any.
+    fn priority(&self) -> Option<u8> {
+        self.regions().find_map(|region| match region {
+            Region::Priority(priority) => Some(*priority),
+            _ => None,
+        })
+    }
+
+    // `text` as rewritten by the regions it's in, innermost first.
+    fn map_text<'s>(&self, text: &'s str) -> Cow<'s, str> {
+        let mut text = Cow::Borrowed(text);
+        for region in self.regions() {
+            if let Region::MapText(f) = region {
+                text = Cow::Owned(f(&text));
+            }
+        }
+        text
+    }
+
+    fn narrowing(&self) -> Width {
+        self.regions()
+            .map(|region| match region {
+                Region::Narrow(columns) => *columns,
+                Region::Prefix { .. }
+                | Region::Depth
+                | Region::Priority(_)
+                | Region::MapText(_) => 0,
+            })
+            .fold(0, Width::saturating_add)
+    }
+
+    /// The column at which the outermost prefix starts, and the text to write from there to the
+    /// start of a line indented to `indent`; or `None` if there are no prefixes.
+    fn line_start(&self, indent: Width) -> Option<(Width, String)> {
+        let mut prefixes: Vec<(Width, &str)> = self
+            .regions()
+            .filter_map(|region| match region {
+                Region::Prefix { column, text } => Some((*column, text.as_str())),
+                Region::Narrow(_) | Region::Depth | Region::Priority(_) | Region::MapText(_) => {
+                    None
+                }
+            })
+            .collect();
+        prefixes.reverse();
+        let base = prefixes.first()?.0;
+        let mut line = String::new();
+        for (column, text) in prefixes.into_iter().chain([(indent, "")]) {
+            let padding = column.saturating_sub(base).max(0) as usize;
+            if line.len() < padding {
+                line.push_str(&" ".repeat(padding - line.len()));
+            }
+            line.push_str(text);
+        }
+        Some((base, line))
+    }
+}
+
+// Scopes are compared region by region, so that memoized layouts are shared between equal
+// scopes. Text maps are compared by identity.
+impl PartialEq for Scope {
+    fn eq(&self, other: &Scope) -> bool {
+        let (mut a, mut b) = (self, other);
+        loop {
+            match (&a.0, &b.0) {
+                (None, None) => return true,
+                (Some(x), Some(y)) if Rc::ptr_eq(x, y) => return true,
+                (Some(x), Some(y)) if x.region == y.region => (a, b) = (&x.parent, &y.parent),
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl Eq for Scope {}
+
+// Equal scopes are equally deep, and the depth is at hand.
+impl std::hash::Hash for Scope {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.depth().hash(state);
+    }
+}
+
+impl PartialEq for Region {
+    fn eq(&self, other: &Region) -> bool {
+        match (self, other) {
+            (
+                Region::Prefix { column, text },
+                Region::Prefix {
+                    column: other_column,
+                    text: other_text,
+                },
+            ) => column == other_column && text == other_text,
+            (Region::Narrow(a), Region::Narrow(b)) => a == b,
+            (Region::Depth, Region::Depth) => true,
+            (Region::Priority(a), Region::Priority(b)) => a == b,
+            (Region::MapText(a), Region::MapText(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// A persistent list of pending work, shared between the layout loop and `fits` lookahead.
+struct Cons<A> {
+    head: Item<A>,
+    tail: Option<Rc<Cons<A>>>,
+    // The length of the list starting here.
+    len: usize,
+}
+
+fn cons<A>(head: Item<A>, tail: Option<Rc<Cons<A>>>) -> Option<Rc<Cons<A>>> {
+    let len = tail.as_ref().map_or(0, |tail| tail.len) + 1;
+    Some(Rc::new(Cons { head, tail, len }))
+}
+
+// Work lists can be as long as the document is deep, so we unlink them iteratively rather than
+// letting each cell drop the next.
+impl<A> Drop for Cons<A> {
+    fn drop(&mut self) {
+        let mut next = self.tail.take();
+        while let Some(cell) = next {
+            next = match Rc::try_unwrap(cell) {
+                Ok(mut cell) => cell.tail.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+/// Everything a layout depends on besides the document itself.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LayoutConfig {
+    width: Width,
+    algorithm: LayoutAlgorithm,
+    version: LayoutVersion,
+    policy: Policy,
+    // Regions of at most this importance are replaced by their summaries.
+    elide: Option<u8>,
+    ambiguous: AmbiguousWidth,
+    // Tabs in text are expanded to stops this many columns apart, unless it's 0.
+    tab_width: Width,
+    #[cfg(feature = "graphemes")]
+    graphemes: bool,
+}
+
+impl LayoutConfig {
+    fn new(width: Width) -> Self {
+        LayoutConfig {
+            width,
+            algorithm: LayoutAlgorithm::Wadler,
+            version: LayoutVersion::default(),
+            policy: Policy::new(FirstLineFit),
+            elide: None,
+            ambiguous: AmbiguousWidth::Narrow,
+            tab_width: 0,
+            #[cfg(feature = "graphemes")]
+            graphemes: false,
+        }
+    }
+
+    // The least column a line can start at. Lines nested below zero start at column 0, but
+    // `LayoutVersion::V1` measures them from where their nesting says, as it always has.
+    fn margin(&self) -> Width {
+        if self.version >= LayoutVersion::V2 {
+            0
+        } else {
+            Width::MIN
+        }
+    }
+
+    fn metrics(&self) -> TextMetrics {
+        TextMetrics {
+            version: self.version,
+            ambiguous: self.ambiguous,
+            tab_width: self.tab_width,
+            #[cfg(feature = "graphemes")]
+            graphemes: self.graphemes,
+        }
+    }
+}
+
+/// How many columns text takes up.
+#[derive(Clone, Copy)]
+struct TextMetrics {
+    version: LayoutVersion,
+    ambiguous: AmbiguousWidth,
+    tab_width: Width,
+    // Count each grapheme cluster as the one or two cells a terminal draws it in.
+    #[cfg(feature = "graphemes")]
+    graphemes: bool,
+}
+
+impl TextMetrics {
+    // `text`, starting at `column`, with its tabs expanded to spaces if they're to be.
+    fn expand_tabs(self, column: Width, text: &str) -> Cow<'_, str> {
+        if self.tab_width <= 0 || !text.contains('\t') {
+            return Cow::Borrowed(text);
+        }
+        let mut expanded = String::with_capacity(text.len());
+        let mut column = column;
+        for (n, part) in text.split('\t').enumerate() {
+            if n > 0 {
+                let stop = self.tab_width - column.rem_euclid(self.tab_width);
+                expanded.push_str(&" ".repeat(stop as usize));
+                column = column.saturating_add(stop);
+            }
+            expanded.push_str(part);
+            column = column.saturating_add(self.width_of(part));
+        }
+        Cow::Owned(expanded)
+    }
+
+    fn width_of(self, text: &str) -> Width {
+        let width = if self.version < LayoutVersion::V3 || text.is_ascii() {
+            text.len()
+        } else {
+            self.display_width(text)
+        };
+        Width::try_from(width).unwrap_or(Width::MAX)
+    }
+
+    fn display_width(self, text: &str) -> usize {
+        use unicode_width::UnicodeWidthStr;
+
+        let width = match self.ambiguous {
+            AmbiguousWidth::Narrow => UnicodeWidthStr::width,
+            AmbiguousWidth::Wide => UnicodeWidthStr::width_cjk,
+        };
+        #[cfg(feature = "graphemes")]
+        if self.graphemes {
+            use unicode_segmentation::UnicodeSegmentation;
+
+            return text
+                .graphemes(true)
+                .map(|cluster| width(cluster).min(2))
+                .sum();
+        }
+        width(text)
+    }
+}
+
+/// The document to lay out for a region of the given importance: the region itself, or its
+/// summary if regions that unimportant are elided.
+fn elided<'a, A>(
+    elide: Option<u8>,
+    importance: u8,
+    doc: &'a Doc<A>,
+    summary: &'a Doc<A>,
+) -> &'a Doc<A> {
+    if elide.is_some_and(|cutoff| importance <= cutoff) {
+        summary
+    } else {
+        doc
+    }
+}
+
+/// How `fits` measures a candidate.
+struct Measure<'a, A> {
+    config: &'a LayoutConfig,
+    policy: &'a dyn FitPolicy,
+    width: Width,
+    // The nesting the choice is made at (or its column, if less).
+    nesting: Width,
+    // For Oppen-style lookahead, the work that follows the candidate: measuring stops at the
+    // first break opportunity in it.
+    oppen: bool,
+    continuation: Option<Rc<Cons<A>>>,
+    elide: Option<u8>,
+    metrics: TextMetrics,
+    // The least column a line can start at (see `LayoutConfig::margin`).
+    margin: Width,
+    // The priority of the choice's breaks: later breaks of lower priority end the measurement,
+    // as they'll be taken first.
+    priority: Option<u8>,
+    // The render being laid out (see `Layouter::render`).
+    render: u64,
+}
+
+// Whether a break of priority `found` is taken before one of priority `deciding`.
+fn lower_priority(found: Option<u8>, deciding: Option<u8>) -> bool {
+    matches!((found, deciding), (Some(found), Some(deciding)) if found < deciding)
+}
+
+// Where lookahead resumes if the first alternative of a later choice fails: that choice would take
+// its second alternative instead.
+struct Retry<A> {
+    // The work after the choice; reaching it means the first alternative didn't fail.
+    end: Option<Rc<Cons<A>>>,
+    line: LineFit,
+    docs: Option<Rc<Cons<A>>>,
+}
+
+// A non-recursive "does it fit?" that peeks ahead.
+// Returns false as soon as the policy rejects a line, and true once the lookahead has measured
+// far enough.
+// Counts the work items it examines in `steps`.
+fn fits<A: Annotation>(
+    measure: &Measure<A>,
+    mut line: LineFit,
+    mut docs: Option<Rc<Cons<A>>>,
+    steps: &mut usize,
+) -> bool {
+    use DocInner as DI;
+
+    let mut past_candidate = false;
+    let mut retries: Vec<Retry<A>> = vec![];
+
+    while let Some(cell) = docs {
+        *steps += 1;
+        if let (true, Some(continuation)) = (measure.oppen, &measure.continuation) {
+            past_candidate |= Rc::ptr_eq(continuation, &cell);
+        }
+        while let Some(Retry { end: Some(end), .. }) = retries.last() {
+            if !Rc::ptr_eq(end, &cell) {
+                break;
+            }
+            retries.pop();
+        }
+        let (i, flat, doc, scope) = &cell.head;
+        let tail = &cell.tail;
+        docs = match &*doc.0 {
+            DI::Line | DI::LineBreak | DI::Alt(..) if past_candidate && !flat => return true,
+            DI::Alt(..) if !flat && lower_priority(scope.priority(), measure.priority) => {
+                return true
+            }
+            DI::Line | DI::LineBreak if !flat => {
+                let indent = (*i).max(measure.margin);
+                if !measure.policy.measure_past(measure.nesting, indent) {
+                    return true;
+                }
+                line = LineFit {
+                    width: measure.width,
+                    indent,
+                    column: indent,
+                    first: false,
+                    remaining: measure.width.saturating_sub(indent),
+                };
+                tail.clone()
+            }
+            DI::Line => {
+                if !line.advance(1, measure.policy) {
+                    return false;
+                }
+                tail.clone()
+            }
+            DI::Empty | DI::LineBreak => tail.clone(),
+            DI::Fail => match retries.pop() {
+                Some(retry) => {
+                    line = retry.line;
+                    retry.docs
+                }
+                None => return false,
+            },
+            DI::Text(s) => {
+                let s = scope.map_text(s);
+                let s = measure.metrics.expand_tabs(line.column, &s);
+                if !line.advance(measure.metrics.width_of(&s), measure.policy) {
+                    return false;
+                }
+                tail.clone()
+            }
+            DI::Concat(x, y) => cons(
+                (*i, *flat, x.clone(), scope.clone()),
+                cons((*i, *flat, y.clone(), scope.clone()), tail.clone()),
+            ),
+            DI::Nest(_, inner) if *flat => {
+                cons((*i, true, inner.clone(), scope.clone()), tail.clone())
+            }
+            DI::Nest(j, inner) => cons(
+                (i.saturating_add(*j), false, inner.clone(), scope.clone()),
+                tail.clone(),
+            ),
+            // Lookahead assumes the first alternative is taken, unless it plainly fails. If a
+            // broken choice's first alternative fails further in, the choice would take its
+            // second, so measurement goes back to that rather than rejecting the candidate.
+            DI::Alt(first, second) if matches!(&*first.0, DI::Fail) => {
+                cons((*i, *flat, second.clone(), scope.clone()), tail.clone())
+            }
+            DI::Alt(first, second) => {
+                if !flat {
+                    retries.push(Retry {
+                        end: tail.clone(),
+                        line,
+                        docs: cons((*i, false, second.clone(), scope.clone()), tail.clone()),
+                    });
+                }
+                cons((*i, *flat, first.clone(), scope.clone()), tail.clone())
+            }
+            DI::FlatAlt(broken, flat_doc) => {
+                let doc = if *flat { flat_doc } else { broken };
+                cons((*i, *flat, doc.clone(), scope.clone()), tail.clone())
+            }
+            DI::Column(f) => cons((*i, *flat, f(line.column), scope.clone()), tail.clone()),
+            DI::Nesting(f) => cons((*i, *flat, f(*i), scope.clone()), tail.clone()),
+            DI::PageWidth(f) => cons((*i, *flat, f(measure.width), scope.clone()), tail.clone()),
+            DI::Context(f) => {
+                let rest = |column| rest_fits(measure.config, *i, tail, column, measure.render);
+                let context = LayoutContext {
+                    config: measure.config,
+                    indent: *i,
+                    column: line.column,
+                    scope,
+                    render: measure.render,
+                    rest_fits: &rest,
+                };
+                cons((*i, *flat, f(&context), scope.clone()), tail.clone())
+            }
+            DI::Depth(f) => {
+                let inner = f(scope.depth());
+                cons((*i, *flat, inner, scope.push(Region::Depth)), tail.clone())
+            }
+            DI::Memo(inner, _) => cons((*i, *flat, inner.clone(), scope.clone()), tail.clone()),
+            DI::Flat(inner) => cons((*i, true, inner.clone(), scope.clone()), tail.clone()),
+            DI::Expand(_) if *flat => return false,
+            DI::Expand(inner) => cons((*i, false, expanded(inner), scope.clone()), tail.clone()),
+            DI::Prefix(text, inner) => {
+                let t_len = measure.metrics.width_of(text);
+                cons(
+                    (i.saturating_add(t_len), *flat, inner.clone(), scope.clone()),
+                    tail.clone(),
+                )
+            }
+            DI::Narrow(_, inner) => cons((*i, *flat, inner.clone(), scope.clone()), tail.clone()),
+            DI::Hint(width, _) if *flat => {
+                if !line.advance(*width, measure.policy) {
+                    return false;
+                }
+                tail.clone()
+            }
+            DI::Hint(_, inner) => cons((*i, *flat, inner.clone(), scope.clone()), tail.clone()),
+            DI::Priority(priority, inner) => {
+                let scope = scope.push(Region::Priority(*priority));
+                cons((*i, *flat, inner.clone(), scope), tail.clone())
+            }
+            DI::MapText(f, inner) => {
+                let scope = scope.push(Region::MapText(f.clone()));
+                cons((*i, *flat, inner.clone(), scope), tail.clone())
+            }
+            DI::Elide(importance, inner, summary) => {
+                let inner = elided(measure.elide, *importance, inner, summary);
+                cons((*i, *flat, inner.clone(), scope.clone()), tail.clone())
+            }
+            DI::Annotate(annotation, inner) => {
+                let (open, close) = markup_width(annotation);
+                if !line.advance(open, measure.policy) {
+                    return false;
+                }
+                let inner = (*i, *flat, inner.clone(), scope.clone());
+                if close == 0 {
+                    cons(inner, tail.clone())
+                } else {
+                    let end = (
+                        *i,
+                        *flat,
+                        DocInner::EndAnnotation(close).into_doc(),
+                        scope.clone(),
+                    );
+                    cons(inner, cons(end, tail.clone()))
+                }
+            }
+            DI::EndAnnotation(close) => {
+                if !line.advance(*close, measure.policy) {
+                    return false;
+                }
+                tail.clone()
+            }
+        };
+    }
+    true
+}
+
+/// The layout algorithm, run incrementally: each call to `next` advances through the work list
+/// until the next piece of output is decided.
+///
+/// Nothing here recurses on the structure of the document; the work list lives on the heap.
+struct Layouter<A> {
+    config: LayoutConfig,
+    // Identifies the render, for the state documents keep for one render at a time; layouts made
+    // along the way share their render's.
+    render: u64,
+    cursor: Width,
+    docs: Option<Rc<Cons<A>>>,
+    // Cached events from a memoized subtree still being emitted.
+    replay: Option<(Rc<[Replayed<A>]>, usize)>,
+    // The line prefixes that follow a newline that was just emitted.
+    pending: Option<RenderEvent<A>>,
+    // Whether to close the open annotations before each line break and reopen them after it.
+    balanced: bool,
+    // Whether to skip annotations entirely, emitting no events for them.
+    plain: bool,
+    // The importance of each elidable region laid out in full so far.
+    importances: BTreeSet<u8>,
+    // The annotations open in the events emitted so far, outermost first.
+    open: Vec<A>,
+    // Events around a line break that are still to be emitted.
+    queued: VecDeque<RenderEvent<A>>,
+    // Set when laying out an alternative only to find its cost.
+    costing: Option<Costing<A>>,
+    // The flat widths declared by the hints being laid out, innermost last, checked in debug
+    // builds as each hinted document ends.
+    hints: Vec<HintCheck<A>>,
+    metrics: RenderMetrics,
+}
+
+// A hinted document being laid out flat, and the width its hint declared.
+struct HintCheck<A> {
+    // The work list that follows the hinted document.
+    end: Option<Rc<Cons<A>>>,
+    // The column it started at.
+    start: Width,
+    width: Width,
+}
+
+// What laying out an alternative for `LayoutAlgorithm::Optimal` has come across so far.
+struct Costing<A> {
+    // The work list that follows the alternative.
+    end: Option<Rc<Cons<A>>>,
+    // Whether layout has got past the alternative.
+    reached_end: bool,
+    // Whether the alternative laid out a `Doc::fail`.
+    failed: bool,
+}
+
+impl<A: Annotation> Layouter<A> {
+    fn new(doc: Doc<A>, config: LayoutConfig) -> Self {
+        let render = RENDERS.with(|renders| renders.replace(renders.get() + 1));
+        Layouter::starting_at((0, false, doc, Scope::default()), config, 0, render)
+    }
+
+    fn starting_at(item: Item<A>, config: LayoutConfig, cursor: Width, render: u64) -> Self {
+        Layouter {
+            config,
+            render,
+            cursor,
+            docs: cons(item, None),
+            replay: None,
+            pending: None,
+            balanced: true,
+            plain: false,
+            importances: BTreeSet::new(),
+            open: vec![],
+            queued: VecDeque::new(),
+            costing: None,
+            hints: vec![],
+            metrics: RenderMetrics::default(),
+        }
+    }
+
+    /// Leave annotated regions open across line breaks.
+    fn unbalanced(mut self) -> Self {
+        self.balanced = false;
+        self
+    }
+
+    // The cost under `LayoutAlgorithm::Optimal` of laying out `docs`, an alternative followed by
+    // `end`, through the end of the line the alternative ends on. The alternatives within it are
+    // chosen as `LayoutAlgorithm::Wadler` would, measuring from the current column as
+    // `LayoutVersion::V2` does (the optimal layout itself always does).
+    fn cost(&mut self, docs: Option<Rc<Cons<A>>>, end: &Option<Rc<Cons<A>>>) -> u64 {
+        let LayoutAlgorithm::Optimal(costs) = self.config.algorithm else {
+            return 0;
+        };
+        let config = LayoutConfig {
+            algorithm: LayoutAlgorithm::Wadler,
+            version: self.config.version.max(LayoutVersion::V2),
+            ..self.config.clone()
+        };
+        let (width, metrics) = (config.width, config.metrics());
+        let mut sub = Layouter {
+            docs,
+            costing: Some(Costing {
+                end: end.clone(),
+                reached_end: false,
+                failed: false,
+            }),
+            ..Layouter::starting_at(
+                (0, false, Doc::<A>::nil(), Scope::default()),
+                config,
+                self.cursor,
+                self.render,
+            )
+        }
+        .plain();
+        let overflow = |column: Width| column.saturating_sub(width).max(0) as u64 * costs.overflow;
+        let (mut cost, mut column) = (0, self.cursor);
+        loop {
+            match sub.next() {
+                None => {
+                    cost += overflow(column);
+                    break;
+                }
+                Some(RenderEvent::Text(s)) => column = column.saturating_add(metrics.width_of(&s)),
+                Some(RenderEvent::Newline { indent }) => {
+                    cost += overflow(column);
+                    if sub
+                        .costing
+                        .as_ref()
+                        .is_some_and(|costing| costing.reached_end)
+                    {
+                        break;
+                    }
+                    cost += costs.line;
+                    column = indent;
+                }
+                Some(RenderEvent::PushAnnotation(_) | RenderEvent::PopAnnotation) => (),
+            }
+        }
+        self.metrics.fits_probes += 1 + sub.metrics.fits_probes;
+        self.metrics.lookahead_steps += sub.metrics.steps + sub.metrics.lookahead_steps;
+        match sub.costing {
+            Some(costing) if costing.failed => u64::MAX,
+            _ => cost,
+        }
+    }
+
+    /// Emit no annotation events, for output that has no use for them.
+    fn plain(mut self) -> Self {
+        self.balanced = false;
+        self.plain = true;
+        self
+    }
+
+    // Check the hints of the hinted documents that have just been laid out against the columns
+    // they took, markup included, as `fits` takes the hint in place of all of them.
+    fn check_hints(&mut self) {
+        while let Some(hint) = self.hints.last() {
+            let ended = match (&hint.end, &self.docs) {
+                (Some(end), Some(docs)) => Rc::ptr_eq(end, docs),
+                (end, docs) => end.is_none() && docs.is_none(),
+            };
+            if !ended {
+                break;
+            }
+            assert_eq!(
+                self.cursor.saturating_sub(hint.start),
+                hint.width,
+                "`group_break_hint` declared the wrong flat width"
+            );
+            self.hints.pop();
+        }
+    }
+
+    fn next_replayed(&mut self) -> Option<Replayed<A>> {
+        let (events, next) = self.replay.as_mut()?;
+        let event = events.get(*next).cloned();
+        *next += 1;
+        if event.is_none() {
+            self.replay = None;
+        }
+        event
+    }
+}
+
+impl<A: Annotation> Layouter<A> {
+    // The next event, with annotated regions left open across line breaks.
+    fn next_unbalanced(&mut self) -> Option<RenderEvent<A>> {
+        use DocInner as DI;
+
+        loop {
+            if let Some(event) = self.pending.take() {
+                return Some(event);
+            }
+            if let Some((event, pending)) = self.next_replayed() {
+                match event {
+                    RenderEvent::PushAnnotation(_) | RenderEvent::PopAnnotation if self.plain => {
+                        continue
+                    }
+                    _ => {
+                        self.pending = pending;
+                        return Some(event);
+                    }
+                }
+            }
+            self.check_hints();
+            let cell = self.docs.take()?;
+            self.metrics.steps += 1;
+            if let Some(costing) = &mut self.costing {
+                let end = costing.end.as_ref();
+                costing.reached_end |= end.is_some_and(|end| Rc::ptr_eq(end, &cell));
+                let failed = matches!(&*cell.head.2 .0, DI::Fail);
+                costing.failed |= failed && !costing.reached_end;
+            }
+            let (indent, flat, doc, scope) = &cell.head;
+            let tail = &cell.tail;
+            let mut event = None;
+            let docs = match &*doc.0 {
+                DI::Empty | DI::Fail => tail.clone(),
+                DI::Text(s) => {
+                    let metrics = self.config.metrics();
+                    let mapped = scope.map_text(s);
+                    let expanded = metrics.expand_tabs(self.cursor, &mapped);
+                    self.cursor = self.cursor.saturating_add(metrics.width_of(&expanded));
+                    // Unchanged text is shared with the document rather than copied.
+                    let text = match (&mapped, expanded) {
+                        (Cow::Borrowed(_), Cow::Borrowed(_)) => s.to_cow(),
+                        (_, expanded) => Cow::Owned(expanded.into_owned()),
+                    };
+                    event = Some(RenderEvent::Text(text));
+                    tail.clone()
+                }
+                DI::Concat(x, y) => cons(
+                    (*indent, *flat, x.clone(), scope.clone()),
+                    cons((*indent, *flat, y.clone(), scope.clone()), tail.clone()),
+                ),
+                DI::Nest(_, inner) if *flat => {
+                    cons((*indent, true, inner.clone(), scope.clone()), tail.clone())
+                }
+                DI::Nest(j, inner) => cons(
+                    (
+                        indent.saturating_add(*j),
+                        false,
+                        inner.clone(),
+                        scope.clone(),
+                    ),
+                    tail.clone(),
+                ),
+                DI::Line if *flat => {
+                    event = Some(RenderEvent::Text(Cow::Borrowed(" ")));
+                    self.cursor = self.cursor.saturating_add(1);
+                    tail.clone()
+                }
+                DI::LineBreak if *flat => tail.clone(),
+                // Dedenting past the left margin stops at it.
+                DI::Line | DI::LineBreak => {
+                    let margin = self.config.margin();
+                    match scope.line_start((*indent).max(margin)) {
+                        Some((base, line)) => {
+                            let base = base.max(margin);
+                            event = Some(RenderEvent::Newline {
+                                indent: base.max(0),
+                            });
+                            let width = self.config.metrics().width_of(&line);
+                            self.cursor = base.saturating_add(width);
+                            self.pending = Some(RenderEvent::Text(Cow::Owned(line)));
+                            tail.clone()
+                        }
+                        None => {
+                            let indent = (*indent).max(margin);
+                            event = Some(RenderEvent::Newline {
+                                indent: indent.max(0),
+                            });
+                            self.cursor = indent;
+                            tail.clone()
+                        }
+                    }
+                }
+                // Flat layouts take the first alternative, unless it plainly fails.
+                DI::Alt(first, second) if *flat => {
+                    let doc = if matches!(&*first.0, DI::Fail) {
+                        second
+                    } else {
+                        first
+                    };
+                    cons((*indent, true, doc.clone(), scope.clone()), tail.clone())
+                }
+                DI::FlatAlt(_, flat_doc) if *flat => cons(
+                    (*indent, true, flat_doc.clone(), scope.clone()),
+                    tail.clone(),
+                ),
+                DI::FlatAlt(broken, _) => cons(
+                    (*indent, false, broken.clone(), scope.clone()),
+                    tail.clone(),
+                ),
+                DI::Alt(first, second)
+                    if matches!(self.config.algorithm, LayoutAlgorithm::Optimal(_)) =>
+                {
+                    let first = cons((*indent, false, first.clone(), scope.clone()), tail.clone());
+                    let second = cons(
+                        (*indent, false, second.clone(), scope.clone()),
+                        tail.clone(),
+                    );
+                    if self.cost(first.clone(), tail) <= self.cost(second.clone(), tail) {
+                        first
+                    } else {
+                        second
+                    }
+                }
+                DI::Alt(first, second) => {
+                    let first = cons((*indent, false, first.clone(), scope.clone()), tail.clone());
+                    let width = self.config.width;
+                    let oppen = self.config.algorithm == LayoutAlgorithm::Oppen;
+                    let measure = Measure {
+                        config: &self.config,
+                        policy: &*self.config.policy.policy,
+                        width,
+                        nesting: (*indent).min(self.cursor),
+                        oppen,
+                        continuation: tail.clone(),
+                        elide: self.config.elide,
+                        metrics: self.config.metrics(),
+                        margin: self.config.margin(),
+                        priority: scope.priority(),
+                        render: self.render,
+                    };
+                    // Prioritized breaks are new, so measuring them from the cursor changes no
+                    // existing layout.
+                    let from_cursor = oppen
+                        || self.config.version >= LayoutVersion::V2
+                        || measure.priority.is_some();
+                    let line = LineFit {
+                        width,
+                        indent: *indent,
+                        column: self.cursor,
+                        first: true,
+                        remaining: if from_cursor {
+                            width.saturating_sub(self.cursor)
+                        } else {
+                            width.saturating_sub(scope.narrowing())
+                        },
+                    };
+                    self.metrics.fits_probes += 1;
+                    let steps = &mut self.metrics.lookahead_steps;
+                    if fits(&measure, line, first.clone(), steps) {
+                        first
+                    } else {
+                        cons(
+                            (*indent, false, second.clone(), scope.clone()),
+                            tail.clone(),
+                        )
+                    }
+                }
+                DI::Column(f) => cons(
+                    (*indent, *flat, f(self.cursor), scope.clone()),
+                    tail.clone(),
+                ),
+                DI::Nesting(f) => cons((*indent, *flat, f(*indent), scope.clone()), tail.clone()),
+                DI::PageWidth(f) => cons(
+                    (*indent, *flat, f(self.config.width), scope.clone()),
+                    tail.clone(),
+                ),
+                DI::Context(f) => {
+                    let (config, render) = (&self.config, self.render);
+                    let rest = |column| rest_fits(config, *indent, tail, column, render);
+                    let context = LayoutContext {
+                        config,
+                        indent: *indent,
+                        column: self.cursor,
+                        scope,
+                        render,
+                        rest_fits: &rest,
+                    };
+                    cons((*indent, *flat, f(&context), scope.clone()), tail.clone())
+                }
+                DI::Depth(f) => {
+                    let inner = f(scope.depth());
+                    let item = (*indent, *flat, inner, scope.push(Region::Depth));
+                    cons(item, tail.clone())
+                }
+                DI::Memo(inner, cache) => {
+                    let key = (
+                        self.config.clone(),
+                        *indent,
+                        self.cursor,
+                        *flat,
+                        scope.clone(),
+                    );
+                    let cached = cache.borrow_mut().get(&key).cloned();
+                    let (events, end, importances) = cached.unwrap_or_else(|| {
+                        let item = (*indent, *flat, inner.clone(), scope.clone());
+                        let config = self.config.clone();
+                        let mut sub = Layouter::starting_at(item, config, self.cursor, self.render);
+                        // Recorded unbalanced, to be balanced as they're replayed.
+                        let events: Rc<[Replayed<A>]> = std::iter::from_fn(|| {
+                            Some((sub.next_unbalanced()?, sub.pending.take()))
+                        })
+                        .collect();
+                        self.metrics.add(&sub.metrics);
+                        let laid_out = (events, sub.cursor, sub.importances);
+                        cache.borrow_mut().insert(key, laid_out.clone());
+                        laid_out
+                    });
+                    self.replay = Some((events, 0));
+                    self.cursor = end;
+                    self.importances.extend(importances);
+                    tail.clone()
+                }
+                // Flattened anyway, e.g., by `Doc::pad_right`.
+                DI::Expand(inner) if *flat => {
+                    cons((*indent, true, inner.clone(), scope.clone()), tail.clone())
+                }
+                DI::Expand(inner) => cons(
+                    (*indent, false, expanded(inner), scope.clone()),
+                    tail.clone(),
+                ),
+                DI::Flat(inner) => {
+                    cons((*indent, true, inner.clone(), scope.clone()), tail.clone())
+                }
+                DI::Prefix(text, inner) => {
+                    let t_len = self.config.metrics().width_of(text);
+                    let region = Region::Prefix {
+                        column: *indent,
+                        text: text.clone(),
+                    };
+                    let item = (
+                        indent.saturating_add(t_len),
+                        *flat,
+                        inner.clone(),
+                        scope.push(region),
+                    );
+                    cons(item, tail.clone())
+                }
+                DI::Elide(importance, inner, summary) => {
+                    let inner = elided(self.config.elide, *importance, inner, summary);
+                    if !std::ptr::eq(inner, summary) {
+                        self.importances.insert(*importance);
+                    }
+                    cons((*indent, *flat, inner.clone(), scope.clone()), tail.clone())
+                }
+                // Hints describe documents before elision.
+                DI::Hint(width, inner) => {
+                    if cfg!(debug_assertions) && *flat && self.config.elide.is_none() {
+                        self.hints.push(HintCheck {
+                            end: tail.clone(),
+                            start: self.cursor,
+                            width: *width,
+                        });
+                    }
+                    cons((*indent, *flat, inner.clone(), scope.clone()), tail.clone())
+                }
+                DI::Narrow(columns, inner) => {
+                    let item = (
+                        *indent,
+                        *flat,
+                        inner.clone(),
+                        scope.push(Region::Narrow(*columns)),
+                    );
+                    cons(item, tail.clone())
+                }
+                DI::Priority(priority, inner) => {
+                    let scope = scope.push(Region::Priority(*priority));
+                    cons((*indent, *flat, inner.clone(), scope), tail.clone())
+                }
+                DI::MapText(f, inner) => {
+                    let scope = scope.push(Region::MapText(f.clone()));
+                    cons((*indent, *flat, inner.clone(), scope), tail.clone())
+                }
+                DI::Annotate(annotation, inner) => {
+                    let (open, close) = markup_width(annotation);
+                    self.cursor = self.cursor.saturating_add(open);
+                    let inner = (*indent, *flat, inner.clone(), scope.clone());
+                    if self.plain && close == 0 {
+                        cons(inner, tail.clone())
+                    } else {
+                        if !self.plain {
+                            event = Some(RenderEvent::PushAnnotation(annotation.clone()));
+                        }
+                        let end = DocInner::EndAnnotation(close).into_doc();
+                        cons(
+                            inner,
+                            cons((*indent, *flat, end, scope.clone()), tail.clone()),
+                        )
+                    }
+                }
+                DI::EndAnnotation(close) => {
+                    self.cursor = self.cursor.saturating_add(*close);
+                    if !self.plain {
+                        event = Some(RenderEvent::PopAnnotation);
+                    }
+                    tail.clone()
+                }
+            };
+            let len = docs.as_ref().map_or(0, |docs| docs.len);
+            self.metrics.peak_work_list = self.metrics.peak_work_list.max(len);
+            self.docs = docs;
+            if event.is_some() {
+                return event;
+            }
+        }
+    }
+}
+
+// Each line's annotations are balanced: the annotations open at a line break are closed before
+// it and reopened after it (and after any line prefix), so back ends never see a region span a
+// break. Memoized subtrees record their events unbalanced, and are balanced here as they're
+// replayed.
+impl<A: Annotation> Iterator for Layouter<A> {
+    type Item = RenderEvent<A>;
+
+    fn next(&mut self) -> Option<RenderEvent<A>> {
+        if let Some(event) = self.queued.pop_front() {
+            return Some(event);
+        }
+        let event = self.next_unbalanced()?;
+        if !self.balanced {
+            return Some(event);
+        }
+        match &event {
+            RenderEvent::PushAnnotation(annotation) => self.open.push(annotation.clone()),
+            RenderEvent::PopAnnotation => {
+                self.open.pop();
+            }
+            RenderEvent::Newline { .. } if !self.open.is_empty() => {
+                self.queued
+                    .extend(self.open.iter().map(|_| RenderEvent::PopAnnotation));
+                self.queued.push_back(event);
+                self.queued.extend(self.pending.take());
+                let reopen = self.open.iter().cloned().map(RenderEvent::PushAnnotation);
+                self.queued.extend(reopen);
+                return self.queued.pop_front();
+            }
+            RenderEvent::Text(_) | RenderEvent::Newline { .. } => (),
+        }
+        Some(event)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Annotations
+// -------------------------------------------------------------------------------------------------
+
+/// A type that documents can be annotated with (see [`Doc::annotate`]).
+///
+/// Annotations take up no room by default. A back end that expands an annotation into visible
+/// markup (e.g., Markdown's `**bold**`) should report the width of that markup here, so layout
+/// leaves room for it. Layout charges `extra_width_broken` where the annotated region starts, and
+/// the rest of `extra_width_flat` where it ends.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// #[derive(Clone)]
+/// struct Bold;
+///
+/// impl Annotation for Bold {
+///     // `**` on either side.
+///     fn extra_width_flat(&self) -> Width {
+///         4
+///     }
+///     // The closing `**` ends up on a later line.
+///     fn extra_width_broken(&self) -> Width {
+///         2
+///     }
+/// }
+///
+/// let doc = Doc::sep([
+///     Doc::<Bold>::text("very"),
+///     Doc::annotate(Bold, Doc::<Bold>::text("important")),
+/// ]);
+/// assert_eq!(doc.clone().render(18), "very important");
+/// assert_eq!(doc.render(17), "very\nimportant");
+/// ```
+pub trait Annotation: Clone + 'static {
+    /// The columns this annotation's markup adds when the annotated region is laid out flat.
+    fn extra_width_flat(&self) -> Width {
+        0
+    }
+
+    /// The columns this annotation's markup adds to the line the annotated region starts on when
+    /// the region is laid out with line breaks.
+    fn extra_width_broken(&self) -> Width {
+        0
+    }
+}
+
+impl Annotation for () {}
+
+/// Class names, for [`Doc::render_html`].
+impl Annotation for String {}
+
+/// Class names, for [`Doc::render_html`].
+impl Annotation for &'static str {}
+
+/// Byte ranges in the source a document was printed from, for [`Doc::render_with_map`].
+impl Annotation for std::ops::Range<usize> {}
+
+/// Ids of the AST nodes a document was printed from, for [`Doc::render_with_map`].
+impl Annotation for usize {}
+
+// The columns `annotation`'s markup adds where a region starts, and where it ends.
+fn markup_width<A: Annotation>(annotation: &A) -> (Width, Width) {
+    let open = annotation.extra_width_broken();
+    (
+        open,
+        annotation.extra_width_flat().saturating_sub(open).max(0),
+    )
+}
+
+// -------------------------------------------------------------------------------------------------
+// Render Options
+// -------------------------------------------------------------------------------------------------
+
+/// Options controlling how a `Doc` is rendered. See [`Doc::render_with`].
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let doc: Doc = Doc::text("{")
+///     .concat(Doc::line().concat(Doc::text("x")).nest(4))
+///     .concat(Doc::line())
+///     .concat(Doc::text("}"));
+///
+/// let options = RenderOptions::new(80).indent("\t", 4);
+/// assert_eq!(doc.render_with(&options), "{\n\tx\n}");
+/// ```
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    width: Width,
+    indent_unit: String,
+    indent_width: Width,
+    trim_trailing_whitespace: bool,
+    line_numbers: usize,
+    algorithm: LayoutAlgorithm,
+    version: LayoutVersion,
+    policy: Policy,
+    color: ColorChoice,
+    ambiguous: AmbiguousWidth,
+    tab_width: Width,
+    #[cfg(feature = "graphemes")]
+    graphemes: bool,
+}
+
+impl RenderOptions {
+    /// Default options for rendering at the given maximum line `width`.
+    pub fn new(width: Width) -> Self {
+        RenderOptions {
+            width,
+            indent_unit: " ".to_string(),
+            indent_width: 1,
+            trim_trailing_whitespace: false,
+            line_numbers: 0,
+            algorithm: LayoutAlgorithm::Wadler,
+            version: LayoutVersion::default(),
+            policy: Policy::new(FirstLineFit),
+            color: ColorChoice::default(),
+            ambiguous: AmbiguousWidth::Narrow,
+            tab_width: 0,
+            #[cfg(feature = "graphemes")]
+            graphemes: false,
+        }
+    }
+
+    /// Emit indentation using `unit` for every `columns` columns of nesting (e.g., `"\t"` for
+    /// every 4 columns, or `"|   "` for indentation guides). Any remainder is padded with spaces.
+    ///
+    /// Layout still measures nesting in columns, so `unit` only changes what is written after
+    /// each line break.
+    pub fn indent<S: Into<String>>(mut self, unit: S, columns: Width) -> Self {
+        self.indent_unit = unit.into();
+        self.indent_width = columns.max(1);
+        self
+    }
+
+    /// Draw a vertical guide (`│`) at every level of indentation, levels being `columns` wide, to
+    /// make deeply nested output easier to follow. Shorthand for `indent("│   ", 4)` and so on.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let inner: Doc = Doc::text("{").concat(Doc::line().concat(Doc::text("x")).nest(2));
+    /// let doc = Doc::text("{").concat(Doc::line().concat(inner).nest(2));
+    /// let options = RenderOptions::new(80).indent_guides(2);
+    /// assert_eq!(doc.render_with(&options), "{\n│ {\n│ │ x");
+    /// ```
+    pub fn indent_guides(self, columns: Width) -> Self {
+        let columns = columns.max(1);
+        let padding = " ".repeat(columns as usize - 1);
+        self.indent(format!("│{}", padding), columns)
+    }
+
+    /// Remove spaces and tabs immediately before each emitted newline. These typically come from
+    /// a `space()` preceding a break chosen by an `alt`, or from indentation on blank lines.
+    pub fn trim_trailing_whitespace(mut self, trim: bool) -> Self {
+        self.trim_trailing_whitespace = trim;
+        self
+    }
+
+    /// Start each line of output with its number, counting from 1, right-aligned in a gutter
+    /// `digits` wide and followed by `" | "` (e.g., `" 9 | "` when `digits` is 2), as error
+    /// messages that quote code do. Numbers too long for the gutter widen it on their own lines.
+    /// The gutter doesn't count toward the width. Defaults to 0, which leaves lines unnumbered.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::vsep(["let x = 1;", "x + 1"].map(Doc::text));
+    /// let options = RenderOptions::new(80).line_numbers(3);
+    /// assert_eq!(doc.render_with(&options), "  1 | let x = 1;\n  2 | x + 1");
+    /// ```
+    pub fn line_numbers(mut self, digits: usize) -> Self {
+        self.line_numbers = digits;
+        self
+    }
+
+    /// Look further ahead when choosing between alternatives (Leijen's `renderSmart`).
+    ///
+    /// By default an alternative is taken if its output up to the next line break fits. With
+    /// smart lookahead, the lines that follow must fit too, for as long as they are indented
+    /// deeper than where the choice was made. This avoids layouts such as a hugged bracket whose
+    /// first line fits but whose nested contents then overflow.
+    ///
+    /// Shorthand for `fit_policy(SmartFit)` (or `fit_policy(FirstLineFit)` when `false`).
+    pub fn smart_lookahead(self, smart: bool) -> Self {
+        if smart {
+            self.fit_policy(SmartFit)
+        } else {
+            self.fit_policy(FirstLineFit)
+        }
+    }
+
+    /// Decide whether alternatives fit using `policy` rather than the default [`FirstLineFit`].
+    pub fn fit_policy<P: FitPolicy + 'static>(mut self, policy: P) -> Self {
+        self.policy = Policy::new(policy);
+        self
+    }
+
+    /// Choose the algorithm used to decide between alternatives. See [`LayoutAlgorithm`].
+    pub fn algorithm(mut self, algorithm: LayoutAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Lay out documents as the given [`LayoutVersion`] does.
+    pub fn layout_version(mut self, version: LayoutVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Expand tab characters in text to spaces, up to the next multiple of `columns` (counting
+    /// from the left margin), and measure them that way when fitting. Defaults to 0, which leaves
+    /// tabs as they are, measured as one column.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::vsep(["a\tb", "abc\tb"].map(Doc::text));
+    /// let options = RenderOptions::new(80).tab_width(4);
+    /// assert_eq!(doc.render_with(&options), "a   b\nabc b");
+    /// ```
+    pub fn tab_width(mut self, columns: Width) -> Self {
+        self.tab_width = columns.max(0);
+        self
+    }
+
+    /// Count characters of ambiguous East Asian width as [`AmbiguousWidth::Wide`] to match a
+    /// terminal that draws them two columns wide, as CJK locales usually do. Defaults to
+    /// [`AmbiguousWidth::Narrow`]. Only affects [`LayoutVersion::V3`] and later, which measure
+    /// display width.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::sep(["α → β", "abcd"].map(Doc::text));
+    /// let options = RenderOptions::new(10).layout_version(LayoutVersion::V3);
+    /// assert_eq!(doc.clone().render_with(&options), "α → β abcd");
+    /// let options = options.ambiguous_width(AmbiguousWidth::Wide);
+    /// assert_eq!(doc.render_with(&options), "α → β\nabcd");
+    /// ```
+    pub fn ambiguous_width(mut self, width: AmbiguousWidth) -> Self {
+        self.ambiguous = width;
+        self
+    }
+
+    /// Measure text one grapheme cluster (a user-perceived character) at a time, each taking up
+    /// the one or two cells a terminal draws it in, rather than summing the widths of its
+    /// characters. This counts emoji sequences, flags, and clusters with combining marks as
+    /// terminals do. Only affects [`LayoutVersion::V3`] and later, which measure display width.
+    ///
+    /// Requires the `graphemes` feature.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // A heart on fire: a heart, a joiner, and a fire, drawn as one emoji two cells wide.
+    /// let emoji = "\u{2764}\u{200d}\u{1f525}";
+    /// let doc: Doc = Doc::sep([emoji, "abcdefg"].map(Doc::text));
+    /// let options = RenderOptions::new(10).layout_version(LayoutVersion::V3);
+    /// assert_eq!(doc.clone().render_with(&options), format!("{emoji}\nabcdefg"));
+    /// let options = options.grapheme_clusters(true);
+    /// assert_eq!(doc.render_with(&options), format!("{emoji} abcdefg"));
+    /// ```
+    #[cfg(feature = "graphemes")]
+    pub fn grapheme_clusters(mut self, graphemes: bool) -> Self {
+        self.graphemes = graphemes;
+        self
+    }
+
+    /// Whether [`Doc::render_ansi_with`] emits escape codes. Defaults to
+    /// [`ColorChoice::Always`]; use [`ColorChoice::Auto`] to leave them out of piped output.
+    pub fn color(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    // Append what starts line `index` (counting from 0) before its indentation: its number, if
+    // lines are numbered.
+    fn write_line_start(&self, index: usize, output: &mut String) {
+        if self.line_numbers > 0 {
+            let digits = self.line_numbers;
+            output.push_str(&format!("{:>digits$} | ", index + 1));
+        }
+    }
+
+    // Append the indentation for `columns` columns of nesting.
+    fn write_indent(&self, columns: Width, output: &mut String) {
+        for _n in 0..(columns / self.indent_width) {
+            output.push_str(&self.indent_unit);
+        }
+        for _n in 0..(columns % self.indent_width) {
+            output.push(' ');
+        }
+    }
+
+    fn config(&self) -> LayoutConfig {
+        LayoutConfig {
+            width: self.width,
+            algorithm: self.algorithm,
+            version: self.version,
+            policy: self.policy.clone(),
+            elide: None,
+            ambiguous: self.ambiguous,
+            tab_width: self.tab_width,
+            #[cfg(feature = "graphemes")]
+            graphemes: self.graphemes,
+        }
+    }
+}
+
+/// Where the separators of a broken list go. See [`Doc::enclose_sep`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SeparatorPlacement {
+    /// At the end of each entry but the last (`a,\nb`).
+    #[default]
+    Trailing,
+    /// At the start of each line but the first (`a\n, b`).
+    Leading,
+}
+
+/// Where the opening delimiter of a block goes. See [`BlockStyle`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BraceStyle {
+    /// On the line the block starts on (`if x {`).
+    #[default]
+    KAndR,
+    /// On a line of its own (`if x\n{`).
+    Allman,
+}
+
+/// How [`Doc::block_with`] lays out a block.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let style = BlockStyle::new().indent(2).braces(BraceStyle::Allman);
+/// let body: Doc = Doc::text("return 0;");
+/// let doc = Doc::text("int main()").concat(body.block_with(Doc::lbrace(), Doc::rbrace(), &style));
+/// assert_eq!(doc.render(80), "int main()\n{\n  return 0;\n}");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlockStyle {
+    indent: Width,
+    braces: BraceStyle,
+    padding: bool,
+}
+
+impl Default for BlockStyle {
+    fn default() -> Self {
+        BlockStyle::new()
+    }
+}
+
+impl BlockStyle {
+    /// The style of [`Doc::block`]: a 4-space indent, K&R braces, and no blank lines.
+    pub fn new() -> Self {
+        BlockStyle {
+            indent: 4,
+            braces: BraceStyle::KAndR,
+            padding: false,
+        }
+    }
+
+    /// Indent the body by `columns`.
+    pub fn indent(mut self, columns: Width) -> Self {
+        self.indent = columns;
+        self
+    }
+
+    /// Place the opening delimiter as `braces` says.
+    pub fn braces(mut self, braces: BraceStyle) -> Self {
+        self.braces = braces;
+        self
+    }
+
+    /// Surround the body with blank lines.
+    pub fn padding(mut self, padding: bool) -> Self {
+        self.padding = padding;
+        self
+    }
+}
+
+/// The algorithm used to decide between the alternatives of a document (e.g., whether a group
+/// breaks). See [`RenderOptions::algorithm`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum LayoutAlgorithm {
+    /// Wadler/Leijen: take an alternative if the output it starts fits up to the next hard line
+    /// break, assuming every soft break along the way stays flat.
+    #[default]
+    Wadler,
+    /// Oppen-style bounded lookahead, as in rustc's `pp.rs`: take an alternative if it, and
+    /// whatever follows it up to the next break opportunity, fits in what remains of the current
+    /// line. Decisions never measure past a break opportunity, so rendering time stays linear in
+    /// the size of the document.
+    Oppen,
+    /// Take whichever alternative costs less (the first, on a tie), as weighed by
+    /// [`LayoutCosts`]: for each, lay out it and what follows through the end of the line it
+    /// ends on, and add up the overflow and line breaks. Unlike the other algorithms, this can
+    /// prefer a layout that overflows a little to one that overflows more, or break a line early
+    /// to keep what follows from overflowing.
+    ///
+    /// The alternatives within the one being weighed are chosen as [`LayoutAlgorithm::Wadler`]
+    /// would, so this improves on first-fit layouts rather than searching for the cheapest
+    /// layout overall, and its cost is roughly that of Wadler layout times the nesting depth.
+    Optimal(LayoutCosts),
+}
+
+/// How [`LayoutAlgorithm::Optimal`] weighs layouts: each column a line extends past the page
+/// width costs `overflow`, and each line break costs `line`.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let call: Doc = Doc::text("f").concat(Doc::tupled([Doc::text("an_overly_long_argument")]));
+/// // Wadler layout breaks the arguments, even though that overflows further.
+/// assert_eq!(call.clone().render(16), "f(\n    an_overly_long_argument\n)");
+/// let optimal = RenderOptions::new(16).algorithm(LayoutAlgorithm::Optimal(LayoutCosts::new()));
+/// assert_eq!(call.render_with(&optimal), "f(an_overly_long_argument)");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LayoutCosts {
+    overflow: u64,
+    line: u64,
+}
+
+impl Default for LayoutCosts {
+    fn default() -> Self {
+        LayoutCosts::new()
+    }
+}
+
+impl LayoutCosts {
+    /// Overflowing by a column costs 100, and a line break costs 1: avoid overflow first, then
+    /// use as few lines as possible.
+    pub fn new() -> Self {
+        LayoutCosts {
+            overflow: 100,
+            line: 1,
+        }
+    }
+
+    /// The cost of each column past the page width.
+    pub fn overflow(mut self, cost: u64) -> Self {
+        self.overflow = cost;
+        self
+    }
+
+    /// The cost of each line break.
+    pub fn line(mut self, cost: u64) -> Self {
+        self.line = cost;
+        self
+    }
+}
+
+/// A version of the layout rules. See [`RenderOptions::layout_version`].
+///
+/// Rendering a document with the same options and layout version produces byte-identical output
+/// in every release with the same major version (for `0.x` releases, the same minor version);
+/// the golden outputs in this crate's test suite check this. Improvements to layout are made as
+/// new versions, which are opt-in until the next major version makes one the default. Older
+/// versions stay available so that generated files don't change until you choose to update
+/// them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum LayoutVersion {
+    /// The original rules. When deciding whether an alternative fits, the first line is measured
+    /// as though it started at the left margin, so text already on the line is not counted.
+    #[default]
+    V1,
+    /// Measures the first line of an alternative from the current column, so it never overflows
+    /// because of text earlier on the line. Lines nested below zero (see [`Doc::dedent`]) are
+    /// measured from column 0, where they start, rather than from their nesting.
+    V2,
+    /// Also measures text by its display width rather than its length in bytes, so that
+    /// accented letters take one column, wide characters (such as CJK) two, and combining marks
+    /// none.
+    V3,
+}
+
+/// How many columns to count for characters of ambiguous East Asian width, such as `→`, `·`,
+/// and Greek and Cyrillic letters, which terminals draw one or two columns wide depending on
+/// their locale and font. See [`RenderOptions::ambiguous_width`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AmbiguousWidth {
+    /// One column, as in most Western locales.
+    #[default]
+    Narrow,
+    /// Two columns, as in most CJK locales.
+    Wide,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Fit Policies
+// -------------------------------------------------------------------------------------------------
+
+/// A line of a candidate layout, as measured so far. See [`FitPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineFit {
+    /// The page width.
+    pub width: Width,
+    /// The indentation of the line.
+    pub indent: Width,
+    /// The column the line has reached.
+    pub column: Width,
+    /// Whether this is the line on which the choice is made, rather than a later one measured
+    /// because of [`FitPolicy::measure_past`].
+    pub first: bool,
+    remaining: Width,
+}
+
+impl LineFit {
+    /// The columns left on this line by the layout algorithm's own measure: negative once the
+    /// line is too long.
+    pub fn remaining(&self) -> Width {
+        self.remaining
+    }
+
+    fn advance(&mut self, columns: Width, policy: &dyn FitPolicy) -> bool {
+        self.remaining = self.remaining.saturating_sub(columns);
+        self.column = self.column.saturating_add(columns);
+        policy.line_fits(self)
+    }
+}
+
+/// Decides whether a candidate layout fits, so formatters can fit more strictly or loosely than
+/// the default. See [`RenderOptions::fit_policy`].
+///
+/// To choose between alternatives, the layout algorithm measures the preferred one line by line,
+/// asking the policy about a line each time it grows, and falls back to the other alternative as
+/// soon as a line doesn't fit.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// // Accept lines that overflow by at most a few columns.
+/// struct Slack(Width);
+///
+/// impl FitPolicy for Slack {
+///     fn line_fits(&self, line: &LineFit) -> bool {
+///         line.remaining() >= -self.0
+///     }
+/// }
+///
+/// let doc: Doc = Doc::sep([Doc::text("hello"), Doc::text("world")]);
+/// assert_eq!(doc.clone().render(10), "hello\nworld");
+/// let options = RenderOptions::new(10).fit_policy(Slack(1));
+/// assert_eq!(doc.render_with(&options), "hello world");
+/// ```
+pub trait FitPolicy {
+    /// Whether `line` fits. Defaults to `line.remaining() >= 0`.
+    fn line_fits(&self, line: &LineFit) -> bool {
+        line.remaining() >= 0
+    }
+
+    /// Whether to keep measuring past a hard line break into a line indented by `indent`, for a
+    /// choice made at nesting `nesting`. Defaults to `false`, i.e., only the first line counts.
+    fn measure_past(&self, _nesting: Width, _indent: Width) -> bool {
+        false
+    }
+}
+
+/// The default [`FitPolicy`]: a candidate fits if its first line does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FirstLineFit;
+
+impl FitPolicy for FirstLineFit {}
+
+/// A [`FitPolicy`] that also requires the lines that follow to fit, for as long as they are
+/// indented deeper than where the choice was made (Leijen's `renderSmart`). See
+/// [`RenderOptions::smart_lookahead`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmartFit;
+
+impl FitPolicy for SmartFit {
+    fn measure_past(&self, nesting: Width, indent: Width) -> bool {
+        indent > nesting
+    }
+}
+
+/// A shared fit policy. Policies are compared by their id, so they can key memoized layouts.
+#[derive(Clone)]
+struct Policy {
+    id: PolicyId,
+    policy: Rc<dyn FitPolicy>,
+}
+
+/// What identifies a policy: its type, when that has only one value (e.g., [`FirstLineFit`]),
+/// or else the policy it was made from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum PolicyId {
+    Type(TypeId),
+    Instance(u64),
+}
+
+impl Policy {
+    fn new<P: FitPolicy + 'static>(policy: P) -> Self {
+        static INSTANCES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        let id = if std::mem::size_of::<P>() == 0 {
+            PolicyId::Type(TypeId::of::<P>())
+        } else {
+            let ordering = std::sync::atomic::Ordering::Relaxed;
+            PolicyId::Instance(INSTANCES.fetch_add(1, ordering))
+        };
+        Policy {
+            id,
+            policy: Rc::new(policy),
+        }
+    }
+}
+
+impl PartialEq for Policy {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Policy {}
+
+impl std::hash::Hash for Policy {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl std::fmt::Debug for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FitPolicy")
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Metrics
+// -------------------------------------------------------------------------------------------------
+
+/// How much work rendering a document took. See [`Doc::render_with_metrics`].
+///
+/// Lookahead is what makes documents expensive: a large `lookahead_steps` relative to `steps`
+/// means alternatives are being measured over long stretches of the document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderMetrics {
+    /// Work items (document nodes, at some indentation) processed by layout.
+    pub steps: usize,
+    /// Alternatives measured to decide whether they fit.
+    pub fits_probes: usize,
+    /// Work items examined while measuring alternatives.
+    pub lookahead_steps: usize,
+    /// The longest the layout work list got (roughly, how deeply nested the document is).
+    pub peak_work_list: usize,
+    /// Wall-clock time spent laying out and rendering.
+    pub elapsed: std::time::Duration,
+}
+
+impl RenderMetrics {
+    // Include the work of laying out a memoized subtree.
+    fn add(&mut self, other: &RenderMetrics) {
+        self.steps += other.steps;
+        self.fits_probes += other.fits_probes;
+        self.lookahead_steps += other.lookahead_steps;
+        self.peak_work_list = self.peak_work_list.max(other.peak_work_list);
+    }
+}
+
+/// The size of a rendered document. See [`Doc::measure`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Dimensions {
+    /// How many lines the output takes up.
+    pub lines: usize,
+    /// The width of the widest line, in the columns a terminal displays it in (see
+    /// [`RenderOptions::ambiguous_width`]), whichever [`LayoutVersion`] laid it out.
+    pub max_line_width: usize,
+    /// The characters in the output, counting each line break as one.
+    pub chars: usize,
+}
+
+// -------------------------------------------------------------------------------------------------
+// Errors
+// -------------------------------------------------------------------------------------------------
+
+/// An error produced while rendering a document. See [`Doc::try_render`] and [`Doc::write_to`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RenderError {
+    /// Writing the output failed.
+    Fmt(std::fmt::Error),
+    /// Writing the output to an [`std::io::Write`] failed, after the output up to `position` had
+    /// been written.
+    Io {
+        error: std::io::Error,
+        position: RenderPosition,
+    },
+}
+
+impl RenderError {
+    /// How far rendering got before failing, if known.
+    pub fn position(&self) -> Option<&RenderPosition> {
+        match self {
+            RenderError::Fmt(_) => None,
+            RenderError::Io { position, .. } => Some(position),
+        }
+    }
+}
+
+/// How much output had been written when rendering stopped. See [`RenderError::position`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RenderPosition {
+    /// Complete lines written (i.e., newlines).
+    pub lines: usize,
+    /// Bytes written.
+    pub bytes: usize,
+    /// The annotated regions (see [`Doc::annotate`]) the output was inside of, outermost first,
+    /// in their `Debug` form.
+    pub open_annotations: Vec<String>,
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::Fmt(err) => write!(f, "formatting error: {}", err),
+            RenderError::Io { error, position } => {
+                write!(
+                    f,
+                    "I/O error after writing {} lines ({} bytes)",
+                    position.lines, position.bytes
+                )?;
+                if !position.open_annotations.is_empty() {
+                    write!(f, " inside {}", position.open_annotations.join(" > "))?;
+                }
+                write!(f, ": {}", error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RenderError::Fmt(err) => Some(err),
+            RenderError::Io { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<std::fmt::Error> for RenderError {
+    fn from(err: std::fmt::Error) -> Self {
+        RenderError::Fmt(err)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Rendering
+// -------------------------------------------------------------------------------------------------
+
+/// How a back end writes annotations into rendered output. Each hook appends to `output`; by
+/// default annotations are dropped.
+trait Markup<A> {
+    /// Text from the document (or a line prefix).
+    fn text(&mut self, text: &str, output: &mut String) {
+        output.push_str(text);
+    }
+    /// An annotated region starts.
+    fn push(&mut self, _annotation: &A, _output: &mut String) {}
+    /// The most recently started region ends.
+    fn pop(&mut self, _output: &mut String) {}
+    /// A new line starts, at the end of `output`.
+    fn start_line(&mut self, _output: &mut String) {}
+}
+
+impl<A> Markup<A> for () {}
+
+impl<A, M: Markup<A>> Markup<A> for &mut M {
+    fn text(&mut self, text: &str, output: &mut String) {
+        (**self).text(text, output)
+    }
+    fn push(&mut self, annotation: &A, output: &mut String) {
+        (**self).push(annotation, output)
+    }
+    fn pop(&mut self, output: &mut String) {
+        (**self).pop(output)
+    }
+    fn start_line(&mut self, output: &mut String) {
+        (**self).start_line(output)
+    }
+}
+
+// Write laid-out `parts` to `output`, letting `markup` write their annotations.
+fn paint<A: Clone>(
+    parts: &Parts<A>,
+    options: &RenderOptions,
+    output: &mut String,
+    markup: &mut impl Markup<A>,
+) -> std::fmt::Result {
+    let mut painter = Painter::new(Cow::Borrowed(options), markup, std::mem::take(output));
+    let mut text = 0;
+    for part in &parts.parts {
+        match part {
+            Part::Newlines { indent, count } => {
+                for _ in 0..*count {
+                    painter.newline(*indent);
+                }
+            }
+            Part::Text(end) => {
+                painter.text(&parts.text[text..*end]);
+                text = *end;
+            }
+            Part::Literal(s) => painter.text(s),
+            Part::PushAnnotation(a) => painter.push(a),
+            Part::PopAnnotation => painter.pop(),
+        }
+    }
+    *output = painter.finish();
+    Ok(())
+}
+
+/// Writes rendered output a line at a time, letting `markup` write annotations. When trimming
+/// trailing whitespace, whitespace (and the markup after it) is held back until more text
+/// follows, so a line can be trimmed before the markup that closes it.
+struct Painter<'a, A, M> {
+    options: Cow<'a, RenderOptions>,
+    markup: M,
+    output: String,
+    // Where the current line, and its content after the indentation, start in `output`.
+    line: usize,
+    content: usize,
+    index: usize,
+    indent: Width,
+    held: Vec<Held<A>>,
+}
+
+/// Output held back at the end of a line, in the order it was painted.
+enum Held<A> {
+    Whitespace(String),
+    Push(A),
+    Pop,
+}
+
+impl<'a, A: Clone, M: Markup<A>> Painter<'a, A, M> {
+    /// Paint onto the end of `output`, starting the first line.
+    fn new(options: Cow<'a, RenderOptions>, markup: M, mut output: String) -> Self {
+        let line = output.len();
+        options.write_line_start(0, &mut output);
+        Painter {
+            options,
+            markup,
+            content: output.len(),
+            output,
+            line,
+            index: 0,
+            indent: 0,
+            held: vec![],
+        }
+    }
+
+    fn event(&mut self, event: RenderEvent<A>) {
+        match event {
+            RenderEvent::Text(s) => self.text(&s),
+            RenderEvent::Newline { indent } => self.newline(indent),
+            RenderEvent::PushAnnotation(a) => self.push(&a),
+            RenderEvent::PopAnnotation => self.pop(),
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if !self.options.trim_trailing_whitespace {
+            return self.markup.text(text, &mut self.output);
+        }
+        let kept = text.trim_end_matches([' ', '\t']);
+        if !kept.is_empty() {
+            self.release();
+            self.markup.text(kept, &mut self.output);
+        }
+        if kept.len() < text.len() {
+            self.held
+                .push(Held::Whitespace(text[kept.len()..].to_string()));
+        }
+    }
+
+    fn push(&mut self, annotation: &A) {
+        if self.held.is_empty() {
+            self.markup.push(annotation, &mut self.output);
+        } else {
+            self.held.push(Held::Push(annotation.clone()));
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.held.is_empty() {
+            self.markup.pop(&mut self.output);
+        } else {
+            self.held.push(Held::Pop);
+        }
+    }
+
+    fn newline(&mut self, indent: Width) {
+        self.end_line();
+        self.output.push('\n');
+        self.start_line(indent);
+    }
+
+    /// Finish the current line, trimming it if the options say to, and return it.
+    fn end_line(&mut self) -> RenderedLine<'_> {
+        if self.options.trim_trailing_whitespace {
+            // Regions opened and closed within the trimmed whitespace are dropped with it.
+            let mut markup: Vec<Held<A>> = vec![];
+            for held in std::mem::take(&mut self.held) {
+                match held {
+                    Held::Whitespace(_) => (),
+                    Held::Pop if matches!(markup.last(), Some(Held::Push(_))) => {
+                        markup.pop();
+                    }
+                    held => markup.push(held),
+                }
+            }
+            let trimmed = self.output[self.line..].trim_end_matches([' ', '\t']).len();
+            self.output.truncate(self.line + trimmed);
+            self.held = markup;
+            self.release();
+        }
+        RenderedLine {
+            index: self.index,
+            indent: self.indent,
+            text: &self.output[self.line..],
+            content: self.output.get(self.content..).unwrap_or_default(),
+        }
+    }
+
+    /// Start the next line at the end of the output, after `indent` columns of indentation.
+    fn start_line(&mut self, indent: Width) {
+        self.index += 1;
+        self.indent = indent;
+        self.line = self.output.len();
+        self.markup.start_line(&mut self.output);
+        self.options.write_line_start(self.index, &mut self.output);
+        self.options.write_indent(indent, &mut self.output);
+        self.content = self.output.len();
+    }
+
+    /// Drop the output painted so far, once its lines have been passed on.
+    fn clear(&mut self) {
+        self.output.clear();
+        self.line = 0;
+        self.content = 0;
+    }
+
+    /// The output painted so far, leaving the last line untrimmed.
+    fn finish(mut self) -> String {
+        self.release();
+        self.output
+    }
+
+    // Write out the held output.
+    fn release(&mut self) {
+        for held in std::mem::take(&mut self.held) {
+            match held {
+                Held::Whitespace(s) => self.markup.text(&s, &mut self.output),
+                Held::Push(a) => self.markup.push(&a, &mut self.output),
+                Held::Pop => self.markup.pop(&mut self.output),
+            }
+        }
     }
 }
@@ -0,0 +1,151 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{Annotation, Doc, Markup, RenderOptions, Style, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Markdown Markers
+// -------------------------------------------------------------------------------------------------
+
+/// Inline Markdown markup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Markdown {
+    /// `**strong**`
+    Strong,
+    /// `*emphasis*`
+    Emphasis,
+    /// `` `code` ``
+    Code,
+}
+
+impl Markdown {
+    fn delimiter(self) -> &'static str {
+        match self {
+            Markdown::Strong => "**",
+            Markdown::Emphasis => "*",
+            Markdown::Code => "`",
+        }
+    }
+}
+
+/// An annotation that can be rendered as Markdown markup, for [`Doc::render_markdown`].
+pub trait MarkdownStyle: Annotation {
+    /// The markup for annotated regions, outermost first. Regions with no markup are left as
+    /// plain text.
+    fn markdown(&self) -> Vec<Markdown>;
+}
+
+/// Bold becomes strong and italic becomes emphasis; other attributes have no Markdown
+/// equivalent and are dropped.
+impl MarkdownStyle for Style {
+    fn markdown(&self) -> Vec<Markdown> {
+        let mut markers = vec![];
+        if self.bold {
+            markers.push(Markdown::Strong);
+        }
+        if self.italic {
+            markers.push(Markdown::Emphasis);
+        }
+        markers
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Markdown Rendering
+// -------------------------------------------------------------------------------------------------
+
+fn escape(text: &str, output: &mut String) {
+    for ch in text.chars() {
+        if matches!(
+            ch,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '|' | '~'
+        ) {
+            output.push('\\');
+        }
+        output.push(ch);
+    }
+}
+
+/// The markers opened by each open annotated region. Layout closes regions before line breaks
+/// and reopens them after, so every line's markup stands on its own.
+#[derive(Default)]
+struct MarkdownWriter {
+    open: Vec<Vec<Markdown>>,
+}
+
+impl MarkdownWriter {
+    fn is_open(&self, marker: Markdown) -> bool {
+        self.open.iter().flatten().any(|open| *open == marker)
+    }
+}
+
+impl<A: MarkdownStyle> Markup<A> for MarkdownWriter {
+    fn text(&mut self, text: &str, output: &mut String) {
+        if self.is_open(Markdown::Code) {
+            output.push_str(text);
+        } else {
+            escape(text, output);
+        }
+    }
+
+    fn push(&mut self, annotation: &A, output: &mut String) {
+        // Markup inside a code span would be printed literally.
+        let mut markers = vec![];
+        if !self.is_open(Markdown::Code) {
+            for marker in annotation.markdown() {
+                if !self.is_open(marker) && !markers.contains(&marker) {
+                    output.push_str(marker.delimiter());
+                    markers.push(marker);
+                }
+            }
+        }
+        self.open.push(markers);
+    }
+
+    fn pop(&mut self, output: &mut String) {
+        for marker in self.open.pop().unwrap_or_default().iter().rev() {
+            output.push_str(marker.delimiter());
+        }
+    }
+}
+
+impl<A: MarkdownStyle> Doc<A> {
+    /// Render the document at the given `width` as Markdown: annotated regions are wrapped in
+    /// their markup (see [`MarkdownStyle`]), and Markdown-significant characters in text are
+    /// escaped (except inside code spans).
+    ///
+    /// The markup takes up no width when the layout is chosen unless the annotation type
+    /// reserves it (see [`Annotation`]).
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc = Doc::<Style>::text("warning:")
+    ///     .styled(Style::new().bold(true))
+    ///     .concat(Doc::<Style>::text(" *unused*"));
+    /// assert_eq!(doc.render_markdown(80), "**warning:** \\*unused\\*");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_markdown(&self, width: Width) -> String {
+        self.render_markdown_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document as Markdown using the given [`RenderOptions`]. See
+    /// [`Doc::render_markdown`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_markdown_with(&self, options: &RenderOptions) -> String {
+        let mut output = String::new();
+        let markup = &mut MarkdownWriter::default();
+        if let Err(err) = self.clone().render_marked_up(options, &mut output, markup) {
+            panic!("failed to render document: {}", err)
+        }
+        output
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::Doc;
+
+// -------------------------------------------------------------------------------------------------
+// Number Formatting
+// -------------------------------------------------------------------------------------------------
+
+/// The base used to print integers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hex,
+}
+
+/// A policy for printing numbers, so printers for data formats can match project conventions.
+///
+/// The default policy prints exactly what Rust's `to_string` does.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let fmt = NumberFormat::new().precision(2).trailing_zero(true);
+/// assert_eq!(fmt.format_float(3.14159), "3.14");
+/// assert_eq!(NumberFormat::new().trailing_zero(true).format_float(2.0), "2.0");
+///
+/// let hex = NumberFormat::new().radix(Radix::Hex).separator('_', 4);
+/// assert_eq!(hex.format_int(0xdead_beef), "0xdead_beef");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumberFormat {
+    precision: Option<usize>,
+    exponent_thresholds: Option<(i32, i32)>,
+    trailing_zero: bool,
+    radix: Radix,
+    separator: Option<(char, usize)>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat::new()
+    }
+}
+
+impl NumberFormat {
+    /// The default policy: Rust's own formatting.
+    pub fn new() -> Self {
+        NumberFormat {
+            precision: None,
+            exponent_thresholds: None,
+            trailing_zero: false,
+            radix: Radix::Decimal,
+            separator: None,
+        }
+    }
+
+    /// Print floats with exactly `digits` fractional digits (instead of the shortest
+    /// representation that round-trips).
+    pub fn precision(mut self, digits: usize) -> Self {
+        self.precision = Some(digits);
+        self
+    }
+
+    /// Use exponent notation for non-zero floats whose magnitude is below `10^-small` or at least
+    /// `10^large`.
+    pub fn exponent_thresholds(mut self, small: i32, large: i32) -> Self {
+        self.exponent_thresholds = Some((small, large));
+        self
+    }
+
+    /// Always print a fractional part for floats (`1.0` rather than `1`).
+    pub fn trailing_zero(mut self, trailing_zero: bool) -> Self {
+        self.trailing_zero = trailing_zero;
+        self
+    }
+
+    /// The base for integers. Non-decimal integers are printed with a `0b`/`0o`/`0x` prefix.
+    pub fn radix(mut self, radix: Radix) -> Self {
+        self.radix = radix;
+        self
+    }
+
+    /// Separate the integer digits into groups of `group` digits, e.g. `1_000_000`.
+    pub fn separator(mut self, separator: char, group: usize) -> Self {
+        self.separator = Some((separator, group.max(1)));
+        self
+    }
+
+    /// Format an integer according to this policy.
+    pub fn format_int(&self, n: i64) -> String {
+        let magnitude = n.unsigned_abs();
+        let (prefix, digits) = match self.radix {
+            Radix::Binary => ("0b", format!("{:b}", magnitude)),
+            Radix::Octal => ("0o", format!("{:o}", magnitude)),
+            Radix::Decimal => ("", magnitude.to_string()),
+            Radix::Hex => ("0x", format!("{:x}", magnitude)),
+        };
+        let sign = if n < 0 { "-" } else { "" };
+        format!("{}{}{}", sign, prefix, self.group_digits(&digits))
+    }
+
+    /// Format a float according to this policy. Non-finite values are printed as Rust prints them.
+    pub fn format_float(&self, f: f64) -> String {
+        if !f.is_finite() {
+            return f.to_string();
+        }
+        let use_exponent = match self.exponent_thresholds {
+            Some((small, large)) if f != 0.0 => {
+                let magnitude = f.abs();
+                magnitude < 10f64.powi(-small) || magnitude >= 10f64.powi(large)
+            }
+            _ => false,
+        };
+        let (mantissa, exponent) = match (use_exponent, self.precision) {
+            (true, Some(p)) => split_exponent(format!("{:.*e}", p, f)),
+            (true, None) => split_exponent(format!("{:e}", f)),
+            (false, Some(p)) => (format!("{:.*}", p, f), None),
+            (false, None) => (f.to_string(), None),
+        };
+
+        let (sign, unsigned) = match mantissa.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", mantissa.as_str()),
+        };
+        let (int_part, frac_part) = match unsigned.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (unsigned, None),
+        };
+        let mut out = format!("{}{}", sign, self.group_digits(int_part));
+        match frac_part {
+            Some(frac) => {
+                out.push('.');
+                out.push_str(frac);
+            }
+            None if self.trailing_zero => out.push_str(".0"),
+            None => (),
+        }
+        if let Some(exponent) = exponent {
+            out.push('e');
+            out.push_str(&exponent);
+        }
+        out
+    }
+
+    /// [`format_int`](Self::format_int) as a document.
+    pub fn int(&self, n: i64) -> Doc {
+        Doc::text(self.format_int(n))
+    }
+
+    /// [`format_float`](Self::format_float) as a document.
+    pub fn float(&self, f: f64) -> Doc {
+        Doc::text(self.format_float(f))
+    }
+
+    fn group_digits(&self, digits: &str) -> String {
+        let Some((separator, group)) = self.separator else {
+            return digits.to_string();
+        };
+        let mut out = String::with_capacity(digits.len() + digits.len() / group);
+        for (i, ch) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(group) {
+                out.push(separator);
+            }
+            out.push(ch);
+        }
+        out
+    }
+}
+
+fn split_exponent(s: String) -> (String, Option<String>) {
+    match s.split_once('e') {
+        Some((mantissa, exponent)) => (mantissa.to_string(), Some(exponent.to_string())),
+        None => (s, None),
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::borrow::Cow;
+
+use crate::{Annotation, Doc, Layouter, Markup, Painter, RenderOptions, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Source Spans
+// -------------------------------------------------------------------------------------------------
+
+/// A position in rendered output. Both coordinates start at zero, and `column` counts bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OutputPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where an annotated region ended up in rendered output: from `start` up to (but not
+/// including) `end`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputSpan<A> {
+    pub start: OutputPosition,
+    pub end: OutputPosition,
+    pub annotation: A,
+}
+
+/// The regions of rendered output each annotation covers. See [`Doc::render_with_map`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutputMap<A> {
+    spans: Vec<OutputSpan<A>>,
+}
+
+impl<A> OutputMap<A> {
+    /// Every annotated region, in the order they start (enclosing regions before the regions
+    /// they contain).
+    pub fn spans(&self) -> &[OutputSpan<A>] {
+        &self.spans
+    }
+
+    /// The annotations of the regions containing `position`, innermost first.
+    pub fn at(&self, position: OutputPosition) -> impl Iterator<Item = &A> {
+        self.spans
+            .iter()
+            .rev()
+            .filter(move |span| span.start <= position && position < span.end)
+            .map(|span| &span.annotation)
+    }
+
+    /// The innermost annotated region containing the given `line` and `column`, e.g., to find
+    /// what the mouse is over in a rendered tree.
+    pub fn annotation_at(&self, line: usize, column: usize) -> Option<&OutputSpan<A>> {
+        let position = OutputPosition { line, column };
+        self.spans
+            .iter()
+            .rev()
+            .find(|span| span.start <= position && position < span.end)
+    }
+}
+
+impl<A: Annotation> Doc<A> {
+    /// Render the document at the given `width`, along with where each annotated region ended up
+    /// in the output. Annotating sub-documents with source spans (or AST node ids) maps positions
+    /// in the formatted output back to the source, e.g., for "go to definition" or to underline
+    /// errors.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// // `f(x)` in the source, at bytes 10..14.
+    /// let call = Doc::annotate(10..14, Doc::<std::ops::Range<usize>>::text("f(x)"));
+    /// let (output, map) = Doc::<std::ops::Range<usize>>::text("let y =")
+    ///     .concat_space(call)
+    ///     .render_with_map(80);
+    /// assert_eq!(output, "let y = f(x)");
+    /// let position = OutputPosition { line: 0, column: 9 };
+    /// assert_eq!(map.at(position).next(), Some(&(10..14)));
+    /// ```
+    pub fn render_with_map(&self, width: Width) -> (String, OutputMap<A>) {
+        self.render_with_map_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document using the given [`RenderOptions`], along with where each annotated
+    /// region ended up in the output. See [`Doc::render_with_map`].
+    pub fn render_with_map_with(&self, options: &RenderOptions) -> (String, OutputMap<A>) {
+        let mut spans = Spans::default();
+        let mut painter = Painter::new(Cow::Borrowed(options), &mut spans, String::new());
+        for event in Layouter::new(self.clone(), options.config()).unbalanced() {
+            painter.event(event);
+        }
+        let output = painter.finish();
+        (output, OutputMap { spans: spans.spans })
+    }
+}
+
+/// Records where each annotated region starts and ends as it's painted.
+struct Spans<A> {
+    spans: Vec<OutputSpan<A>>,
+    open: Vec<usize>,
+    line: usize,
+    // Where the current line starts in the output.
+    start: usize,
+}
+
+impl<A> Default for Spans<A> {
+    fn default() -> Self {
+        Spans {
+            spans: vec![],
+            open: vec![],
+            line: 0,
+            start: 0,
+        }
+    }
+}
+
+impl<A> Spans<A> {
+    fn position(&self, output: &str) -> OutputPosition {
+        OutputPosition {
+            line: self.line,
+            column: output.len() - self.start,
+        }
+    }
+}
+
+impl<A: Clone> Markup<A> for Spans<A> {
+    fn push(&mut self, annotation: &A, output: &mut String) {
+        self.open.push(self.spans.len());
+        self.spans.push(OutputSpan {
+            start: self.position(output),
+            end: self.position(output),
+            annotation: annotation.clone(),
+        });
+    }
+
+    fn pop(&mut self, output: &mut String) {
+        if let Some(index) = self.open.pop() {
+            self.spans[index].end = self.position(output);
+        }
+    }
+
+    fn start_line(&mut self, output: &mut String) {
+        self.line += 1;
+        self.start = output.len();
+    }
+}
@@ -0,0 +1,252 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::IsTerminal;
+
+use crate::{Annotation, Doc, Markup, RenderOptions, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Styles
+// -------------------------------------------------------------------------------------------------
+
+/// A terminal color.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Color {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    /// One of the 256 colors of the extended palette.
+    Fixed(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn sgr(self, base: u8, out: &mut String) {
+        let code = match self {
+            Color::Black => 0,
+            Color::Red => 1,
+            Color::Green => 2,
+            Color::Yellow => 3,
+            Color::Blue => 4,
+            Color::Magenta => 5,
+            Color::Cyan => 6,
+            Color::White => 7,
+            Color::Fixed(n) => {
+                out.push_str(&format!("{};5;{}", base + 8, n));
+                return;
+            }
+            Color::Rgb(r, g, b) => {
+                out.push_str(&format!("{};2;{};{};{}", base + 8, r, g, b));
+                return;
+            }
+        };
+        out.push_str(&(base + code).to_string());
+    }
+}
+
+/// Text styling for terminal output, used as the annotation type of documents rendered with
+/// [`Doc::render_ansi`].
+///
+/// Nested styles combine: an inner style's colors replace the outer ones, and its attributes
+/// are added to them.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let error = Style::new().bold(true).fg(Color::Red);
+/// let doc = Doc::<Style>::text("error").styled(error).concat(Doc::<Style>::text(": oops"));
+/// assert_eq!(doc.render_ansi(80), "\x1b[1;31merror\x1b[0m: oops");
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Style {
+    pub(crate) bold: bool,
+    pub(crate) italic: bool,
+    pub(crate) underline: bool,
+    pub(crate) fg: Option<Color>,
+    pub(crate) bg: Option<Color>,
+}
+
+impl Style {
+    /// The plain style.
+    pub fn new() -> Self {
+        Style::default()
+    }
+
+    /// Print in bold.
+    pub fn bold(mut self, bold: bool) -> Self {
+        self.bold = bold;
+        self
+    }
+
+    /// Print in italics.
+    pub fn italic(mut self, italic: bool) -> Self {
+        self.italic = italic;
+        self
+    }
+
+    /// Underline the text.
+    pub fn underline(mut self, underline: bool) -> Self {
+        self.underline = underline;
+        self
+    }
+
+    /// The foreground (text) color.
+    pub fn fg(mut self, color: Color) -> Self {
+        self.fg = Some(color);
+        self
+    }
+
+    /// The background color.
+    pub fn bg(mut self, color: Color) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// `self` nested inside `outer`.
+    fn within(self, outer: Style) -> Style {
+        Style {
+            bold: self.bold || outer.bold,
+            italic: self.italic || outer.italic,
+            underline: self.underline || outer.underline,
+            fg: self.fg.or(outer.fg),
+            bg: self.bg.or(outer.bg),
+        }
+    }
+
+    /// Write the escape sequence that switches to this style (from the plain style).
+    fn write_sgr(&self, out: &mut String) {
+        if self.is_plain() {
+            return;
+        }
+        let mut codes = vec![];
+        for (on, code) in [(self.bold, "1"), (self.italic, "3"), (self.underline, "4")] {
+            if on {
+                codes.push(code.to_string());
+            }
+        }
+        for (color, base) in [(self.fg, 30), (self.bg, 40)] {
+            if let Some(color) = color {
+                let mut code = String::new();
+                color.sgr(base, &mut code);
+                codes.push(code);
+            }
+        }
+        out.push_str("\x1b[");
+        out.push_str(&codes.join(";"));
+        out.push('m');
+    }
+}
+
+// Escape codes take up no room on the terminal.
+impl Annotation for Style {}
+
+// -------------------------------------------------------------------------------------------------
+// ANSI Rendering
+// -------------------------------------------------------------------------------------------------
+
+/// Whether to emit ANSI escape codes. See [`RenderOptions::color`], which also defaults to
+/// `Always`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorChoice {
+    /// Emit escape codes if standard output is a terminal, `NO_COLOR` is unset (or empty), and
+    /// `TERM` isn't `dumb`.
+    Auto,
+    #[default]
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether this choice emits escape codes here and now.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+                let dumb = std::env::var_os("TERM").is_some_and(|term| term == "dumb");
+                !no_color && !dumb && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Tracks the styles in effect while rendering, so each annotated region can be closed by
+/// restoring the style around it. Layout closes regions before line breaks, so indentation isn't
+/// colored.
+#[derive(Default)]
+pub(crate) struct Ansi {
+    styles: Vec<Style>,
+}
+
+impl Ansi {
+    fn current(&self) -> Style {
+        self.styles.last().copied().unwrap_or_default()
+    }
+}
+
+impl Markup<Style> for Ansi {
+    fn push(&mut self, style: &Style, output: &mut String) {
+        let style = style.within(self.current());
+        style.write_sgr(output);
+        self.styles.push(style);
+    }
+
+    fn pop(&mut self, output: &mut String) {
+        let closed = self.styles.pop().unwrap_or_default();
+        if closed != self.current() {
+            output.push_str(RESET);
+            self.current().write_sgr(output);
+        }
+    }
+}
+
+impl Doc<Style> {
+    /// Shorthand for [`Doc::annotate`] with a [`Style`].
+    pub fn styled(self, style: Style) -> Doc<Style> {
+        Doc::annotate(style, self)
+    }
+
+    /// Render the document at the given `width`, emitting ANSI escape codes for its styles. The
+    /// escape codes take up no width when the layout is chosen.
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_ansi(&self, width: Width) -> String {
+        self.render_ansi_with(&RenderOptions::new(width))
+    }
+
+    /// Render the document using the given [`RenderOptions`], emitting ANSI escape codes for its
+    /// styles if [`RenderOptions::color`] allows them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_ansi_with(&self, options: &RenderOptions) -> String {
+        let mut output = String::new();
+        let result = if options.color.enabled() {
+            self.clone()
+                .render_marked_up(options, &mut output, &mut Ansi::default())
+        } else {
+            self.clone().render_marked_up(options, &mut output, &mut ())
+        };
+        if let Err(err) = result {
+            panic!("failed to render document: {}", err)
+        }
+        output
+    }
+}
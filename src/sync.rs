@@ -0,0 +1,240 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use crate::{Annotation, Doc, LeafDoc, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Thread-Safe Documents
+// -------------------------------------------------------------------------------------------------
+
+type SyncFn<A> = Arc<dyn Fn(Width) -> SyncDoc<A> + Send + Sync + 'static>;
+
+/// A document that can be built on one thread and sent to another, for printers that build
+/// documents in parallel. It's `Send` and `Sync` when its annotations are, and shares its nodes
+/// through `Arc`. It offers the core combinators of [`Doc`], and converts into a `Doc` to be
+/// rendered.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let workers: Vec<_> = (0..2)
+///     .map(|i| std::thread::spawn(move || SyncDoc::text(format!("item{i}")).nest(2)))
+///     .collect();
+/// let items = workers.into_iter().map(|worker| Doc::from(worker.join().unwrap()));
+/// let doc: Doc = Doc::vsep(items);
+/// assert_eq!(doc.render(80), "item0\nitem1");
+/// ```
+pub struct SyncDoc<A = ()>(Arc<SyncInner<A>>);
+
+enum SyncInner<A> {
+    Empty,
+    Text(Cow<'static, str>),
+    Line,
+    LineBreak,
+    Concat(SyncDoc<A>, SyncDoc<A>),
+    Nest(Width, SyncDoc<A>),
+    Alt(SyncDoc<A>, SyncDoc<A>),
+    FlatAlt(SyncDoc<A>, SyncDoc<A>),
+    Group(SyncDoc<A>),
+    Nesting(SyncFn<A>),
+    Column(SyncFn<A>),
+    PageWidth(SyncFn<A>),
+    Annotate(A, SyncDoc<A>),
+}
+
+impl<A> SyncInner<A> {
+    fn into_doc(self) -> SyncDoc<A> {
+        SyncDoc(Arc::new(self))
+    }
+}
+
+impl<A> Clone for SyncDoc<A> {
+    fn clone(&self) -> Self {
+        SyncDoc(Arc::clone(&self.0))
+    }
+}
+
+// Deep documents are dropped without recursion, as `Doc`s are.
+impl<A> Drop for SyncDoc<A> {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        take_children(self, &mut stack);
+        while let Some(mut doc) = stack.pop() {
+            take_children(&mut doc, &mut stack);
+        }
+    }
+}
+
+fn take_children<A>(doc: &mut SyncDoc<A>, stack: &mut Vec<SyncDoc<A>>) {
+    let Some(inner) = Arc::get_mut(&mut doc.0) else {
+        return;
+    };
+    match std::mem::replace(inner, SyncInner::Empty) {
+        SyncInner::Concat(x, y) | SyncInner::Alt(x, y) | SyncInner::FlatAlt(x, y) => {
+            stack.push(x);
+            stack.push(y);
+        }
+        SyncInner::Nest(_, x) | SyncInner::Group(x) | SyncInner::Annotate(_, x) => stack.push(x),
+        SyncInner::Empty
+        | SyncInner::Text(_)
+        | SyncInner::Line
+        | SyncInner::LineBreak
+        | SyncInner::Nesting(_)
+        | SyncInner::Column(_)
+        | SyncInner::PageWidth(_) => (),
+    }
+}
+
+impl<A> SyncDoc<A> {
+    /// The empty document. See [`Doc::nil`].
+    pub fn nil() -> SyncDoc<A> {
+        SyncInner::Empty.into_doc()
+    }
+
+    /// A document from raw text. See [`Doc::text`].
+    pub fn text<S: Into<String>>(str: S) -> SyncDoc<A> {
+        SyncInner::Text(Cow::Owned(str.into())).into_doc()
+    }
+
+    /// A document from static text, without copying it. See [`Doc::static_text`].
+    pub fn static_text(text: &'static str) -> SyncDoc<A> {
+        SyncInner::Text(Cow::Borrowed(text)).into_doc()
+    }
+
+    /// A single ASCII space. See [`Doc::space`].
+    pub fn space() -> SyncDoc<A> {
+        SyncDoc::static_text(" ")
+    }
+
+    /// A line break that is a space when flattened. See [`Doc::line`].
+    pub fn line() -> SyncDoc<A> {
+        SyncInner::Line.into_doc()
+    }
+
+    /// A space if it fits, or else a line break. See [`Doc::softline`].
+    pub fn softline() -> SyncDoc<A> {
+        SyncDoc::space().alt(SyncDoc::line())
+    }
+
+    /// A line break that is empty when flattened. See [`Doc::line_break`].
+    pub fn line_break() -> SyncDoc<A> {
+        SyncInner::LineBreak.into_doc()
+    }
+
+    /// `self` followed by `other`. See [`Doc::concat`].
+    pub fn concat(self, other: SyncDoc<A>) -> SyncDoc<A> {
+        SyncInner::Concat(self, other).into_doc()
+    }
+
+    /// Indent the lines of `self` after the first by `depth`. See [`Doc::nest`].
+    pub fn nest(self, depth: Width) -> SyncDoc<A> {
+        SyncInner::Nest(depth, self).into_doc()
+    }
+
+    /// `self` if it fits, or else `other`. See [`Doc::alt`].
+    pub fn alt(self, other: SyncDoc<A>) -> SyncDoc<A> {
+        SyncInner::Alt(self, other).into_doc()
+    }
+
+    /// `self`, or `flat` when laid out on one line. See [`Doc::flat_alt`].
+    pub fn flat_alt(self, flat: SyncDoc<A>) -> SyncDoc<A> {
+        SyncInner::FlatAlt(self, flat).into_doc()
+    }
+
+    /// `self` on one line if it fits, or else broken. See [`Doc::group`].
+    pub fn group(self) -> SyncDoc<A> {
+        SyncInner::Group(self).into_doc()
+    }
+
+    /// Annotate `doc` with `annotation`. See [`Doc::annotate`].
+    pub fn annotate(annotation: A, doc: SyncDoc<A>) -> SyncDoc<A> {
+        SyncInner::Annotate(annotation, doc).into_doc()
+    }
+
+    /// A document built from the column it starts at. See [`Doc::column`].
+    pub fn column<F>(f: F) -> SyncDoc<A>
+    where
+        F: Fn(Width) -> SyncDoc<A> + Send + Sync + 'static,
+    {
+        SyncInner::Column(Arc::new(f)).into_doc()
+    }
+
+    /// A document built from the current nesting level. See [`Doc::nesting`].
+    pub fn nesting<F>(f: F) -> SyncDoc<A>
+    where
+        F: Fn(Width) -> SyncDoc<A> + Send + Sync + 'static,
+    {
+        SyncInner::Nesting(Arc::new(f)).into_doc()
+    }
+
+    /// A document built from the page width. See [`Doc::page_width`].
+    pub fn page_width<F>(f: F) -> SyncDoc<A>
+    where
+        F: Fn(Width) -> SyncDoc<A> + Send + Sync + 'static,
+    {
+        SyncInner::PageWidth(Arc::new(f)).into_doc()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Conversion
+// -------------------------------------------------------------------------------------------------
+
+// Converted iteratively, as deep documents would overflow the stack, and subtrees shared within
+// the `SyncDoc` stay shared in the `Doc`.
+impl<A: Annotation> From<SyncDoc<A>> for Doc<A> {
+    fn from(doc: SyncDoc<A>) -> Doc<A> {
+        let mut converted: HashMap<*const SyncInner<A>, Doc<A>> = HashMap::new();
+        let mut stack = vec![(doc.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if converted.contains_key(&Arc::as_ptr(&doc.0)) {
+                continue;
+            }
+            if !children_done {
+                let children: Vec<SyncDoc<A>> = match &*doc.0 {
+                    SyncInner::Concat(x, y) | SyncInner::Alt(x, y) | SyncInner::FlatAlt(x, y) => {
+                        vec![x.clone(), y.clone()]
+                    }
+                    SyncInner::Nest(_, x) | SyncInner::Group(x) | SyncInner::Annotate(_, x) => {
+                        vec![x.clone()]
+                    }
+                    _ => vec![],
+                };
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let child = |d: &SyncDoc<A>| converted[&Arc::as_ptr(&d.0)].clone();
+            let node = match &*doc.0 {
+                SyncInner::Empty => Doc::<A>::nil(),
+                SyncInner::Text(Cow::Borrowed(s)) => Doc::<A>::static_text(s),
+                SyncInner::Text(Cow::Owned(s)) => Doc::<A>::text(s.clone()),
+                SyncInner::Line => Doc::<A>::line(),
+                SyncInner::LineBreak => Doc::<A>::line_break(),
+                SyncInner::Concat(x, y) => child(x).concat(child(y)),
+                SyncInner::Nest(i, x) => child(x).nest(*i),
+                SyncInner::Alt(x, y) => child(x).alt(child(y)),
+                SyncInner::FlatAlt(x, y) => child(x).flat_alt(child(y)),
+                SyncInner::Group(x) => child(x).group(),
+                SyncInner::Nesting(f) => {
+                    let f = f.clone();
+                    Doc::nesting(move |i| f(i).into())
+                }
+                SyncInner::Column(f) => {
+                    let f = f.clone();
+                    Doc::column(move |c| f(c).into())
+                }
+                SyncInner::PageWidth(f) => {
+                    let f = f.clone();
+                    Doc::page_width(move |w| f(w).into())
+                }
+                SyncInner::Annotate(a, x) => Doc::annotate(a.clone(), child(x)),
+            };
+            converted.insert(Arc::as_ptr(&doc.0), node);
+        }
+        converted[&Arc::as_ptr(&doc.0)].clone()
+    }
+}
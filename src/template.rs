@@ -0,0 +1,78 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Support for `doc_template!` expansions; not meant to be used directly.
+//!
+//! A template's text and hints are plain [`Doc`]s, and so are the values of its `ToDoc` holes.
+//! Joining a plain piece with a `Doc<A>` lifts it to `Doc<A>`, so a template's annotation type is
+//! inferred from its holes, and a template without annotated holes builds a plain `Doc`.
+//!
+//! Both choices are made by method resolution on the pieces' types: expansions call
+//! `(&Hole(&value)).hole()` and `(&&&Join(lhs, rhs)).join()`, and each impl below is reached
+//! through one more reference than the one before it, so it only applies if those before it don't.
+
+use crate::{Annotation, Doc, ToDoc};
+
+// -------------------------------------------------------------------------------------------------
+// Holes
+// -------------------------------------------------------------------------------------------------
+
+/// A template hole's value: spliced in as is if it is a `Doc<A>`, or else through `ToDoc`.
+pub struct Hole<'a, T: ?Sized>(pub &'a T);
+
+pub trait DocHole<A> {
+    fn hole(&self) -> Doc<A>;
+}
+
+impl<A: Annotation> DocHole<A> for Hole<'_, Doc<A>> {
+    fn hole(&self) -> Doc<A> {
+        self.0.clone()
+    }
+}
+
+pub trait ToDocHole {
+    fn hole(&self) -> Doc;
+}
+
+impl<T: ToDoc + ?Sized> ToDocHole for &Hole<'_, T> {
+    fn hole(&self) -> Doc {
+        self.0.to_doc()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Concatenation
+// -------------------------------------------------------------------------------------------------
+
+/// Two consecutive template pieces, to be concatenated.
+pub struct Join<L, R>(pub L, pub R);
+
+pub trait JoinDocs<A> {
+    fn join(&self) -> Doc<A>;
+}
+
+impl<A: Annotation> JoinDocs<A> for &&Join<Doc<A>, Doc<A>> {
+    fn join(&self) -> Doc<A> {
+        self.0.clone().concat(self.1.clone())
+    }
+}
+
+pub trait JoinPlainLeft<A> {
+    fn join(&self) -> Doc<A>;
+}
+
+impl<A: Annotation> JoinPlainLeft<A> for &Join<Doc, Doc<A>> {
+    fn join(&self) -> Doc<A> {
+        self.0.unannotated().concat(self.1.clone())
+    }
+}
+
+pub trait JoinPlainRight<A> {
+    fn join(&self) -> Doc<A>;
+}
+
+impl<A: Annotation> JoinPlainRight<A> for Join<Doc<A>, Doc> {
+    fn join(&self) -> Doc<A> {
+        self.0.clone().concat(self.1.unannotated())
+    }
+}
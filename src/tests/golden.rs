@@ -0,0 +1,116 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Golden outputs for each [`LayoutVersion`]. A version's layouts must not change within a major
+//! version, so these snapshots may only be added to, never updated.
+
+use insta::assert_snapshot;
+
+use crate::{
+    examples::{json::*, lambda::*, xml::*},
+    *,
+};
+
+// -------------------------------------------------------------------------------------------------
+// Corpus
+// -------------------------------------------------------------------------------------------------
+
+const WIDTHS: [Width; 4] = [8, 20, 40, 80];
+
+fn corpus() -> Vec<(&'static str, Doc)> {
+    let json = Json::object(vec![
+        ("name", Json::string("pretty-simple")),
+        (
+            "tags",
+            Json::Array(vec![Json::string("layout"), Json::Null]),
+        ),
+        ("nested", Json::object(vec![("depth", Json::Int(2))])),
+    ]);
+    let li = |s| XML::elem("li", vec![], vec![XML::text(s)]);
+    let xml = XML::elem(
+        "ul",
+        vec![("class", "items")],
+        vec![li("one"), li("two"), li("three")],
+    );
+    let exp = Exp::let_in(
+        "compose",
+        Exp::lam("f", Exp::lam("g", Exp::var("x"))),
+        Exp::app(
+            Exp::app(Exp::var("compose"), Exp::var("id")),
+            Exp::var("id"),
+        ),
+    );
+    let words = Doc::words("the quick brown fox jumps over the lazy dog");
+    let call = Doc::text("call(").concat(
+        Doc::softline_empty()
+            .concat(Doc::intersperse(
+                ["alpha", "beta", "gamma"].map(Doc::text),
+                Doc::text(",").concat(Doc::softline()),
+            ))
+            .nest(4)
+            .concat(Doc::softline_empty())
+            .group(),
+    );
+    let prefixed = Doc::text("label: ").concat(Doc::sep(["aaaa", "bbbb", "cccc"].map(Doc::text)));
+    let quoted = Doc::quoted_block("> ", Doc::sep(["quoted", "text", "here"].map(Doc::text)));
+    let fill = Doc::fill(&["a", "bb", "ccc", "dddd", "eeeee"].map(Doc::text));
+    let wide = Doc::sep(["名前", "café", "日本語テキスト", "naïve"].map(Doc::text));
+    // Before `V2`, lines dedented past the margin are measured from their nesting.
+    let aligned = Doc::text("ab ").concat(Doc::vsep(["c", "d"].map(Doc::text)).align());
+    let dedent = Doc::text("{").concat(Doc::line().concat(aligned).dedent(2));
+    vec![
+        ("json", json_doc_pretty(&json)),
+        ("xml", xml_doc_pretty(&xml)),
+        ("exp", expr_doc_pretty(&exp)),
+        ("words", words),
+        ("call", call.concat(Doc::text(")"))),
+        ("prefixed", prefixed),
+        ("quoted", quoted),
+        ("fill", fill),
+        ("wide", wide),
+        ("dedent", dedent),
+    ]
+}
+
+fn render_all(doc: &Doc, version: LayoutVersion) -> String {
+    let renders = WIDTHS.map(|width| {
+        let options = RenderOptions::new(width).layout_version(version);
+        format!("-- width {width}\n{}", doc.clone().render_with(&options))
+    });
+    renders.join("\n")
+}
+
+// -------------------------------------------------------------------------------------------------
+// Tests
+// -------------------------------------------------------------------------------------------------
+
+#[test]
+fn golden_v1() {
+    for (name, doc) in corpus() {
+        assert_snapshot!(format!("v1_{name}"), render_all(&doc, LayoutVersion::V1));
+    }
+}
+
+#[test]
+fn golden_v2() {
+    for (name, doc) in corpus() {
+        assert_snapshot!(format!("v2_{name}"), render_all(&doc, LayoutVersion::V2));
+    }
+}
+
+#[test]
+fn golden_v3() {
+    for (name, doc) in corpus() {
+        assert_snapshot!(format!("v3_{name}"), render_all(&doc, LayoutVersion::V3));
+    }
+}
+
+#[test]
+fn default_is_v1() {
+    for (_, doc) in corpus() {
+        for width in WIDTHS {
+            let options = RenderOptions::new(width).layout_version(LayoutVersion::V1);
+            assert_eq!(doc.clone().render(width), doc.clone().render_with(&options));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{examples::json::*, *};
+
+// -------------------------------------------------------------------------------------------------
+// Tests
+// -------------------------------------------------------------------------------------------------
+
+mod tests {
+    use super::*;
+    use insta::assert_snapshot;
+
+    fn record(id: i64, tags: &[&str]) -> Json {
+        Json::object(vec![
+            ("id", Json::Int(id)),
+            ("ok", Json::Bool(id % 2 == 0)),
+            (
+                "tags",
+                Json::Array(tags.iter().map(|t| Json::string(*t)).collect()),
+            ),
+        ])
+    }
+
+    fn json_lines(records: &[Json], width: Width, pretty_oversized: bool) -> String {
+        let mut out = vec![];
+        write_json_lines(&mut out, records, width, pretty_oversized).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn j1_nested() {
+        let json = Json::object(vec![
+            ("name", Json::string("pretty \"simple\"")),
+            ("version", Json::Float(0.5)),
+            ("deps", Json::Array(vec![])),
+            ("nested", record(7, &["a", "b"])),
+        ]);
+        assert_snapshot!("j1_compact", json_doc_compact(&json).render(120));
+        assert_snapshot!("j1_pretty", json_doc_pretty(&json).render(30));
+    }
+
+    #[test]
+    fn j2_json_lines() {
+        let records = vec![
+            record(1, &["short"]),
+            record(2, &["a", "much", "longer", "list", "of", "tags"]),
+            Json::Null,
+        ];
+        assert_snapshot!("j2_compact", json_lines(&records, 45, false));
+        assert_snapshot!("j2_pretty_oversized", json_lines(&records, 45, true));
+    }
+
+    #[test]
+    fn j3_number_format() {
+        let json = Json::Array(vec![
+            Json::Int(1_000_000),
+            Json::Float(2.0),
+            Json::Float(0.000123),
+            Json::Float(6.02e23),
+            Json::Float(-1234.5678),
+        ]);
+        let numbers = NumberFormat::new()
+            .trailing_zero(true)
+            .exponent_thresholds(3, 9)
+            .separator('_', 3);
+        assert_snapshot!("j3_default", json_doc_pretty(&json).render(120));
+        assert_snapshot!("j3_custom", json_doc_with(&json, &numbers).render(120));
+    }
+}
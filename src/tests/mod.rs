@@ -4,6 +4,14 @@
 #[cfg(test)]
 mod exp;
 #[cfg(test)]
+mod golden;
+#[cfg(test)]
+mod json;
+#[cfg(test)]
+mod stack_safety;
+#[cfg(test)]
+mod template;
+#[cfg(test)]
 mod unit_tests;
 #[cfg(test)]
 mod xml;
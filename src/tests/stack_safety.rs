@@ -0,0 +1,75 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rendering, flattening, and dropping documents must never recurse proportionally to document
+//! size. These tests build documents far deeper than any thread stack could handle recursively.
+
+use crate::*;
+
+const DEPTH: usize = 1_000_000;
+
+/// Deep enough to overflow any thread stack if handled recursively, but cheaper than `DEPTH`.
+const SHALLOWER: usize = 200_000;
+
+/// A left-leaning spine `DEPTH` levels deep whose every level also hangs a shared, grouped
+/// subtree of nine nodes off to the right: ten million nodes reachable from the root.
+fn deep_and_wide() -> Doc {
+    let leaf = Doc::hsep([Doc::text("a"), Doc::text("b"), Doc::text("c")]).group();
+    let mut doc = Doc::nil();
+    for i in 0..DEPTH {
+        let sep = if i % 8 == 0 {
+            Doc::line()
+        } else {
+            Doc::softline()
+        };
+        doc = doc.concat(sep.concat(leaf.clone()));
+    }
+    doc
+}
+
+#[test]
+fn render_deep_and_wide() {
+    let doc = deep_and_wide();
+    let output = doc.clone().render(40);
+    assert_eq!(output.matches("a b c").count(), DEPTH);
+    // Dropping the last reference to a deep document must not recurse either.
+    drop(doc);
+}
+
+#[test]
+fn flatten_and_group_deep() {
+    let mut doc = Doc::text("x");
+    for _ in 0..SHALLOWER {
+        doc = Doc::line().concat(doc).nest(0).group();
+    }
+    let flat = doc.clone().flatten().render(10);
+    assert_eq!(flat.len(), SHALLOWER + 1);
+    assert_eq!(doc.render(10).lines().count(), SHALLOWER - 8);
+}
+
+#[test]
+fn fill_many_items() {
+    let words: Vec<Doc> = (0..SHALLOWER).map(|i| Doc::text(i.to_string())).collect();
+    let output = Doc::fill(&words).render(80);
+    assert!(output.lines().all(|line| line.len() <= 80));
+}
+
+#[test]
+fn abandon_layout_midway() {
+    let mut doc = Doc::nil();
+    for i in 0..SHALLOWER {
+        doc = doc.concat(Doc::text(i.to_string()).concat(Doc::line()));
+    }
+    let first: Vec<RenderEvent> = doc.layout(80).take(4).collect();
+    assert_eq!(first[0], RenderEvent::Text("0".into()));
+}
+
+#[test]
+fn map_annotations_deep() {
+    let mut doc = Doc::<&str>::text("x");
+    for _ in 0..SHALLOWER {
+        doc = Doc::annotate("tag", doc.concat(Doc::<&str>::text("y")));
+    }
+    let mapped = doc.map_annotations(|tag| tag.to_string());
+    assert_eq!(mapped.strip_annotations().render(80).len(), SHALLOWER + 1);
+}
@@ -0,0 +1,48 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use insta::assert_snapshot;
+
+use crate::*;
+
+#[test]
+fn template_holes_and_braces() {
+    let cond = Doc::text("ready");
+    let body = Doc::text("go()");
+    let doc = doc_template!("if {cond} {{ {body} }}");
+    assert_eq!(doc.render(80), "if ready { go() }");
+}
+
+#[test]
+fn template_layout_hints() {
+    let cond = Doc::text("x > 0");
+    let stmts = Doc::vsep([Doc::text("step_one();"), Doc::text("step_two();")]);
+    let doc = doc_template!("{[}if {cond} {{{>}{~}{stmts}{<}{~}}}{]}");
+    assert_snapshot!(doc.render(20))
+}
+
+#[test]
+fn template_group_flattens() {
+    let args = Doc::intersperse(
+        ["alpha", "beta"].into_iter().map(Doc::text),
+        Doc::comma().concat(Doc::softline()),
+    );
+    let doc = doc_template!("{[}call({>2}{-}{args}{<}{-}){]}");
+    assert_eq!(doc.clone().render(80), "call(alpha, beta)");
+    assert_eq!(doc.render(5), "call(\n  alpha,\n  beta)");
+}
+
+#[test]
+fn template_annotated_holes() {
+    let keyword = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("let"));
+    let value = Doc::annotate(Semantic::Literal, Doc::<Semantic>::text("42"));
+    let name = Doc::text("answer");
+    let doc: Doc<Semantic> = doc_template!("{[}{keyword} {name} ={>}{/}{value};{<}{]}");
+    assert_eq!(doc.clone().render(80), "let answer = 42;");
+    assert_eq!(doc.render(12), "let answer =\n    42;");
+    let doc = doc_template!("{keyword} {name}");
+    assert_eq!(
+        doc.render_html(80),
+        "<span class=\"keyword\">let</span> answer"
+    );
+}
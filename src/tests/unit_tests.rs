@@ -41,7 +41,7 @@ fn stack_stress() {
     //          , ...]
     //
     // Lengths grow so some groups barely fit while others don't.
-    fn group_with_k_items(k: usize, base: usize, indent: i16) -> Doc {
+    fn group_with_k_items(k: usize, base: usize, indent: Width) -> Doc {
         let items: Vec<Doc> = (0..k)
             .map(|i| {
                 // Vary the payload length to create tight fit/no-fit edges.
@@ -79,7 +79,7 @@ fn stack_stress() {
             // Increasing item count + base length create rising pressure.
             let k = 3 + (g % 9); // between 3 and 11 items
             let base = 4 + (g % 13); // item payload base length
-            let indent = 2 + (g % 6) as i16;
+            let indent = 2 + (g % 6) as Width;
 
             let grp = group_with_k_items(k, base, indent);
 
@@ -97,7 +97,7 @@ fn stack_stress() {
             let deep = {
                 let inner = Doc::line().concat(Doc::text("inner")).braces();
                 // group the inner too
-                Doc::alt(inner.clone().flatten(), inner.nest(2 + (g % 4) as i16))
+                Doc::alt(inner.clone().flatten(), inner.nest(2 + (g % 4) as Width))
             };
             blocks.push(Doc::hcat(vec![grp, Doc::space(), deep]));
         }
@@ -105,7 +105,7 @@ fn stack_stress() {
     }
 
     let doc = build_alt_stress(1000);
-    let widths = [20_i16, 32, 48, 64, 96, 140];
+    let widths = [20, 32, 48, 64, 96, 140];
     for &w in &widths {
         let cloned = doc.clone();
         let _render = cloned.render(w);
@@ -142,3 +142,2835 @@ fn stack_stress_2() {
     ));
     let _render = doc.render(100);
 }
+
+#[test]
+fn indent_unit() {
+    let body = Doc::vsep([
+        Doc::text("one"),
+        Doc::text("two")
+            .concat(Doc::line().concat(Doc::text("three")).nest(4))
+            .concat(Doc::line().concat(Doc::text("four")).nest(6)),
+    ]);
+    let doc = Doc::text("root").concat(Doc::line().concat(body).nest(4));
+    let tabs = RenderOptions::new(80).indent("\t", 4);
+    let guides = RenderOptions::new(80).indent("|   ", 4);
+    assert_eq!(
+        doc.clone().render_with(&tabs),
+        "root\n\tone\n\ttwo\n\t\tthree\n\t\t  four"
+    );
+    assert_snapshot!(doc.clone().render_with(&guides));
+    let guides = RenderOptions::new(80).indent_guides(4);
+    assert_snapshot!(doc.render_with(&guides), @r"
+    root
+    │   one
+    │   two
+    │   │   three
+    │   │     four
+    ");
+}
+
+#[test]
+fn trim_trailing_whitespace() {
+    let doc = Doc::text("key:")
+        .concat(Doc::space())
+        .concat(Doc::alt(
+            Doc::text("a very long value that does not fit"),
+            Doc::line().concat(Doc::text("value")).nest(2),
+        ))
+        .concat(Doc::line().nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("end"));
+    assert_eq!(doc.clone().render(20), "key: \n  value\n    \nend");
+    let options = RenderOptions::new(20).trim_trailing_whitespace(true);
+    assert_eq!(doc.render_with(&options), "key:\n  value\n\nend");
+}
+
+#[test]
+fn trim_trailing_whitespace_inside_markup() {
+    let bold = Style::new().bold(true);
+    let doc = Doc::<Style>::text("a ")
+        .styled(bold)
+        .concat(Doc::<Style>::text(" ").styled(bold))
+        .concat(Doc::<Style>::line_break())
+        .concat(Doc::<Style>::text("b"));
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_eq!(doc.render_ansi_with(&options), "\x1b[1ma\x1b[0m\nb");
+    let doc = Doc::annotate("kw", Doc::<&str>::text("if "))
+        .concat(Doc::<&str>::line_break())
+        .concat(Doc::<&str>::text("x"));
+    assert_eq!(
+        doc.render_html_with(&options),
+        "<span class=\"kw\">if</span>\nx"
+    );
+}
+
+#[test]
+fn number_format() {
+    let fixed = NumberFormat::new().precision(3);
+    assert_eq!(fixed.format_float(1.0), "1.000");
+    assert_eq!(fixed.format_float(-0.0005), "-0.001");
+    let sci = NumberFormat::new().exponent_thresholds(2, 6).precision(2);
+    assert_eq!(sci.format_float(1234567.0), "1.23e6");
+    assert_eq!(sci.format_float(0.001), "1.00e-3");
+    assert_eq!(sci.format_float(12.5), "12.50");
+    let bin = NumberFormat::new().radix(Radix::Binary).separator('_', 4);
+    assert_eq!(bin.format_int(-37), "-0b10_0101");
+    assert_eq!(NumberFormat::new().format_float(f64::NAN), "NaN");
+}
+
+#[test]
+fn render_into_reuses_buffer() {
+    let doc = Doc::sep(["alpha", "beta", "gamma"].into_iter().map(Doc::text));
+    let mut buffer = String::new();
+    for width in [80, 5] {
+        buffer.clear();
+        doc.render_into(width, &mut buffer);
+        assert_eq!(buffer, doc.clone().render(width));
+    }
+    buffer.push_str(" | ");
+    doc.render_into(80, &mut buffer);
+    assert_eq!(buffer, "alpha\nbeta\ngamma | alpha beta gamma");
+}
+
+#[test]
+fn sharing_report_and_intern() {
+    // Each row rebuilds the same boilerplate from scratch.
+    let row = |i: usize| {
+        Doc::text("#[derive(Debug)]")
+            .concat(Doc::line())
+            .concat(Doc::text(format!("struct S{i};")))
+    };
+    let doc = Doc::vsep((0..100).map(row));
+    let report = doc.sharing_report();
+    assert_eq!(report.nodes - report.distinct, report.redundant_nodes);
+    assert!(report.redundant_nodes >= 99 * 2);
+    assert!(report.redundant_text_bytes >= 99 * "#[derive(Debug)]".len());
+    assert!(report.estimated_savings() > report.redundant_text_bytes);
+
+    let interned = doc.intern();
+    let after = interned.sharing_report();
+    assert_eq!(after.redundant_nodes, 0);
+    assert_eq!(after.distinct, report.distinct);
+    assert_eq!(interned.render(80), doc.render(80));
+}
+
+#[test]
+fn try_render() {
+    let doc = Doc::hsep(["a", "b"].into_iter().map(Doc::text));
+    assert_eq!(doc.clone().try_render(80).unwrap(), doc.render(80));
+    let err: Box<dyn std::error::Error> = Box::new(RenderError::Fmt(std::fmt::Error));
+    assert!(err.to_string().contains("formatting error"));
+    assert!(err.source().is_some());
+}
+
+#[test]
+fn layout_events_are_lazy() {
+    // Only the first line is laid out, so the column closure is never reached.
+    let doc = Doc::text("first")
+        .concat(Doc::line())
+        .concat(Doc::column(|_| panic!("laid out too eagerly")));
+    let events: Vec<RenderEvent> = doc.layout(80).take(2).collect();
+    assert_eq!(
+        events,
+        vec![
+            RenderEvent::Text("first".into()),
+            RenderEvent::Newline { indent: 0 }
+        ]
+    );
+}
+
+#[test]
+fn memoize_shared_subtree() {
+    use std::{cell::Cell, rc::Rc};
+
+    // Count how often the shared cell is laid out via a column probe inside it.
+    let layouts = Rc::new(Cell::new(0));
+    let counter = layouts.clone();
+    let cell = Doc::column(move |_| {
+        counter.set(counter.get() + 1);
+        Doc::sep(["shared", "cell", "contents"].into_iter().map(Doc::text))
+    });
+    let table = |cell: Doc| {
+        Doc::vsep((0..50).map(|i| Doc::text(format!("row {i:02}: ")).concat(cell.clone().align())))
+    };
+
+    let plain = table(cell.clone()).render(20);
+    let plain_layouts = layouts.replace(0);
+    let memoized = table(cell.clone().memoize()).render(20);
+    assert_eq!(plain, memoized);
+    assert!(layouts.get() < plain_layouts);
+    assert_eq!(layouts.get(), 1);
+
+    // Inside a prefixed region, the cached layout keeps the prefixes of the lines it breaks.
+    let quoted = |cell: Doc| Doc::quoted_block("// ", table(cell)).render(20);
+    let plain = quoted(cell.clone());
+    layouts.set(0);
+    let cell = cell.memoize();
+    assert_eq!(quoted(cell.clone()), plain);
+    assert_eq!(layouts.get(), 1);
+    // Later renders with the same options reuse the cached layout.
+    assert_eq!(quoted(cell), plain);
+    assert_eq!(layouts.get(), 1);
+}
+
+#[test]
+fn render_lines() {
+    let doc = Doc::text("fn main() {")
+        .concat(Doc::line().concat(Doc::text("body();")).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    let numbered: Vec<String> = doc
+        .render_lines(80)
+        .enumerate()
+        .map(|(i, line)| format!("{:>2} | {line}", i + 1))
+        .collect();
+    assert_snapshot!(numbered.join("\n"));
+    let lines: Vec<String> = doc.render_lines(80).collect();
+    assert_eq!(
+        lines,
+        doc.clone().render(80).split('\n').collect::<Vec<_>>()
+    );
+    assert_eq!(Doc::nil().render_lines(80).collect::<Vec<_>>(), vec![""]);
+    let options = RenderOptions::new(80).indent("\t", 4).line_numbers(1);
+    let lines: Vec<String> = doc.render_lines_with(&options).collect();
+    assert_eq!(
+        lines,
+        doc.render_with(&options).split('\n').collect::<Vec<_>>()
+    );
+    assert_eq!(lines[1], "2 | \tbody();");
+}
+
+#[test]
+fn lines() {
+    let template = Doc::lines(
+        r#"
+        match x {
+            Some(y) => y,
+
+            None => 0,
+        }
+        "#,
+    );
+    let doc = Doc::text("let z =")
+        .concat(Doc::line().concat(template).nest(4))
+        .concat(Doc::text(";"));
+    assert_snapshot!(doc.render(80));
+    assert_eq!(Doc::lines("a\n  b").render(80), "a\n  b");
+    assert_eq!(Doc::lines("").render(80), "");
+}
+
+#[test]
+fn smart_lookahead() {
+    // Either hug the block after `call(`, or break it onto its own, shallower lines.
+    let block = Doc::vsep([
+        Doc::text("first_item,"),
+        Doc::text("second_item_that_is_long"),
+    ]);
+    let hugged = Doc::text("call(")
+        .concat(block.clone().align())
+        .concat(Doc::rparen());
+    let broken = Doc::text("call(")
+        .concat(Doc::line().concat(block).nest(2))
+        .concat(Doc::line())
+        .concat(Doc::rparen());
+    let doc = hugged.alt(broken);
+
+    // The hugged first line fits, so plain lookahead takes it and the nested line overflows.
+    let plain = doc.clone().render(28);
+    assert!(plain.lines().any(|line| line.len() > 28));
+    let smart = doc.render_with(&RenderOptions::new(28).smart_lookahead(true));
+    assert!(smart.lines().all(|line| line.len() <= 28));
+    assert_snapshot!(format!("{plain}\n---\n{smart}"));
+}
+
+#[test]
+fn natural_width() {
+    let pair = |x: &str, y: &str| Doc::sep([Doc::text(x), Doc::text(y)]);
+    let doc = Doc::sep([pair("alpha", "beta"), pair("gamma", "delta")]);
+    assert_eq!(doc.natural_width(80), 22);
+    // Two lines is the best we can do in 15 columns, and "gamma delta" needs 11 of them.
+    let width = doc.natural_width(15);
+    assert_eq!(width, 11);
+    assert_eq!(doc.clone().render(width), "alpha beta\ngamma delta");
+    assert_eq!(doc.clone().render(width - 1).lines().count(), 3);
+}
+
+#[test]
+fn oppen_algorithm() {
+    let doc = Doc::sep([Doc::text("x"), Doc::text("y")])
+        .concat(Doc::softline())
+        .concat(Doc::text("zzzzzzzz"));
+    let wadler = doc.clone().render(6);
+    let oppen = doc.render_with(&RenderOptions::new(6).algorithm(LayoutAlgorithm::Oppen));
+    // Wadler breaks the group because it measures through the following softline as though it
+    // were a space; Oppen only measures up to that break opportunity.
+    assert_snapshot!(format!("{wadler}\n---\n{oppen}"));
+
+    // Each softline only has to fit the word after it, so words are packed greedily.
+    let words = (0..30).map(|i| Doc::text(format!("w{i}")));
+    let packed = Doc::concat_with(words, |x, y| x.concat(Doc::softline()).concat(y))
+        .render_with(&RenderOptions::new(20).algorithm(LayoutAlgorithm::Oppen));
+    assert_snapshot!(packed);
+}
+
+#[test]
+fn quoted_block() {
+    let words = |s: &str| Doc::sep(s.split(' ').map(Doc::text));
+    let code = Doc::text("fn main() {")
+        .concat(Doc::line().concat(Doc::text("body();")).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    let original = Doc::vsep([words("can you take a look at this?"), Doc::nil(), code]);
+    let reply = Doc::vsep([
+        Doc::quoted_block("> ", original),
+        Doc::nil(),
+        words("looks good to me"),
+    ]);
+    let doc =
+        Doc::text("Thread:").concat(Doc::line().concat(Doc::quoted_block("> ", reply)).nest(2));
+    let output = doc.render(24);
+    assert!(output.lines().all(|line| line.len() <= 24));
+    assert_snapshot!(output);
+}
+
+#[test]
+fn fit_policy() {
+    // Wadler's ribbon: at most this many columns of non-indentation text per line.
+    struct Ribbon(Width);
+
+    impl FitPolicy for Ribbon {
+        fn line_fits(&self, line: &LineFit) -> bool {
+            line.remaining() >= 0 && line.column - line.indent <= self.0
+        }
+    }
+
+    let items = Doc::sep(["alpha", "beta", "gamma"].map(Doc::text));
+    let doc = Doc::text("items:").concat(Doc::line().concat(items).nest(8));
+    let loose = doc.clone().render(80);
+    let ribbon = doc.render_with(&RenderOptions::new(80).fit_policy(Ribbon(12)));
+    assert_snapshot!(format!("{loose}\n---\n{ribbon}"));
+}
+
+#[test]
+fn annotate() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Style {
+        Keyword,
+        Name,
+    }
+
+    impl Annotation for Style {}
+
+    let doc = Doc::sep([
+        Doc::annotate(Style::Keyword, Doc::<Style>::text("let")),
+        Doc::annotate(Style::Name, Doc::<Style>::text("x")),
+    ]);
+    // The group is flattened, and the annotations come along with it.
+    let flat: Vec<RenderEvent<Style>> = doc.clone().layout(80).collect();
+    assert_eq!(
+        flat,
+        vec![
+            RenderEvent::PushAnnotation(Style::Keyword),
+            RenderEvent::Text("let".into()),
+            RenderEvent::PopAnnotation,
+            RenderEvent::Text(" ".into()),
+            RenderEvent::PushAnnotation(Style::Name),
+            RenderEvent::Text("x".into()),
+            RenderEvent::PopAnnotation,
+        ]
+    );
+    let broken: Vec<RenderEvent<Style>> = doc.clone().layout(4).collect();
+    assert!(broken.contains(&RenderEvent::Newline { indent: 0 }));
+    assert_eq!(broken.len(), 7);
+    // Plain rendering ignores annotations.
+    assert_eq!(doc.clone().render(80), "let x");
+    assert_eq!(doc.render(4), "let\nx");
+}
+
+#[test]
+fn words() {
+    let doc: Doc = Doc::text_trimmed("  Note: ").concat_space(Doc::words(
+        "the   quick brown\n\tfox jumps over  the lazy dog ",
+    ));
+    assert_eq!(
+        doc.clone().render(80),
+        "Note: the quick brown fox jumps over the lazy dog"
+    );
+    assert_snapshot!(doc.render_with(&RenderOptions::new(20).algorithm(LayoutAlgorithm::Oppen)));
+    assert_eq!(<Doc>::words(" \n ").render(80), "");
+}
+
+#[test]
+fn ansi_styles() {
+    let error = Style::new().bold(true).fg(Color::Red);
+    let path = Style::new().underline(true);
+    let code = |s: &str| Doc::<Style>::text(s).styled(Style::new().fg(Color::Fixed(208)));
+    let message = Doc::sep([
+        Doc::<Style>::text("error")
+            .styled(error)
+            .concat(Doc::<Style>::text(":")),
+        Doc::<Style>::text("mismatched types in").concat_space(code("let x: u8 = \"hi\"")),
+        Doc::<Style>::text("at").concat_space(Doc::<Style>::text("src/main.rs").styled(path)),
+    ]);
+    let doc = message
+        .nest(2)
+        .styled(Style::new().bg(Color::Rgb(0, 0, 64)));
+
+    // The escape codes don't count towards the width, so this still fits on one line.
+    let plain_width = doc.clone().render(80).len() as Width;
+    let flat = doc.clone().render_ansi(plain_width);
+    assert!(!flat.contains('\n'));
+    let broken = doc.render_ansi(plain_width - 1);
+    assert_snapshot!(format!("{flat}\n---\n{broken}").replace('\x1b', "\\e"));
+}
+
+#[test]
+fn annotation_width() {
+    // A Markdown-style back end that wraps emphasized text in `_`s.
+    #[derive(Clone)]
+    struct Emphasis;
+
+    impl Annotation for Emphasis {
+        fn extra_width_flat(&self) -> Width {
+            2
+        }
+        fn extra_width_broken(&self) -> Width {
+            1
+        }
+    }
+
+    fn markdown(doc: Doc<Emphasis>, width: Width) -> String {
+        let mut out = String::new();
+        for event in doc.layout(width) {
+            match event {
+                RenderEvent::Text(s) => out.push_str(&s),
+                RenderEvent::Newline { indent } => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent as usize));
+                }
+                RenderEvent::PushAnnotation(Emphasis) | RenderEvent::PopAnnotation => out.push('_'),
+            }
+        }
+        out
+    }
+
+    let emphasized = Doc::annotate(
+        Emphasis,
+        Doc::sep([Doc::<Emphasis>::text("really"), Doc::<Emphasis>::text("do")]),
+    );
+    let doc = Doc::sep([
+        Doc::<Emphasis>::text("you"),
+        emphasized,
+        Doc::<Emphasis>::text("this"),
+    ]);
+    let flat = markdown(doc.clone(), 21);
+    assert_eq!(flat, "you _really do_ this");
+    assert!(flat.len() <= 21);
+    // Without the markup, the text alone would fit in 18 columns.
+    assert_snapshot!(markdown(doc, 18));
+
+    // Columns after the markup account for it.
+    let column = Doc::annotate(Emphasis, Doc::<Emphasis>::text("x"))
+        .concat(Doc::column(|c| Doc::<Emphasis>::text(format!(" at {c}"))));
+    assert_eq!(markdown(column.clone().group(), 80), "_x_ at 3");
+    assert_eq!(markdown(column, 80), "_x_ at 3");
+}
+
+#[test]
+fn render_html() {
+    let tag = |s: &str| Doc::annotate("tag", Doc::<&str>::text(s));
+    let attr = Doc::annotate("attr", Doc::<&str>::text("href"))
+        .concat(Doc::<&str>::text("="))
+        .concat(Doc::annotate("string", Doc::<&str>::text("\"a&b\"")));
+    let body = Doc::<&str>::text("<")
+        .concat(tag("a"))
+        .concat_space(attr)
+        .concat(Doc::<&str>::text(">"));
+    let doc = Doc::<&str>::text("html!").concat(Doc::<&str>::line().concat(body).nest(2).group());
+    assert_snapshot!(format!(
+        "{}\n---\n{}",
+        doc.render_html(80),
+        doc.render_html(10)
+    ));
+
+    let style = Style::new().bold(true).fg(Color::Rgb(255, 136, 0));
+    let styled = Doc::<Style>::text("x")
+        .styled(style)
+        .concat(Doc::<Style>::text("y").styled(Style::new()));
+    assert_eq!(
+        styled.render_html(80),
+        "<span class=\"bold fg-rgb-ff8800\">x</span>y"
+    );
+}
+
+#[test]
+fn render_markdown() {
+    #[derive(Clone)]
+    enum Report {
+        Heading,
+        Code,
+    }
+
+    impl Annotation for Report {}
+
+    impl MarkdownStyle for Report {
+        fn markdown(&self) -> Vec<Markdown> {
+            match self {
+                Report::Heading => vec![Markdown::Strong, Markdown::Emphasis],
+                Report::Code => vec![Markdown::Code],
+            }
+        }
+    }
+
+    let code = |s: &str| Doc::annotate(Report::Code, Doc::<Report>::text(s));
+    let findings = Doc::sep([
+        Doc::<Report>::text("- replace").concat_space(code("a_b * 2")),
+        Doc::<Report>::text("- with").concat_space(code("a_b << 1")),
+        Doc::<Report>::text("- see [docs]"),
+    ]);
+    let title = Doc::annotate(
+        Report::Heading,
+        Doc::<Report>::text("Lint #3:").concat_space(code("x")),
+    );
+    let doc = title.concat(Doc::<Report>::line().concat(findings).nest(2));
+    assert_snapshot!(format!(
+        "{}\n---\n{}",
+        doc.render_markdown(80),
+        doc.render_markdown(40)
+    ));
+}
+
+#[test]
+fn color_choice() {
+    let doc = Doc::<Style>::text("ok").styled(Style::new().fg(Color::Green));
+    let render = |color| {
+        doc.clone()
+            .render_ansi_with(&RenderOptions::new(80).color(color))
+    };
+    assert_eq!(render(ColorChoice::Always), "\x1b[32mok\x1b[0m");
+    assert_eq!(render(ColorChoice::Never), "ok");
+    assert_eq!(render(ColorChoice::default()), doc.render_ansi(80));
+    // Without a terminal (e.g., in CI), `Auto` leaves the escapes out.
+    if !std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+        assert_eq!(render(ColorChoice::Auto), "ok");
+    }
+}
+
+#[test]
+fn write_to() {
+    // Accepts `capacity` bytes, then fails.
+    struct Limited {
+        written: Vec<u8>,
+        capacity: usize,
+    }
+
+    impl std::io::Write for Limited {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.written.len() + buf.len() > self.capacity {
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let item = |s: &str| Doc::annotate("item", Doc::<&str>::text(s));
+    let items = Doc::annotate("list", Doc::vsep([item("a"), item("b")]));
+    let doc = Doc::<&str>::text("list:").concat(Doc::<&str>::line().concat(items).nest(2));
+    let mut out = vec![];
+    doc.write_to(80, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), doc.clone().render(80));
+
+    let mut out = Limited {
+        written: vec![],
+        capacity: 12,
+    };
+    let err = doc.write_to(80, &mut out).unwrap_err();
+    let position = RenderPosition {
+        lines: 2,
+        bytes: 10,
+        open_annotations: vec!["\"list\"".to_string()],
+    };
+    assert_eq!(err.position(), Some(&position));
+    assert_eq!(out.written, b"list:\n  a\n");
+    assert_eq!(
+        err.to_string(),
+        "I/O error after writing 2 lines (10 bytes) inside \"list\": disk full"
+    );
+}
+
+#[test]
+fn breakable_text() {
+    let url = Doc::breakable_text(
+        "https://example.com/some/rather-long/path/to-a/resource.html",
+        &["/", "-"],
+    );
+    let doc: Doc = Doc::text("see:").concat(Doc::line().concat(url).nest(4));
+    let wadler = doc.clone().render(30);
+    let oppen = doc.render_with(&RenderOptions::new(30).algorithm(LayoutAlgorithm::Oppen));
+    assert_snapshot!(format!("{wadler}\n---\n{oppen}"));
+
+    // Runs of separators stay together, and there are no empty pieces.
+    let doc: Doc = Doc::breakable_text("/a//b-/", &["/", "-", ""]);
+    assert_eq!(doc.clone().render(80), "/a//b-/");
+    assert_eq!(doc.render(1), "/\na//\nb-/");
+}
+
+#[test]
+fn annotations_across_line_breaks() {
+    use RenderEvent::*;
+
+    let text = |s: &str| Doc::<&str>::text(s);
+    let inner = Doc::annotate(
+        "inner",
+        text("a").concat(Doc::<&str>::line()).concat(text("b")),
+    );
+    let doc = Doc::annotate("outer", Doc::quoted_block("> ", inner));
+    let events: Vec<RenderEvent<&str>> = doc.layout(80).collect();
+    assert_eq!(
+        events,
+        vec![
+            PushAnnotation("outer"),
+            Text("> ".into()),
+            PushAnnotation("inner"),
+            Text("a".into()),
+            PopAnnotation,
+            PopAnnotation,
+            Newline { indent: 0 },
+            Text("> ".into()),
+            PushAnnotation("outer"),
+            PushAnnotation("inner"),
+            Text("b".into()),
+            PopAnnotation,
+            PopAnnotation,
+        ]
+    );
+
+    // Memoized subtrees are laid out on their own, but their events still nest inside the
+    // enclosing regions.
+    let shared = Doc::annotate(
+        "item",
+        text("x").concat(Doc::<&str>::line()).concat(text("y")),
+    )
+    .memoize();
+    let doc = Doc::annotate("list", Doc::vsep([shared.clone(), shared]).nest(2));
+    let mut open = vec![];
+    for event in doc.layout(80) {
+        match event {
+            PushAnnotation(a) => open.push(a),
+            PopAnnotation => assert!(open.pop().is_some()),
+            Newline { .. } => assert!(open.is_empty()),
+            Text(_) => assert!(!open.is_empty()),
+        }
+    }
+    assert!(open.is_empty());
+}
+
+#[test]
+fn render_metrics() {
+    let items = |n: usize| -> Doc { Doc::sep((0..n).map(|i| Doc::text(format!("item{i}")))) };
+    let options = RenderOptions::new(40);
+    let (output, small) = items(3).render_with_metrics(&options);
+    assert_eq!(output, items(3).render(40));
+    assert_eq!(small.fits_probes, 1);
+    assert!(small.steps > 0 && small.peak_work_list > 0);
+
+    // Every nested group measures past the inner ones, so lookahead grows faster than the
+    // document does.
+    let nested =
+        |depth: usize| (0..depth).fold(items(2), |doc, _| Doc::sep([Doc::text("x"), doc.nest(2)]));
+    let (_, shallow) = nested(5).render_with_metrics(&options);
+    let (_, deep) = nested(50).render_with_metrics(&options);
+    assert!(deep.fits_probes > shallow.fits_probes);
+    assert!(deep.lookahead_steps > 10 * shallow.lookahead_steps);
+}
+
+#[test]
+fn map_annotations() {
+    #[derive(Clone, Debug, PartialEq)]
+    enum Tag {
+        Keyword,
+        Comment,
+    }
+
+    impl Annotation for Tag {}
+
+    let keyword = Doc::annotate(Tag::Keyword, Doc::<Tag>::text("fn"));
+    let comment = Doc::annotate(Tag::Comment, Doc::<Tag>::text("// hi"));
+    let body =
+        Doc::nesting(move |i| Doc::annotate(Tag::Keyword, Doc::<Tag>::text(format!("at{i}"))));
+    let doc = Doc::vsep([keyword.clone(), comment, keyword, body.nest(2)]);
+
+    // Comments aren't styled, so they map to plain regions.
+    let styled = doc.map_annotations(|tag| match tag {
+        Tag::Keyword => Style::new().bold(true),
+        Tag::Comment => Style::new(),
+    });
+    assert_eq!(
+        styled.clone().render_ansi(80),
+        "\x1b[1mfn\x1b[0m\n// hi\n\x1b[1mfn\x1b[0m\n\x1b[1mat2\x1b[0m"
+    );
+    // The shared `keyword` subtree is still shared.
+    assert_eq!(styled.sharing_report().nodes, doc.sharing_report().nodes);
+
+    let plain = doc.strip_annotations();
+    assert_eq!(plain.clone().render(80), "fn\n// hi\nfn\nat2");
+    assert!(plain
+        .layout(80)
+        .all(|event| !matches!(event, RenderEvent::PushAnnotation(_))));
+}
+
+#[test]
+fn render_with_map() {
+    // Node ids: 0 is the call, 1 the callee, and 2 and 3 its arguments.
+    let node = |id: usize, doc: Doc<usize>| Doc::annotate(id, doc);
+    let args = Doc::intersperse(
+        [
+            node(2, Doc::<usize>::text("first")),
+            node(3, Doc::<usize>::text("second")),
+        ],
+        Doc::<usize>::text(",").concat(Doc::<usize>::line()),
+    );
+    let call = node(
+        0,
+        node(1, Doc::<usize>::text("call"))
+            .concat(Doc::<usize>::text("("))
+            .concat(
+                Doc::<usize>::line()
+                    .concat(args)
+                    .nest(4)
+                    .concat(Doc::<usize>::line())
+                    .group(),
+            )
+            .concat(Doc::<usize>::text(")")),
+    );
+    let (output, map) = call.render_with_map(10);
+    assert_eq!(output, "call(\n    first,\n    second\n)");
+
+    let at = |line, column| {
+        let ids: Vec<usize> = map.at(OutputPosition { line, column }).copied().collect();
+        ids
+    };
+    assert_eq!(at(0, 0), vec![1, 0]);
+    assert_eq!(at(1, 6), vec![2, 0]);
+    assert_eq!(at(2, 4), vec![3, 0]);
+    assert_eq!(at(3, 0), vec![0]);
+    assert_eq!(at(3, 1), Vec::<usize>::new());
+
+    let second = map.annotation_at(2, 4).unwrap();
+    assert_eq!(second.annotation, 3);
+    assert_eq!(second.start, OutputPosition { line: 2, column: 4 });
+    assert_eq!(
+        second.end,
+        OutputPosition {
+            line: 2,
+            column: 10
+        }
+    );
+    assert_eq!(map.annotation_at(1, 2).map(|span| span.annotation), Some(0));
+    assert!(map.annotation_at(3, 1).is_none());
+    assert!(map.annotation_at(9, 0).is_none());
+
+    let call_span = &map.spans()[0];
+    assert_eq!(call_span.annotation, 0);
+    assert_eq!(call_span.start, OutputPosition { line: 0, column: 0 });
+    assert_eq!(call_span.end, OutputPosition { line: 3, column: 1 });
+
+    let options = RenderOptions::new(10).indent("\t", 4);
+    let (output, map) = call.render_with_map_with(&options);
+    assert_eq!(output, call.clone().render_with(&options));
+    let second = map.annotation_at(2, 1).unwrap();
+    assert_eq!(second.annotation, 3);
+    assert_eq!(second.end, OutputPosition { line: 2, column: 7 });
+
+    // A memoized region spanning lines is still one region.
+    let block = node(
+        4,
+        Doc::vsep([Doc::<usize>::text("x"), Doc::<usize>::text("y")]),
+    )
+    .memoize();
+    let (output, map) = Doc::<usize>::text("{")
+        .concat(block.nest(2))
+        .render_with_map(80);
+    assert_eq!(output, "{x\n  y");
+    assert_eq!(map.spans().len(), 1);
+    assert_eq!(map.spans()[0].end, OutputPosition { line: 1, column: 3 });
+}
+
+#[test]
+fn group_break_hint() {
+    let args = || -> Doc {
+        Doc::intersperse(
+            ["alpha", "beta", "gamma"].map(Doc::text),
+            Doc::text(",").concat(Doc::line()),
+        )
+    };
+    let call = |body: Doc| Doc::text("f(").concat(body.nest(2)).concat(Doc::text(")"));
+    let options = RenderOptions::new(40);
+    for width in [10, 40] {
+        assert_eq!(
+            call(args().group_break_hint(18)).render(width),
+            call(args().group()).render(width)
+        );
+    }
+
+    // The hinted group is never measured.
+    let (_, plain) = call(args().group()).render_with_metrics(&options);
+    let (_, hinted) = call(args().group_break_hint(18)).render_with_metrics(&options);
+    assert!(hinted.lookahead_steps < plain.lookahead_steps);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "`group_break_hint` declared the wrong flat width")]
+fn group_break_hint_wrong() {
+    let doc: Doc = Doc::sep(["alpha", "beta"].map(Doc::text)).group_break_hint(3);
+    doc.render(80);
+}
+
+#[test]
+fn group_break_hint_markup() {
+    // `**` on either side.
+    #[derive(Clone)]
+    struct Bold;
+
+    impl Annotation for Bold {
+        fn extra_width_flat(&self) -> Width {
+            4
+        }
+    }
+
+    // Hints count markup, as the layout does; nested hints are each checked as they end.
+    let word = || Doc::annotate(Bold, Doc::<Bold>::text("alpha")).group_break_hint(9);
+    let doc = Doc::sep([word(), word()]).group_break_hint(19);
+    assert_eq!(doc.clone().render(19), "alpha alpha");
+    assert_eq!(doc.render(18), "alpha\nalpha");
+}
+
+#[test]
+fn example_args() {
+    use crate::examples::{Args, PrintStyle};
+
+    let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+    assert_eq!(parse(&[]), Ok(Args::default()));
+    let args = Args {
+        width: 20,
+        style: PrintStyle::Compact,
+    };
+    assert_eq!(parse(&["--width", "20", "--style=compact"]), Ok(args));
+    assert!(parse(&["--width"]).is_err());
+    assert!(parse(&["--style", "loud"]).is_err());
+    assert!(parse(&["--color"]).is_err());
+
+    let doc = Doc::sep(["a", "b"].map(Doc::text));
+    assert_eq!(args.render(doc.clone()), "a b");
+    assert_eq!(Args { width: 2, ..args }.render(doc.clone()), "a b");
+    assert_eq!(
+        Args {
+            width: 2,
+            ..Args::default()
+        }
+        .render(doc),
+        "a\nb"
+    );
+}
+
+#[test]
+fn themes() {
+    let doc = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("let"))
+        .concat_space(Doc::<Semantic>::text("x = "))
+        .concat(Doc::annotate(Semantic::Literal, Doc::<Semantic>::text("1")))
+        .concat_space(Doc::annotate(
+            Semantic::Comment,
+            Doc::<Semantic>::text("// one"),
+        ));
+
+    let theme = Theme::plain()
+        .keyword(Style::new().bold(true))
+        .comment(Style::new().fg(Color::Blue));
+    assert_eq!(
+        doc.clone().render_themed(&theme, 80),
+        "\x1b[1mlet\x1b[0m x = 1 \x1b[34m// one\x1b[0m"
+    );
+    assert_eq!(
+        doc.clone().render_themed(&Theme::default(), 80),
+        "\x1b[1;35mlet\x1b[0m x = \x1b[32m1\x1b[0m \x1b[3;34m// one\x1b[0m"
+    );
+    assert_eq!(
+        Theme::default().style(Semantic::Error),
+        Style::new().bold(true).fg(Color::Red)
+    );
+
+    let options = RenderOptions::new(80).color(ColorChoice::Never);
+    assert_eq!(
+        doc.clone().render_themed_with(&Theme::default(), &options),
+        "let x = 1 // one"
+    );
+    assert_eq!(
+        doc.render_html(80),
+        "<span class=\"keyword\">let</span> x = <span class=\"literal\">1</span> \
+         <span class=\"comment\">// one</span>"
+    );
+}
+
+#[test]
+fn with_min_width() {
+    let field = |name: &str| -> Doc { Doc::text(name.to_string()).with_min_width(8) };
+    let variants = Doc::vsep(
+        [("Red", 1), ("Green", 2), ("Ultraviolet", 3)]
+            .map(|(name, n)| field(name).concat(Doc::text(format!("= {n}")))),
+    );
+    assert_snapshot!(variants.render(80), @r"
+    Red     = 1
+    Green   = 2
+    Ultraviolet= 3
+    ");
+
+    // Measured from where the field starts, not from the start of the line.
+    let indented = Doc::text("  ").concat(field("ab")).concat(Doc::text("|"));
+    assert_eq!(indented.render(80), "  ab      |");
+
+    // The padding counts toward fitting.
+    let group = Doc::sep([field("a"), Doc::text("b")]).group();
+    assert_eq!(group.clone().render(10), "a        b");
+    assert_eq!(group.render(9), "a       \nb");
+}
+
+#[test]
+fn pre_rendered() {
+    let external = "match x {\n    1 => a,\n\n    _ => b,\n}";
+    let fragment = || -> Doc { Doc::pre_rendered(external.lines().map(String::from).collect()) };
+    let doc = Doc::text("fn f() {")
+        .concat(
+            Doc::line()
+                .concat(Doc::text("let y = "))
+                .concat(fragment())
+                .concat(Doc::text(";"))
+                .nest(4),
+        )
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_snapshot!(doc.render_with(&options), @r"
+    fn f() {
+        let y = match x {
+            1 => a,
+
+            _ => b,
+        };
+    }
+    ");
+    assert_eq!(Doc::<()>::pre_rendered(vec![]).render(80), "");
+}
+
+#[test]
+fn render_plain() {
+    use std::cell::Cell;
+
+    thread_local! {
+        static CLONES: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Counted;
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            CLONES.with(|clones| clones.set(clones.get() + 1));
+            Counted
+        }
+    }
+
+    impl Annotation for Counted {}
+
+    let item = |i: usize| Doc::annotate(Counted, Doc::<Counted>::text(format!("item{i}")));
+    let doc = Doc::annotate(Counted, Doc::sep((0..6).map(item)).nest(2).group());
+    let options = RenderOptions::new(20).indent("\t", 4);
+    let plain = doc.clone().render_plain_with(&options);
+    assert_eq!(CLONES.with(Cell::get), 0);
+    assert_eq!(plain, doc.clone().render_with(&options));
+    assert!(CLONES.with(Cell::get) > 0);
+
+    // Memoized subtrees replay their annotations, which are skipped.
+    let shared = Doc::annotate("cell", Doc::<&str>::text("shared")).memoize();
+    let table = Doc::vsep([shared.clone(), shared.clone().nest(2), shared]);
+    assert_eq!(table.clone().render_plain(80), table.render(80));
+}
+
+#[test]
+fn render_elided() {
+    let entries = |name: &str, n: usize| -> Doc {
+        Doc::vsep((0..n).map(|i| Doc::text(format!("{name} entry {i}"))))
+            .elidable(0, Doc::text(format!("({n} entries)")))
+    };
+    let section = |name: &str, n: usize, importance: u8| -> Doc {
+        Doc::text(format!("[{name}]"))
+            .concat(Doc::line().concat(entries(name, n)).nest(2))
+            .elidable(importance, Doc::text(format!("[{name}] ...")))
+    };
+    let doc = Doc::vsep([
+        section("errors", 2, 9),
+        section("warnings", 3, 5),
+        section("debug", 4, 1),
+    ]);
+
+    let rendered = |height| doc.clone().render_elided(80, height);
+    assert_eq!(rendered(100), doc.clone().render(80));
+    // All entries are summarized first, then whole sections by importance.
+    assert_snapshot!(rendered(6), @r"
+    [errors]
+      (2 entries)
+    [warnings]
+      (3 entries)
+    [debug]
+      (4 entries)
+    ");
+    assert_snapshot!(rendered(5), @r"
+    [errors]
+      (2 entries)
+    [warnings]
+      (3 entries)
+    [debug] ...
+    ");
+    assert_snapshot!(rendered(4), @r"
+    [errors]
+      (2 entries)
+    [warnings] ...
+    [debug] ...
+    ");
+    assert_snapshot!(rendered(3), @r"
+    [errors] ...
+    [warnings] ...
+    [debug] ...
+    ");
+    // Once everything is elided, the output is cut off.
+    assert_snapshot!(rendered(2), @r"
+    [errors] ...
+    [warnings] ...
+    ");
+
+    // Memoized regions are elided, too.
+    let shared = entries("shared", 3).memoize();
+    let twice = Doc::vsep([shared.clone(), shared]);
+    assert_eq!(twice.clone().render_elided(80, 6), twice.clone().render(80));
+    assert_eq!(twice.render_elided(80, 5), "(3 entries)\n(3 entries)");
+}
+
+#[test]
+fn delimiters() {
+    let call: Doc = Doc::text("f(").concat(
+        Doc::line()
+            .concat(Doc::text("[1, 2],"))
+            .concat(Doc::line())
+            .concat(Doc::text("{ x }"))
+            .nest(4),
+    );
+    let check = Delimiters::new();
+    assert!(check
+        .check(&call.clone().concat(Doc::text(")")).render(8))
+        .is_ok());
+
+    let err = check
+        .check(&call.clone().concat(Doc::text("]")).render(8))
+        .unwrap_err();
+    let at = |line, column| OutputPosition { line, column };
+    assert_eq!(
+        err,
+        DelimiterError::Mismatched {
+            found: ']',
+            position: at(2, 9),
+            open: Some(('(', ')', at(0, 1))),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "3:10: found `]`, expected `)` to close `(` at 1:2"
+    );
+
+    let err = check.check(&call.render(8)).unwrap_err();
+    assert_eq!(err.to_string(), "1:2: `(` is never closed");
+    assert_eq!(
+        check.check("x)").unwrap_err().to_string(),
+        "1:2: found `)` with nothing to close"
+    );
+
+    // Comments are skipped, up to the end of the line.
+    let comments = Delimiters::new().pairs([('<', '>')]).skip(|rest| {
+        rest.starts_with("//")
+            .then(|| rest.find('\n').unwrap_or(rest.len()))
+    });
+    assert!(comments.check("<a> // <\n<b>(").is_ok());
+    assert_eq!(
+        comments.check("<a // >\n>>").unwrap_err().position(),
+        at(1, 1)
+    );
+}
+
+#[test]
+fn list_tupled_record() {
+    let point = |x: i64, y: i64| -> Doc { Doc::tupled([x, y].map(|n| Doc::text(n.to_string()))) };
+    let field = |name: &str, value: Doc| Doc::text(format!("{name}:")).concat_space(value);
+    let shape = Doc::record([
+        field("name", Doc::text("\"triangle\"")),
+        field(
+            "points",
+            Doc::list([point(0, 0), point(10, 0), point(5, 8)]),
+        ),
+    ]);
+    assert_snapshot!(shape.clone().render(80), @r#"{ name: "triangle", points: [(0, 0), (10, 0), (5, 8)] }"#);
+    assert_snapshot!(shape.clone().render(40), @r#"
+    {
+        name: "triangle",
+        points: [(0, 0), (10, 0), (5, 8)]
+    }
+    "#);
+    assert_snapshot!(shape.render(20), @r#"
+    {
+        name: "triangle",
+        points: [
+            (0, 0),
+            (10, 0),
+            (5, 8)
+        ]
+    }
+    "#);
+    assert_eq!(<Doc>::list([]).render(80), "[]");
+    assert_eq!(<Doc>::tupled([]).render(80), "()");
+    assert_eq!(<Doc>::record([]).render(80), "{}");
+}
+
+#[test]
+fn layout_is_reused() {
+    use std::{cell::Cell, rc::Rc};
+
+    let calls = Rc::new(Cell::new(0));
+    let counted = {
+        let calls = calls.clone();
+        Doc::column(move |_| {
+            calls.set(calls.get() + 1);
+            Doc::annotate(Semantic::Literal, Doc::<Semantic>::text("42"))
+        })
+    };
+    let doc =
+        Doc::<Semantic>::text("answer:").concat(Doc::<Semantic>::line().concat(counted).nest(2));
+    let mut layout = doc.layout(80);
+
+    // Iterating lays out only as far as needed; painting finishes the layout.
+    assert_eq!(layout.next(), Some(RenderEvent::Text("answer:".into())));
+    assert_eq!(calls.get(), 0);
+    assert_eq!(layout.to_string(), "answer:\n  42");
+    assert_eq!(layout.line_count(), 2);
+    assert_eq!(
+        layout.to_html(),
+        "answer:\n  <span class=\"literal\">42</span>"
+    );
+    assert_eq!(
+        layout.to_ansi(&Theme::default()),
+        "answer:\n  \x1b[32m42\x1b[0m"
+    );
+    assert_eq!(layout.by_ref().count(), 4);
+    assert_eq!(calls.get(), 1);
+}
+
+#[test]
+fn punctuate() {
+    let items = || ["alpha", "beta", "gamma", "delta"].map(Doc::text);
+    let punctuated: Vec<Doc> = Doc::punctuate(Doc::text(";"), items());
+    assert_eq!(punctuated.len(), 4);
+    assert_eq!(
+        Doc::sep(punctuated.clone()).render(80),
+        "alpha; beta; gamma; delta"
+    );
+    assert_eq!(
+        Doc::fill(&punctuated).render(80),
+        "alpha; beta; gamma; delta"
+    );
+    assert_eq!(
+        Doc::vsep(punctuated).render(80),
+        "alpha;\nbeta;\ngamma;\ndelta"
+    );
+    assert!(Doc::<()>::punctuate(Doc::comma(), []).is_empty());
+    assert_eq!(
+        <Doc>::vsep(Doc::punctuate(Doc::comma(), [Doc::text("x")])).render(80),
+        "x"
+    );
+}
+
+#[test]
+fn enclose_and_quotes() {
+    let name = || -> Doc { Doc::text("x") };
+    assert_eq!(name().squotes().render(80), "'x'");
+    assert_eq!(name().dquotes().render(80), "\"x\"");
+    assert_eq!(name().backticks().render(80), "`x`");
+    assert_eq!(
+        name().enclose(Doc::text("<<"), Doc::text(">>")).render(80),
+        "<<x>>"
+    );
+}
+
+#[test]
+fn each_line() {
+    let body: Doc = Doc::vsep([
+        Doc::text("let x = 1;"),
+        Doc::text("let longer_name = x + 1;"),
+    ]);
+    let doc = || -> Doc {
+        Doc::text("{")
+            .concat(Doc::line().concat(body.clone()).nest(2))
+            .concat(Doc::line())
+            .concat(Doc::text("}"))
+    };
+    let numbered = doc().each_line(80, |line| {
+        format!("{} {}|{}", line.index, line.indent, line.content)
+    });
+    assert_snapshot!(numbered, @r"
+    0 0|{
+    1 2|let x = 1;
+    2 2|let longer_name = x + 1;
+    3 0|}
+    ");
+    let truncated = doc().each_line(80, |line| line.text.chars().take(12).collect());
+    assert_snapshot!(truncated, @r"
+    {
+      let x = 1;
+      let longer
+    }
+    ");
+    // Every line, including the last, goes through `f`.
+    assert_eq!(<Doc>::nil().each_line(80, |_| "!".to_string()), "!");
+}
+
+#[test]
+fn char_and_punctuation() {
+    let tokens: Doc = Doc::hcat([
+        Doc::text("x"),
+        Doc::colon(),
+        Doc::char(' '),
+        Doc::text("T"),
+        Doc::char(' '),
+        Doc::equals(),
+        Doc::char('>'),
+        Doc::char('λ'),
+        Doc::dot(),
+        Doc::arrow(),
+        Doc::fat_arrow(),
+        Doc::semicolon(),
+    ]);
+    assert_eq!(tokens.render(80), "x: T =>λ.->=>;");
+    // Punctuation is shared rather than allocated per use.
+    let same = |a: Doc, b: Doc| std::rc::Rc::ptr_eq(&a.0, &b.0);
+    assert!(same(Doc::char('.'), Doc::dot()));
+    assert!(same(Doc::char(';'), Doc::semicolon()));
+    assert!(same(Doc::char('{'), Doc::lbrace()));
+    assert!(same(Doc::arrow(), Doc::arrow()));
+    assert!(!same(Doc::char('λ'), Doc::char('λ')));
+}
+
+#[test]
+fn separator_placement() {
+    let list = |placement| -> Doc {
+        let items = ["alpha", "beta", "gamma"].map(Doc::text);
+        Doc::enclose_sep(
+            Doc::lparen(),
+            items,
+            Doc::semicolon(),
+            Doc::rparen(),
+            placement,
+        )
+    };
+    let binding = |placement| -> Doc { Doc::text("let xs = ").concat(list(placement)) };
+    assert_snapshot!(binding(SeparatorPlacement::Leading).render(80), @"let xs = (alpha; beta; gamma)");
+    assert_snapshot!(binding(SeparatorPlacement::Leading).render(18), @r"
+    let xs = ( alpha
+             ; beta
+             ; gamma
+             )
+    ");
+    assert_snapshot!(binding(SeparatorPlacement::Trailing).render(18), @r"
+    let xs = (
+        alpha;
+        beta;
+        gamma
+    )
+    ");
+    assert_eq!(
+        <Doc>::enclose_sep(
+            Doc::lbracket(),
+            [],
+            Doc::comma(),
+            Doc::rbracket(),
+            SeparatorPlacement::Leading
+        )
+        .render(80),
+        "[]"
+    );
+}
+
+#[test]
+fn rainbow() {
+    fn call(name: &str, args: Vec<Doc<String>>) -> Doc<String> {
+        let args = Doc::intersperse(
+            args,
+            Doc::<String>::comma().concat(Doc::<String>::softline()),
+        );
+        let args = Doc::rainbow(
+            Doc::<String>::lparen(),
+            args.nest(2),
+            Doc::<String>::rparen(),
+            |depth| format!("depth-{}", depth % 2),
+        );
+        Doc::<String>::text(name).concat(args).group()
+    }
+    let doc = call(
+        "f",
+        vec![
+            call("g", vec![Doc::<String>::text("x")]),
+            call("h", vec![call("k", vec![]), Doc::<String>::text("y")]),
+        ],
+    );
+    assert_snapshot!(doc.render_html(80), @r#"f<span class="depth-0">(</span>g<span class="depth-1">(</span>x<span class="depth-1">)</span>, h<span class="depth-1">(</span>k<span class="depth-0">(</span><span class="depth-0">)</span>, y<span class="depth-1">)</span><span class="depth-0">)</span>"#);
+    // The depth doesn't depend on how the document breaks.
+    let squash = |html: String| html.replace(char::is_whitespace, "");
+    assert_eq!(squash(doc.render_html(8)), squash(doc.render_html(80)));
+    assert_eq!(
+        <Doc>::at_depth(|d| Doc::text(d.to_string())).render(80),
+        "0"
+    );
+}
+
+#[test]
+fn string() {
+    let doc = |s| -> Doc {
+        Doc::text("{")
+            .concat(Doc::line().concat(Doc::string(s)).nest(2))
+            .concat(Doc::line())
+            .concat(Doc::text("}"))
+    };
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_snapshot!(doc("one\r\ntwo\n\n  three\n").render_with(&options), @r"
+    {
+      one
+      two
+
+        three
+
+    }
+    ");
+    assert_eq!(<Doc>::string("single").render(80), "single");
+    assert_eq!(<Doc>::string("").render(80), "");
+}
+
+#[test]
+fn layout_runs_of_line_breaks() {
+    let blank_lines =
+        || -> Doc { Doc::vsep([Doc::text("a"), Doc::nil(), Doc::nil(), Doc::text("b")]) };
+    let expected = [
+        RenderEvent::Text("a".into()),
+        RenderEvent::Newline { indent: 2 },
+        RenderEvent::Newline { indent: 2 },
+        RenderEvent::Newline { indent: 2 },
+        RenderEvent::Text("b".into()),
+    ];
+    let events: Vec<RenderEvent> = blank_lines().nest(2).layout_events(80).collect();
+    assert_eq!(events, expected);
+    let mut layout = blank_lines().nest(2).layout(80);
+    assert_eq!(layout.by_ref().take(2).collect::<Vec<_>>(), expected[..2]);
+    // A clone finishes the layout, and carries on from the same event.
+    let clone = layout.clone();
+    assert_eq!(layout.collect::<Vec<_>>(), expected[2..]);
+    assert_eq!(clone.line_count(), 4);
+    assert_eq!(clone.collect::<Vec<_>>(), expected[2..]);
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    let layout = blank_lines().nest(2).layout_with(&options);
+    assert_eq!(layout.clone().to_string(), "a\n\n\n  b");
+}
+
+#[test]
+fn reflow() {
+    let text = "
+        The quick brown fox
+        jumps over the lazy dog.
+
+
+        It keeps on running,   far away.
+    ";
+    let comment = || -> Doc { Doc::quoted_block("/// ", Doc::reflow(text)) };
+    let options = RenderOptions::new(24)
+        .algorithm(LayoutAlgorithm::Oppen)
+        .trim_trailing_whitespace(true);
+    assert_snapshot!(comment().render_with(&options), @r"
+    /// The quick brown fox
+    /// jumps over the lazy
+    /// dog.
+    ///
+    /// It keeps on running,
+    /// far away.
+    ");
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_snapshot!(comment().render_with(&options), @r"
+    /// The quick brown fox jumps over the lazy dog.
+    ///
+    /// It keeps on running, far away.
+    ");
+    assert_eq!(<Doc>::reflow(" \n\n ").render(80), "");
+}
+
+#[test]
+fn paragraphs() {
+    let items = || -> Vec<Doc> {
+        vec![
+            Doc::nil(),
+            Doc::text("use std::fmt;"),
+            Doc::nil().concat(Doc::text("")).nest(4),
+            Doc::text("struct A;"),
+            Doc::vsep(Vec::<Doc>::new()),
+            Doc::text("struct B;"),
+            Doc::nil(),
+        ]
+    };
+    let module = Doc::text("mod m {")
+        .concat(Doc::line().concat(Doc::paragraphs(items())).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_snapshot!(module.render_with(&options), @r"
+    mod m {
+        use std::fmt;
+
+        struct A;
+
+        struct B;
+    }
+    ");
+    assert_eq!(<Doc>::paragraphs([Doc::nil(), Doc::nil()]).render(80), "");
+}
+
+#[test]
+fn fill_sep_and_fill_cat() {
+    let items = || (1..=6).map(|i| Doc::text(format!("item{i}")));
+    assert_eq!(
+        <Doc>::fill_sep(items()).render(80),
+        "item1 item2 item3 item4 item5 item6"
+    );
+    assert_eq!(
+        <Doc>::fill_cat(items()).render(80),
+        "item1item2item3item4item5item6"
+    );
+    assert_snapshot!(<Doc>::fill_cat(items()).render(12), @r"
+    item1
+    item2
+    item3
+    item4
+    item5item6
+    ");
+    assert_eq!(<Doc>::fill_cat(std::iter::empty()).render(80), "");
+}
+
+#[test]
+fn fill_break() {
+    let field = |name: &str, ty: &str| -> Doc {
+        Doc::text(name.to_string())
+            .fill_break(8)
+            .concat(Doc::text(format!(": {ty},")))
+    };
+    let fields = Doc::vsep([
+        field("id", "u64"),
+        field("name", "String"),
+        field("exactly8", "bool"),
+        field("created_at", "Timestamp"),
+    ]);
+    let doc = Doc::text("struct Row {")
+        .concat(Doc::line().concat(fields).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    assert_snapshot!(doc.render(80), @r"
+    struct Row {
+        id      : u64,
+        name    : String,
+        exactly8: bool,
+        created_at
+                : Timestamp,
+    }
+    ");
+}
+
+#[test]
+fn vcat_and_cat() {
+    let items = || ["one", "two", "three"].map(Doc::text);
+    assert_eq!(<Doc>::vcat(items()).render(80), "one\ntwo\nthree");
+    assert_eq!(<Doc>::cat(items()).render(80), "onetwothree");
+    assert_eq!(<Doc>::sep(items()).render(80), "one two three");
+    // Breaks nest like any other, and a flattened break takes no room when deciding whether
+    // an enclosing group fits.
+    let call = |width| {
+        Doc::text("f(")
+            .concat(Doc::line_break().concat(<Doc>::cat(items())).nest(2))
+            .concat(Doc::line_break())
+            .concat(Doc::text(")"))
+            .group()
+            .render(width)
+    };
+    assert_eq!(call(14), "f(onetwothree)");
+    assert_snapshot!(call(13), @r"
+    f(
+      onetwothree
+    )
+    ");
+    assert_snapshot!(call(8), @r"
+    f(
+      one
+      two
+      three
+    )
+    ");
+}
+
+#[test]
+fn width() {
+    // Continue the value's lines after the variable-width `name := ` prefix.
+    let binding = |name: &str, value: Doc| -> Doc {
+        Doc::width(Doc::text(format!("{name} := ")), move |w| {
+            value.clone().nest(w)
+        })
+    };
+    let value = || Doc::vsep(["first", "second"].map(Doc::text));
+    let doc = Doc::vsep([binding("x", value()), binding("longer", value())]);
+    assert_snapshot!(doc.render(80), @r"
+    x := first
+         second
+    longer := first
+              second
+    ");
+    // Across line breaks, it's the distance between the starting and ending columns.
+    let measured = <Doc>::width(
+        Doc::text("ab").concat(Doc::line()).concat(Doc::text("c")),
+        |w| Doc::text(format!(" ({w})")),
+    );
+    assert_eq!(measured.render(80), "ab\nc (1)");
+}
+
+#[test]
+fn page_width() {
+    let right_aligned = |s: &'static str| -> Doc {
+        Doc::page_width(move |page| {
+            Doc::column(move |column| {
+                Doc::spaces((page - column - s.len() as Width).max(1)).concat(Doc::text(s))
+            })
+        })
+    };
+    let header = || Doc::text("NAME(1)").concat(right_aligned("page 1"));
+    assert_snapshot!(Doc::vsep([header(), Doc::quoted_block("> ", header())]).render(24), @r"
+    NAME(1)           page 1
+    > NAME(1)         page 1
+    ");
+    assert_eq!(header().render(10), "NAME(1) page 1");
+    // Deciding whether a group fits sees the same width.
+    let doc: Doc = Doc::text("a")
+        .concat(Doc::softline())
+        .concat(Doc::page_width(|w| Doc::text("b".repeat(w as usize - 2))))
+        .group();
+    assert_eq!(doc.render(6), "a bbbb");
+}
+
+#[test]
+fn align_to() {
+    let field = |name: &str, ty: &str| -> Doc {
+        Doc::text(name.to_string())
+            .concat(Doc::align_to(16))
+            .concat(Doc::text(ty.to_string()))
+    };
+    // The column is absolute, so it's the same however deeply the fields are nested.
+    let doc = Doc::text("struct A {")
+        .concat(
+            Doc::line()
+                .concat(Doc::vsep([
+                    field("id", "u64"),
+                    field("a_very_long_name", "String"),
+                    field("exactly_12ch", "bool"),
+                ]))
+                .nest(4),
+        )
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    assert_snapshot!(doc.render(80), @r"
+    struct A {
+        id          u64
+        a_very_long_name
+                    String
+        exactly_12chbool
+    }
+    ");
+}
+
+#[test]
+fn pad_left_and_pad_right() {
+    let listing = Doc::vsep(["fn main() {", "    run();", "}"].iter().enumerate().map(
+        |(i, line)| -> Doc {
+            Doc::text((i + 1).to_string())
+                .pad_left(3)
+                .concat(Doc::text(" "))
+                .concat(Doc::text(line.to_string()))
+        },
+    ));
+    assert_snapshot!(listing.render(80), @r"
+      1 fn main() {
+      2     run();
+      3 }
+    ");
+    // Fields are laid out flat, and wider ones aren't cut.
+    let field = || -> Doc { Doc::sep(["a", "b"].map(Doc::text)) };
+    assert_eq!(
+        field().pad_right(5).concat(Doc::text("|")).render(1),
+        "a b  |"
+    );
+    assert_eq!(
+        field().pad_left(5).concat(Doc::text("|")).render(1),
+        "  a b|"
+    );
+    assert_eq!(field().pad_left(2).render(80), "a b");
+    // Padding counts the columns text takes up as the render measures it.
+    let v3 = RenderOptions::new(80).layout_version(LayoutVersion::V3);
+    assert_eq!(<Doc>::text("日本").pad_left(6).render_with(&v3), "  日本");
+}
+
+#[test]
+fn flush_right() {
+    let decl = |name: &str, ty: &str| -> Doc {
+        Doc::text(format!("let {name}:")).concat(Doc::text(ty.to_string()).flush_right())
+    };
+    let doc = Doc::vsep([decl("x", "u8"), decl("total", "Vec<u64>")]);
+    let block = Doc::text("{")
+        .concat(Doc::line().concat(doc.clone()).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    assert_snapshot!(block.render(24), @r"
+    {
+        let x:            u8
+        let total:  Vec<u64>
+    }
+    ");
+    // Too full a line keeps a single space.
+    assert_snapshot!(doc.render(10), @r"
+    let x:  u8
+    let total: Vec<u64>
+    ");
+    let v3 = RenderOptions::new(10).layout_version(LayoutVersion::V3);
+    let doc: Doc = Doc::text("名前").concat(Doc::text("型").flush_right());
+    assert_eq!(doc.render_with(&v3), "名前    型");
+}
+
+#[test]
+fn dedent() {
+    let words = || -> Doc { Doc::sep(["aaa", "bbb"].map(Doc::text)) };
+    // Nesting adds up before clamping, so dedenting and then indenting again cancels out.
+    let doc: Doc = Doc::text("x")
+        .concat(
+            Doc::line()
+                .concat(Doc::text("y"))
+                .concat(Doc::line().concat(Doc::text("z")).nest(6))
+                .dedent(4),
+        )
+        .nest(2);
+    assert_snapshot!(doc.render(80), @r"
+    x
+    y
+        z
+    ");
+    // A line dedented past the margin starts at column 0, and is measured from there.
+    let doc = Doc::text("-").concat(Doc::line().concat(words()).dedent(3));
+    assert_eq!(doc.clone().render(7), "-\naaa bbb");
+    assert_eq!(doc.render(6), "-\naaa\nbbb");
+}
+
+#[test]
+fn expand() {
+    let fields = || ["x: 1", "y: 2"].map(Doc::text);
+    let point = |fields: Doc| Doc::text("Point ").concat(fields);
+    let doc = |fields: Doc| -> Doc {
+        let value = Doc::line().concat(point(fields)).nest(4);
+        Doc::text("let p =").concat(value).group()
+    };
+    assert_eq!(
+        doc(Doc::record(fields())).render(80),
+        "let p = Point { x: 1, y: 2 }"
+    );
+    // The enclosing group breaks as well as the expanded one.
+    assert_snapshot!(doc(Doc::record(fields()).expand()).render(80), @r"
+    let p =
+        Point {
+            x: 1,
+            y: 2
+        }
+    ");
+    let args: Doc = Doc::sep(["a", "b"].map(Doc::text)).expand();
+    assert_eq!(args.render(80), "a\nb");
+    // Groups break wherever they sit in the expanded document.
+    let nested = Doc::record(fields()).nest(2);
+    assert_eq!(
+        nested.expand().render(80),
+        "{\n      x: 1,\n      y: 2\n  }"
+    );
+    let annotated = Doc::annotate(
+        Semantic::Literal,
+        Doc::record(["x: 1", "y: 2"].map(Doc::<Semantic>::text)),
+    );
+    assert_eq!(annotated.expand().render(80), "{\n    x: 1,\n    y: 2\n}");
+    let trailing: Doc = Doc::text("p = ").concat(Doc::record(fields()).align());
+    assert_eq!(
+        trailing.expand().render(80),
+        "p = {\n        x: 1,\n        y: 2\n    }"
+    );
+    // Softlines inside the broken layout still choose for themselves.
+    let words: Doc = Doc::text("a")
+        .concat(Doc::softline())
+        .concat(Doc::text("b"));
+    let list = Doc::text("[")
+        .concat(Doc::line().concat(words).nest(2))
+        .concat(Doc::line())
+        .concat(Doc::text("]"))
+        .group();
+    assert_eq!(list.expand().render(80), "[\n  a b\n]");
+    // Forcing the document flat still lays it out flat.
+    assert_eq!(
+        Doc::record(fields()).expand().pad_right(0).render(80),
+        "{ x: 1, y: 2 }"
+    );
+}
+
+#[test]
+fn list_flat_at_most() {
+    let items = |n: usize| (1..=n).map(|i| Doc::text(format!("x{i}")));
+    let call = |n| -> Doc {
+        let args = Doc::line_break()
+            .concat(Doc::list_flat_at_most(items(n), 2))
+            .nest(4);
+        Doc::text("f(")
+            .concat(args)
+            .concat(Doc::line_break())
+            .concat(Doc::text(")"))
+            .group()
+    };
+    assert_eq!(call(2).render(80), "f([x1, x2])");
+    assert_snapshot!(call(3).render(80), @r"
+    f(
+        [
+            x1,
+            x2,
+            x3
+        ]
+    )
+    ");
+    // Short lists still break when they don't fit.
+    assert_snapshot!(call(2).render(6), @r"
+    f(
+        [
+            x1,
+            x2
+        ]
+    )
+    ");
+    assert_eq!(<Doc>::list_flat_at_most(items(0), 0).render(80), "[]");
+}
+
+#[test]
+fn sep_with_leading_separators() {
+    let fields = || ["alpha", "beta", "gamma", "delta"].map(<Doc>::text);
+    let record = |width| -> String {
+        let broken = Doc::line().concat(Doc::text(", "));
+        let body = Doc::sep_with(fields(), Doc::text(", "), broken);
+        Doc::text("{ ")
+            .concat(body)
+            .concat(Doc::text(" }"))
+            .align()
+            .render(width)
+    };
+    assert_eq!(record(80), "{ alpha, beta, gamma, delta }");
+    assert_snapshot!(record(20), @r"
+    { alpha
+    , beta
+    , gamma
+    , delta }
+    ");
+    // The flat form is used in flattened documents too.
+    let separator: Doc = Doc::line().flat_alt(Doc::text("; "));
+    let doc = Doc::intersperse([Doc::text("a"), Doc::text("b")], separator);
+    assert_eq!(doc.clone().flatten().render(80), "a; b");
+    assert_eq!(doc.render(80), "a\nb");
+}
+
+#[test]
+fn comma_list_trailing_comma() {
+    let args = |n: usize| (1..=n).map(|i| <Doc>::text(format!("argument{i}")));
+    let call = |n, width| -> String {
+        let args = Doc::line_break().concat(Doc::comma_list(args(n))).nest(4);
+        Doc::text("call(")
+            .concat(args)
+            .concat(Doc::line_break())
+            .concat(Doc::text(")"))
+            .group()
+            .render(width)
+    };
+    assert_eq!(call(2, 80), "call(argument1, argument2)");
+    // Broken only as far as needed: the arguments still fit on one line of their own.
+    assert_snapshot!(call(2, 25), @r"
+    call(
+        argument1, argument2
+    )
+    ");
+    assert_snapshot!(call(3, 26), @r"
+    call(
+        argument1,
+        argument2,
+        argument3,
+    )
+    ");
+    assert_eq!(call(0, 80), "call()");
+    assert_eq!(<Doc>::comma_list([Doc::text("a")]).render(0), "a,");
+}
+
+#[test]
+fn block_styles() {
+    let body = || Doc::vsep([Doc::text("let x = 1;"), Doc::text("x")]);
+    let function = |style: &BlockStyle| -> String {
+        let inner = body().block_with(Doc::lbrace(), Doc::rbrace(), style);
+        let outer = Doc::text("loop ").concat(inner);
+        let outer = outer.block_with(Doc::lbrace(), Doc::rbrace(), style);
+        // Blocks group their bodies, so use a width that breaks them. Trimming drops the space
+        // before Allman braces and the indentation of blank lines.
+        let options = RenderOptions::new(12).trim_trailing_whitespace(true);
+        Doc::text("fn f() ").concat(outer).render_with(&options)
+    };
+    let block: Doc = body().block(Doc::lbrace(), Doc::rbrace());
+    let default = body().block_with(Doc::lbrace(), Doc::rbrace(), &BlockStyle::default());
+    assert_eq!(block.render(80), default.render(80));
+    assert_snapshot!(function(&BlockStyle::new().indent(2)), @r"
+    fn f() {
+      loop {
+        let x = 1;
+        x
+      }
+    }
+    ");
+    let allman = BlockStyle::new().braces(BraceStyle::Allman).padding(true);
+    assert_snapshot!(function(&allman), @r"
+    fn f()
+    {
+
+        loop
+        {
+
+            let x = 1;
+            x
+
+        }
+
+    }
+    ");
+}
+
+#[test]
+fn hang_with_tree() {
+    fn node(label: &str, children: Vec<Doc>) -> Doc {
+        let count = children.len();
+        let children = children.into_iter().enumerate().map(|(i, child)| {
+            let (connector, fill) = if i + 1 < count {
+                ("├── ", "│   ")
+            } else {
+                ("└── ", "    ")
+            };
+            Doc::text(connector).concat(child).hang_with(fill)
+        });
+        Doc::vsep(std::iter::once(Doc::text(label)).chain(children))
+    }
+    let tree = node(
+        "crate",
+        vec![
+            node(
+                "src",
+                vec![
+                    node("lib.rs", vec![]),
+                    node("tests", vec![node("mod.rs", vec![])]),
+                ],
+            ),
+            node("Cargo.toml", vec![]),
+        ],
+    );
+    let options = RenderOptions::new(80).trim_trailing_whitespace(true);
+    assert_snapshot!(tree.render_with(&options), @r"
+    crate
+    ├── src
+    │   ├── lib.rs
+    │   └── tests
+    │       └── mod.rs
+    └── Cargo.toml
+    ");
+    // Nesting inside `self` is relative to the end of the fill.
+    let body: Doc = Doc::text("a").concat(Doc::line().concat(Doc::text("b")).nest(2));
+    assert_eq!(body.indent_with("// ").render(80), "// a\n//   b");
+}
+
+#[test]
+fn prefix_lines() {
+    let text = "Prefixed lines count toward the width, so the comment wraps inside it.";
+    let comment = |width| -> String {
+        let body: Doc = Doc::reflow(text).prefix_lines("# ");
+        let options = RenderOptions::new(width).algorithm(LayoutAlgorithm::Oppen);
+        Doc::text("def f():")
+            .concat(Doc::line().concat(body).nest(4))
+            .render_with(&options)
+    };
+    assert_snapshot!(comment(40), @r"
+    def f():
+        # Prefixed lines count toward the
+        # width, so the comment wraps inside
+        # it.
+    ");
+}
+
+#[test]
+fn line_numbers() {
+    let body = Doc::vsep((1..=10).map(|i| Doc::text(format!("step{i}();"))));
+    let doc: Doc = Doc::text("fn main() {")
+        .concat(Doc::line().concat(body).nest(4))
+        .concat(Doc::line())
+        .concat(Doc::line())
+        .concat(Doc::text("}"));
+    let options = RenderOptions::new(80)
+        .line_numbers(2)
+        .trim_trailing_whitespace(true);
+    let numbered = doc.clone().render_with(&options);
+    assert_snapshot!(numbered, @r"
+     1 | fn main() {
+     2 |     step1();
+     3 |     step2();
+     4 |     step3();
+     5 |     step4();
+     6 |     step5();
+     7 |     step6();
+     8 |     step7();
+     9 |     step8();
+    10 |     step9();
+    11 |     step10();
+    12 |
+    13 | }
+    ");
+    // Every renderer numbers lines the same way.
+    assert_eq!(doc.clone().render_plain_with(&options), numbered);
+    let mut written = vec![];
+    doc.write_to_with(&options, &mut written).unwrap();
+    assert_eq!(String::from_utf8(written).unwrap(), numbered);
+    let lines = doc.each_line_with(&options, |line| format!("{}:{}", line.indent, line.content));
+    assert_eq!(lines.lines().nth(1), Some("4:step1();"));
+    // Too-long numbers widen the gutter.
+    let options = RenderOptions::new(80).line_numbers(1);
+    let short: Doc = Doc::vsep((0..10).map(|_| Doc::text("x")));
+    assert!(short.render_with(&options).ends_with("\n9 | x\n10 | x"));
+}
+
+#[test]
+fn prefix_lines_with_doc_comment() {
+    let text = "Lay out the document.\n\nLines are filled greedily under the Oppen algorithm.";
+    let comment = |width| -> String {
+        let body = Doc::reflow(text).prefix_lines_with("/** ", " * ");
+        let doc: Doc = Doc::text("struct S {").concat(
+            Doc::line()
+                .concat(body)
+                .concat(Doc::line())
+                .concat(Doc::text(" */"))
+                .concat(Doc::line())
+                .concat(Doc::text("fn layout();"))
+                .nest(4),
+        );
+        let options = RenderOptions::new(width)
+            .algorithm(LayoutAlgorithm::Oppen)
+            .trim_trailing_whitespace(true);
+        doc.concat(Doc::line())
+            .concat(Doc::text("}"))
+            .render_with(&options)
+    };
+    assert_snapshot!(comment(40), @r"
+    struct S {
+        /** Lay out the document.
+         *
+         * Lines are filled greedily under
+         * the Oppen algorithm.
+         */
+        fn layout();
+    }
+    ");
+}
+
+#[test]
+fn repeat() {
+    let cell: Doc = Doc::text("ab");
+    for n in [0, 1, 2, 3, 7, 8, 100] {
+        assert_eq!(cell.clone().repeat(n).render(1000), "ab".repeat(n));
+    }
+    // Copies break like their concatenation does.
+    let item: Doc = Doc::text("x").concat(Doc::softline());
+    let items = Doc::hcat([item.clone(), item.clone(), item.clone()]);
+    assert_eq!(item.repeat(3).render(3), items.render(3));
+    // Large counts are cheap, and lay out without overflowing the stack.
+    let long: Doc = Doc::text("=").repeat(1 << 20);
+    assert_eq!(long.render(80).len(), 1 << 20);
+}
+
+#[test]
+fn lazy() {
+    use std::cell::Cell;
+
+    // A complete binary tree of the given height, each subtree built only when reached.
+    fn tree(height: u32, built: Rc<Cell<usize>>) -> Doc {
+        built.set(built.get() + 1);
+        if height == 0 {
+            return Doc::text("leaf");
+        }
+        let child = {
+            let built = built.clone();
+            move || tree(height - 1, built.clone())
+        };
+        let children = Doc::list([Doc::lazy(child.clone()), Doc::lazy(child)]);
+        // Wide pages show a summary, and narrow pages the whole tree.
+        Doc::text(format!("<{height} levels>")).alt(children)
+    }
+    let built = Rc::new(Cell::new(0));
+    let doc = tree(20, built.clone());
+    assert_eq!(doc.clone().render(80), "<20 levels>");
+    assert_eq!(built.get(), 1);
+    // Reaching a lazy document builds it once, however often layout visits it.
+    let built = Rc::new(Cell::new(0));
+    let doc = tree(2, built.clone());
+    assert_snapshot!(doc.render(0), @r"
+    [
+        [
+            leaf,
+            leaf
+        ],
+        [
+            leaf,
+            leaf
+        ]
+    ]
+    ");
+    assert_eq!(built.get(), 7);
+}
+
+#[test]
+fn recursive_cycles() {
+    // `a` and `b` refer to each other, and `c` to itself.
+    let edges: Rc<[(&str, Vec<usize>)]> =
+        Rc::from(vec![("a", vec![1, 2]), ("b", vec![0]), ("c", vec![2])]);
+    let graph = |max_depth| -> Doc {
+        let edges = edges.clone();
+        Doc::recursive(0, max_depth, Doc::text("<cycle>"), move |node, recurse| {
+            let (name, targets) = &edges[node];
+            let children = targets.iter().map(|target| recurse(*target));
+            Doc::text(*name).concat(Doc::record(children))
+        })
+    };
+    assert_eq!(graph(0).render(80), "a{ <cycle>, <cycle> }");
+    assert_eq!(graph(1).render(80), "a{ b{ <cycle> }, c{ <cycle> } }");
+    assert_snapshot!(graph(2).render(30), @r"
+    a{
+        b{ a{ <cycle>, <cycle> } },
+        c{ c{ <cycle> } }
+    }
+    ");
+}
+
+#[test]
+fn fail_prunes_alternatives() {
+    let text = |s: &str| -> Doc { Doc::text(s) };
+    // The first alternative that doesn't fail is taken, even in an enclosing lookahead.
+    let choice = Doc::fail().alt(text("second")).alt(text("third"));
+    assert_eq!(choice.render(80), "second");
+    let grouped = text("[")
+        .concat(Doc::softline_empty())
+        .concat(Doc::fail().alt(text("ok")))
+        .concat(text("]"))
+        .group();
+    assert_eq!(grouped.render(80), "[ok]");
+    // A failure nested further in makes the whole alternative fail.
+    let nested = text("a").concat(Doc::fail()).alt(text("b"));
+    assert_eq!(nested.render(80), "b");
+    // One in a later choice makes that choice break instead, and doesn't reject this one.
+    let later = Doc::space()
+        .concat(text("y").concat(Doc::fail()))
+        .alt(Doc::line().concat(text("y")).nest(2));
+    assert_eq!(
+        text("aaaa").alt(text("b")).concat(later).render(80),
+        "aaaa\n  y"
+    );
+    // With nothing else to take, it renders as nothing.
+    assert_eq!(text("x").alt(Doc::fail()).render(0), "");
+    assert_eq!(text("x").concat(Doc::fail()).render(80), "x");
+}
+
+#[test]
+fn alternatives() {
+    let text = |s: &str| -> Doc { Doc::text(s) };
+    let choices = || {
+        [
+            text("one line"),
+            text("two").concat(Doc::line()).concat(text("lines")),
+            text("t").concat(Doc::line()).concat(text("h")),
+        ]
+    };
+    assert_eq!(Doc::alternatives(choices()).render(80), "one line");
+    assert_eq!(Doc::alternatives(choices()).render(5), "two\nlines");
+    assert_eq!(Doc::alternatives(choices()).render(1), "t\nh");
+    // The last alternative is taken when nothing fits.
+    assert_eq!(Doc::alternatives(choices()).render(0), "t\nh");
+    assert_eq!(Doc::alternatives([text("only")]).render(0), "only");
+    assert_eq!(<Doc>::alternatives([]).render(80), "");
+    // Inside a flattened group, the first alternative is laid out flat.
+    let grouped = text("[")
+        .concat(Doc::alternatives(choices()))
+        .concat(text("]"));
+    assert_eq!(grouped.group().render(80), "[one line]");
+}
+
+#[test]
+fn conditional_group() {
+    let text = |s: &str| -> Doc { Doc::text(s) };
+    let candidates = || {
+        [
+            text("everything on one line"),
+            text("short").concat(Doc::line().concat(text("a longer line")).nest(2)),
+            text("s").concat(Doc::line().concat(text("rest")).nest(2)),
+        ]
+    };
+    assert_eq!(
+        Doc::conditional_group(candidates()).render(22),
+        "everything on one line"
+    );
+    assert_snapshot!(Doc::conditional_group(candidates()).render(18), @r"
+    short
+      a longer line
+    ");
+    // Alternatives check only the first line; conditional groups check every line.
+    assert_snapshot!(Doc::alternatives(candidates()).render(14), @r"
+    short
+      a longer line
+    ");
+    assert_snapshot!(Doc::conditional_group(candidates()).render(14), @r"
+    s
+      rest
+    ");
+    // Candidates are measured from where they start, including their nesting.
+    let nested = text("x:").concat(
+        Doc::line()
+            .concat(Doc::conditional_group(candidates()))
+            .nest(4),
+    );
+    assert_snapshot!(nested.render(18), @r"
+    x:
+        s
+          rest
+    ");
+    // The last candidate is taken when none fits.
+    assert_eq!(Doc::conditional_group(candidates()).render(1), "s\n  rest");
+    assert_eq!(<Doc>::conditional_group([]).render(80), "");
+    // Candidates are measured with the render's options.
+    let v3 = RenderOptions::new(14).layout_version(LayoutVersion::V3);
+    let wide = Doc::conditional_group([text("日本語日本語"), text("broken")]);
+    assert_eq!(wide.render_with(&v3), "日本語日本語");
+    // What follows a candidate on its last line counts, as it does for groups.
+    let call = |candidates: [Doc; 2]| {
+        text("x = ")
+            .concat(Doc::conditional_group(candidates))
+            .concat(text(";;;;;;;;"))
+    };
+    let broken = || text("f(").concat(Doc::line_break().concat(text("a, b)")).nest(4));
+    let flat = call([text("f(a, b)"), broken()]);
+    assert_eq!(flat.clone().render(12), "x = f(\n    a, b);;;;;;;;");
+    assert_eq!(flat.render(19), "x = f(a, b);;;;;;;;");
+}
+
+#[test]
+fn conditional_group_markup() {
+    // Markup ten columns wide.
+    #[derive(Clone)]
+    struct Wide;
+
+    impl Annotation for Wide {
+        fn extra_width_flat(&self) -> Width {
+            10
+        }
+    }
+
+    let text = Doc::<Wide>::text;
+    let wide = |doc: Doc<Wide>| Doc::annotate(Wide, doc);
+    let newlines = |doc: Doc<Wide>, width| {
+        let events = doc.layout(width);
+        events
+            .filter(|event| matches!(event, RenderEvent::Newline { .. }))
+            .count()
+    };
+    let candidates = || {
+        [
+            wide(text("aaaa bbbb")),
+            wide(text("aaaa"))
+                .concat(Doc::<Wide>::line())
+                .concat(text("bbbb")),
+        ]
+    };
+    // Laid out flat, the markup makes the line 19 columns wide.
+    let group = wide(Doc::sep([text("aaaa"), text("bbbb")]));
+    assert_eq!(newlines(group.clone(), 12), 1);
+    assert_eq!(newlines(Doc::conditional_group(candidates()), 12), 1);
+    assert_eq!(newlines(group, 19), 0);
+    assert_eq!(newlines(Doc::conditional_group(candidates()), 19), 0);
+}
+
+#[test]
+fn optimal_layout() {
+    let optimal =
+        |width, costs| RenderOptions::new(width).algorithm(LayoutAlgorithm::Optimal(costs));
+    let costs = LayoutCosts::new();
+    let text = |s: &str| -> Doc { Doc::text(s) };
+
+    // Later lines of an alternative count too: hugging the arguments overflows on the second.
+    let args = || Doc::vsep([text("first_argument"), text("second_argument")]);
+    let call = Doc::alternatives([
+        text("call(").concat(args().align()).concat(text(")")),
+        text("call(")
+            .concat(Doc::line().concat(args()).nest(4))
+            .concat(Doc::line())
+            .concat(text(")")),
+    ]);
+    assert_snapshot!(call.clone().render(20), @r"
+    call(first_argument
+         second_argument)
+    ");
+    assert_snapshot!(call.render_with(&optimal(20, costs)), @r"
+    call(
+        first_argument
+        second_argument
+    )
+    ");
+
+    // What follows an alternative on its last line counts as well.
+    let statement = Doc::sep([text("let"), text("x"), text("=")])
+        .concat(Doc::softline())
+        .concat(text("value"))
+        .concat(text(";"));
+    assert_eq!(
+        statement.clone().render_with(&optimal(14, costs)),
+        "let x = value;"
+    );
+    assert_eq!(
+        statement.render_with(&optimal(13, costs)),
+        "let x =\nvalue;"
+    );
+
+    // Expensive line breaks make overflow worth it.
+    let words = Doc::sep(["a", "few", "short", "words"].map(text));
+    assert_eq!(
+        words.clone().render_with(&optimal(10, costs)),
+        "a\nfew\nshort\nwords"
+    );
+    let costs = costs.line(1000);
+    assert_eq!(words.render_with(&optimal(10, costs)), "a few short words");
+
+    // Failing alternatives are never taken.
+    let choice = Doc::fail().alt(text("fallback"));
+    assert_eq!(choice.render_with(&optimal(80, costs)), "fallback");
+}
+
+#[test]
+fn break_priority() {
+    fn text(s: &str) -> Doc {
+        Doc::text(s)
+    }
+    let sum = |a: &str, b: &str| {
+        text(a)
+            .concat(Doc::softline().break_priority(2))
+            .concat(text("+ "))
+            .concat(text(b))
+    };
+    let args = |priority: Option<u8>| {
+        let comma = match priority {
+            Some(priority) => Doc::softline().break_priority(priority),
+            None => Doc::softline(),
+        };
+        text("f(")
+            .concat(sum("alpha", "beta"))
+            .concat(text(","))
+            .concat(comma)
+            .concat(sum("gamma", "delta"))
+            .concat(text(")"))
+    };
+
+    // Untagged breaks aren't assumed to be taken, so the sums break first.
+    assert_snapshot!(args(None).render(20), @r"
+    f(alpha
+    + beta, gamma
+    + delta)
+    ");
+
+    // The argument boundary breaks before the sums do.
+    assert_snapshot!(args(Some(1)).render(20), @r"
+    f(alpha + beta,
+    gamma + delta)
+    ");
+    assert_snapshot!(args(Some(1)).render(15), @r"
+    f(alpha + beta,
+    gamma + delta)
+    ");
+
+    // Breaks of higher priority are only taken when the lower ones aren't enough.
+    assert_snapshot!(args(Some(1)).render(10), @r"
+    f(alpha
+    + beta,
+    gamma
+    + delta)
+    ");
+
+    // Reversing the priorities breaks inside the sums first.
+    assert_snapshot!(args(Some(3)).render(20), @r"
+    f(alpha
+    + beta, gamma
+    + delta)
+    ");
+}
+
+#[test]
+fn no_break() {
+    let literal = || -> Doc { Doc::sep(["\"a", "string", "literal\""].map(Doc::text)).group() };
+    let call = |arg: Doc| {
+        Doc::text("print(")
+            .concat(Doc::softline_empty().concat(arg).nest(4))
+            .concat(Doc::text(")"))
+            .group()
+    };
+    assert_snapshot!(call(literal()).render(12), @r#"
+    print(
+        "a
+        string
+        literal")
+    "#);
+
+    // The literal overflows rather than being split, but the breaks around it are still taken.
+    assert_snapshot!(call(literal().no_break()).render(12), @r#"
+    print(
+        "a string literal")
+    "#);
+
+    // Even hard breaks are kept on the line.
+    let lines: Doc = Doc::text("a").concat(Doc::line()).concat(Doc::text("b"));
+    assert_eq!(lines.no_break().render(80), "a b");
+}
+
+#[test]
+fn balanced_folding() {
+    fn depth(doc: &Doc) -> usize {
+        let (mut level, mut depth) = (vec![doc.clone()], 0);
+        while !level.is_empty() {
+            level = level.iter().flat_map(crate::analysis::children).collect();
+            depth += 1;
+        }
+        depth
+    }
+
+    let numbers = || (0..1024).map(|i| Doc::text(i.to_string()));
+    let list = Doc::intersperse(numbers(), Doc::text(","));
+    assert!(depth(&list) <= 32, "depth {}", depth(&list));
+    let expected: Vec<String> = (0..1024).map(|i| i.to_string()).collect();
+    assert_eq!(list.render(80), expected.join(","));
+    assert!(depth(&Doc::hcat(numbers())) <= 16);
+
+    // Grouping doesn't change the order of the documents.
+    let letters = || ["a", "b", "c", "d", "e"].map(<Doc>::text);
+    let spaced = Doc::hsep(letters());
+    assert_eq!(spaced.render(80), "a b c d e");
+    // `concat_with` still folds from the left, for combinators that aren't associative.
+    let nested = Doc::concat_with(letters(), |x, y| Doc::hcat([x, y]).parens());
+    assert_eq!(nested.render(80), "((((ab)c)d)e)");
+    assert_eq!(<Doc>::concat_with([], Doc::concat).render(80), "");
+}
+
+#[test]
+fn normalize() {
+    // A generated call, one token per node.
+    let call = |name: &str, args: &[&str]| -> Doc {
+        let args = args
+            .iter()
+            .map(|arg| Doc::hcat([Doc::text(*arg), Doc::nil()]));
+        Doc::hcat([Doc::text(name), Doc::text("(")])
+            .concat(
+                Doc::softline_empty()
+                    .concat(Doc::intersperse(
+                        args,
+                        Doc::text(",").concat(Doc::softline()),
+                    ))
+                    .nest(2)
+                    .nest(2),
+            )
+            .concat(Doc::softline_empty())
+            .concat(Doc::text(")"))
+            .group()
+    };
+    let doc = Doc::vsep([
+        call("f", &["x", "y"]),
+        call("g", &["alpha", "beta", "gamma"]),
+    ]);
+    let normal = doc.normalize();
+    assert!(normal.sharing_report().nodes < doc.sharing_report().nodes);
+    for width in [80, 10] {
+        assert_eq!(normal.clone().render(width), doc.clone().render(width));
+    }
+    assert_snapshot!(normal.render(10), @r"
+    f(x, y)
+    g(
+        alpha,
+        beta, gamma)
+    ");
+
+    let text: Doc = Doc::hcat(["a", "", "b", "c"].map(Doc::text))
+        .nest(4)
+        .nest(-4);
+    assert_eq!(text.normalize().sharing_report().nodes, 1);
+
+    // Shared subtrees stay shared.
+    let shared: Doc = Doc::text("x").concat(Doc::line()).group();
+    let pair = shared.clone().concat(shared).normalize();
+    let report = pair.sharing_report();
+    assert_eq!(report.nodes, report.distinct);
+}
+
+#[test]
+fn debug_structure() {
+    let doc = Doc::annotate("keyword", Doc::<&str>::text("fn"))
+        .concat(
+            Doc::<&str>::line()
+                .concat(Doc::<&str>::text("main"))
+                .nest(4)
+                .group(),
+        )
+        .concat(Doc::column(|_| Doc::<&str>::nil()));
+    let group: Doc = Doc::text("a").concat(Doc::line()).nest(2).group();
+    assert_snapshot!(
+        format!("{:?}", group),
+        @r#"Alt(Flat(Nest(2, Concat(Text("a"), Line))), Nest(2, Concat(Text("a"), Line)))"#
+    );
+    assert_snapshot!(format!("{:#?}", doc), @r#"
+    Concat(
+        Concat(
+            Annotate(
+                "keyword",
+                Text("fn"),
+            ),
+            Alt(
+                Flat(
+                    Nest(
+                        4,
+                        Concat(
+                            Line,
+                            Text("main"),
+                        ),
+                    ),
+                ),
+                Nest(
+                    4,
+                    Concat(
+                        Line,
+                        Text("main"),
+                    ),
+                ),
+            ),
+        ),
+        Column(<fn>),
+    )
+    "#);
+}
+
+#[test]
+fn doc_stats() {
+    // Both alternatives of a grouped text are the same node.
+    let leaf: Doc = Doc::text("leaf").group();
+    let stats = leaf.stats();
+    assert_eq!((stats.nodes, stats.max_depth, stats.text_bytes), (2, 2, 4));
+
+    // A shared subtree is counted once, and the deepest path wins.
+    let shared = Doc::sep(["x", "yz"].map(Doc::text));
+    let doc = shared
+        .clone()
+        .concat(Doc::line())
+        .concat(shared.nest(2))
+        .concat(Doc::text("!"));
+    let stats = doc.stats();
+    assert_eq!(stats.nodes, doc.sharing_report().nodes);
+    assert_eq!(stats.text_bytes, 4);
+    assert_eq!(stats.alternatives, 1);
+    assert_eq!(stats.max_depth, shared_depth(&doc));
+
+    fn shared_depth(doc: &Doc) -> usize {
+        let children = crate::analysis::children(doc);
+        1 + children.iter().map(shared_depth).max().unwrap_or(0)
+    }
+}
+
+#[test]
+fn measure() {
+    let doc = Doc::annotate(
+        Style::new().bold(true),
+        Doc::<Style>::text("fn main() {")
+            .concat(
+                Doc::<Style>::line()
+                    .concat(Doc::<Style>::text("println!(\"héllo\");"))
+                    .nest(4),
+            )
+            .concat(Doc::<Style>::line())
+            .concat(Doc::<Style>::text("}")),
+    );
+    let dimensions = |output: &str| Dimensions {
+        lines: output.split('\n').count(),
+        max_line_width: output
+            .split('\n')
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0),
+        chars: output.chars().count(),
+    };
+    let options = [
+        RenderOptions::new(80),
+        RenderOptions::new(80).line_numbers(3),
+        RenderOptions::new(80).indent_guides(2),
+    ];
+    for options in options {
+        let rendered = doc.clone().render_plain_with(&options);
+        assert_eq!(doc.measure_with(&options), dimensions(&rendered));
+    }
+    assert_eq!(
+        doc.measure(80),
+        Dimensions {
+            lines: 3,
+            // `é` takes one column, though it's two bytes.
+            max_line_width: 22,
+            chars: 36,
+        }
+    );
+    assert_eq!(
+        <Doc>::nil().measure(80),
+        Dimensions {
+            lines: 1,
+            ..Dimensions::default()
+        }
+    );
+}
+
+#[test]
+fn fold_and_nodes() {
+    let keyword = |s: &str| Doc::annotate("keyword", Doc::<&str>::text(s.to_string()));
+    let doc = Doc::sep([
+        keyword("let"),
+        Doc::<&str>::text("x"),
+        Doc::<&str>::text("="),
+    ])
+    .concat(Doc::<&str>::line().concat(Doc::<&str>::text("1")).nest(4));
+
+    // The greatest nesting of any part of the document.
+    let nesting = doc.fold(|doc, children: Vec<Width>| {
+        let deepest = children.into_iter().max().unwrap_or(0);
+        match doc.node() {
+            DocNode::Nest(i, _) => deepest + i,
+            _ => deepest,
+        }
+    });
+    assert_eq!(nesting, 4);
+
+    // Rename keywords, rebuilding only what changes.
+    let renamed = doc.fold(|doc, children| match doc.node() {
+        DocNode::Annotate(&"keyword", _) => Doc::annotate("keyword", Doc::<&str>::text("var")),
+        _ => doc.with_children(children),
+    });
+    assert_eq!(renamed.render(80), "var x =\n    1");
+    assert_eq!(doc.render(80), "let x =\n    1");
+
+    // Deep documents don't recurse.
+    let mut deep: Doc = Doc::text("x");
+    for _ in 0..200_000 {
+        deep = deep.nest(1);
+    }
+    assert_eq!(
+        deep.fold(|_, children: Vec<usize>| children.len() + children.iter().sum::<usize>()),
+        200_000
+    );
+}
+
+#[test]
+fn map_text() {
+    // Eastern Arabic digits take two bytes each, so the layout changes with them.
+    let arabic = |s: &str| -> String {
+        s.chars()
+            .map(|c| match c.to_digit(10) {
+                Some(d) => char::from_u32(0x0660 + d).unwrap_or(c),
+                None => c,
+            })
+            .collect()
+    };
+    let numbers = || -> Doc { Doc::sep(["12", "345", "6789"].map(Doc::text)) };
+    assert_eq!(numbers().render(12), "12 345 6789");
+    let localized = numbers().map_text(arabic);
+    assert_eq!(localized.clone().render(80), "١٢ ٣٤٥ ٦٧٨٩");
+    assert_eq!(localized.clone().render(12), "١٢\n٣٤٥\n٦٧٨٩");
+
+    // Rewrites nest, innermost first, and survive normalization.
+    let doc: Doc = Doc::text("ab")
+        .concat(Doc::text("c").map_text(|s| format!("[{s}]")))
+        .map_text(|s| s.to_uppercase());
+    assert_eq!(doc.clone().render(80), "AB[C]");
+    assert_eq!(doc.normalize().render(80), "AB[C]");
+    let capitalized = Doc::hcat(["a", "b"].map(<Doc>::text)).map_text(|s| {
+        let mut chars = s.chars();
+        chars
+            .next()
+            .map_or(String::new(), |c| c.to_uppercase().chain(chars).collect())
+    });
+    assert_eq!(capitalized.normalize().render(80), "AB");
+}
+
+#[test]
+fn text_sanitized() {
+    let field = |s: &str| -> Doc {
+        Doc::text("name:")
+            .concat(Doc::line().concat(Doc::text_sanitized(s)).nest(4))
+            .concat(Doc::line())
+            .concat(Doc::text("end"))
+    };
+    assert_snapshot!(field("plain text, ünïcode").render(80), @r"
+    name:
+        plain text, ünïcode
+    end
+    ");
+    // A stray newline would otherwise escape the indentation.
+    assert_snapshot!(field("line\nbreak\r\tand \u{7f}\u{9b}").render(80), @r"
+    name:
+        line\nbreak\r\tand \u{7f}\u{9b}
+    end
+    ");
+}
+
+#[test]
+fn validate() {
+    let message = "first line\nsecond line";
+    let good: Doc = Doc::text("note:")
+        .concat(Doc::lines(message).nest(2))
+        .group();
+    assert_eq!(good.validate(), Ok(()));
+    assert_eq!(
+        Doc::text_sanitized(message).concat(good.clone()).validate(),
+        Ok(())
+    );
+
+    let bad = good.concat(Doc::annotate((), Doc::text(message)).nest(2));
+    assert_eq!(
+        bad.validate(),
+        Err(ValidationError::NewlineInText {
+            text: message.to_string()
+        })
+    );
+    assert_eq!(
+        bad.validate().unwrap_err().to_string(),
+        r#"text contains a line break: "first line\nsecond line""#
+    );
+}
+
+#[test]
+fn wide_layouts() {
+    // Lines past 32k columns used to wrap around to negative widths.
+    let long = "x".repeat(40_000);
+    let doc: Doc = Doc::text(long.clone())
+        .concat(Doc::line())
+        .concat(Doc::text("end"))
+        .group();
+    assert_eq!(doc.clone().render(50_000), format!("{long} end"));
+    assert_eq!(doc.render(80), format!("{long}\nend"));
+    let column: Doc = Doc::text(long).concat(Doc::column(|c| Doc::text(c.to_string())));
+    assert!(column.render(80).ends_with("40000"));
+
+    // Nesting accumulates past 32k columns, too.
+    let mut nested: Doc = Doc::line().concat(Doc::text("x"));
+    for _ in 0..5 {
+        nested = nested.nest(10_000);
+    }
+    assert_eq!(nested.render(80).len(), 1 + 50_000 + 1);
+}
+
+#[test]
+fn tab_expansion() {
+    let doc: Doc = Doc::text("a\tb")
+        .concat(Doc::line())
+        .concat(Doc::text("cdef"))
+        .group();
+    // Unexpanded, the tab counts as one column.
+    assert_eq!(doc.clone().render(8), "a\tb cdef");
+    let options = RenderOptions::new(8).tab_width(4);
+    assert_eq!(doc.render_with(&options), "a   b\ncdef");
+
+    // Tab stops are counted from the left margin, not from where the text starts.
+    let nested: Doc = Doc::text("x")
+        .concat(Doc::line().concat(Doc::text("\ty\tz")).nest(2))
+        .concat(Doc::line().concat(Doc::text("ab\tc")));
+    let options = RenderOptions::new(80).tab_width(4);
+    assert_eq!(nested.render_with(&options), "x\n    y   z\nab  c");
+}
+
+#[test]
+fn literal_text_is_borrowed() {
+    use std::borrow::Cow;
+
+    let doc: Doc = Doc::static_text("let")
+        .concat(Doc::line())
+        .concat(Doc::text("x1"))
+        .group();
+    let events: Vec<RenderEvent> = doc.layout(80).collect();
+    assert!(matches!(
+        &events[0],
+        RenderEvent::Text(Cow::Borrowed("let"))
+    ));
+    assert!(matches!(&events[1], RenderEvent::Text(Cow::Borrowed(" "))));
+    assert!(matches!(&events[2], RenderEvent::Text(Cow::Owned(s)) if s == "x1"));
+}
+
+#[test]
+fn text_slices() {
+    use std::rc::Rc;
+
+    let source: Rc<str> = Rc::from("fn main() {}");
+    let name: Doc = Doc::text_slice(&source, 3..7);
+    let doc = Doc::text_slice(&source, 0..2)
+        .concat(Doc::space())
+        .concat(name.clone().map_text(str::to_uppercase))
+        .concat(Doc::text_slice(&source, 7..12));
+    // The slices share the source rather than copying it.
+    assert_eq!(Rc::strong_count(&source), 4);
+    assert_eq!(doc.render(80), "fn MAIN() {}");
+    assert_eq!(name.stats().text_bytes, 4);
+    assert!(matches!(name.node(), DocNode::Text("main")));
+}
+
+#[test]
+fn documents_cross_threads() {
+    // Build on worker threads, and render on this one.
+    let workers: Vec<_> = (0..4)
+        .map(|i| {
+            std::thread::spawn(move || -> SyncDoc<Semantic> {
+                let value = SyncDoc::column(move |c| SyncDoc::text(format!("{i} at {c}")));
+                SyncDoc::annotate(Semantic::Keyword, SyncDoc::static_text("let"))
+                    .concat(SyncDoc::softline())
+                    .concat(value)
+                    .group()
+            })
+        })
+        .collect();
+    let docs = workers
+        .into_iter()
+        .map(|worker| worker.join().unwrap().into());
+    let doc: Doc<Semantic> = Doc::vsep(docs);
+    assert_eq!(
+        doc.render(80),
+        "let 0 at 4\nlet 1 at 4\nlet 2 at 4\nlet 3 at 4"
+    );
+    // Subtrees shared in a `SyncDoc` stay shared once converted.
+    let item: SyncDoc = SyncDoc::text("item");
+    let pair: Doc = SyncDoc::concat(item.clone(), item).into();
+    assert_eq!(pair.sharing_report().nodes, 2);
+}
+
+#[test]
+fn plain_documents_infer() {
+    // Without an annotation in sight, leaf constructors build plain documents.
+    let d = Doc::text("a").concat(Doc::line());
+    assert_eq!(d.group().render(80), "a ");
+    let keyword = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("fn"));
+    assert_eq!(keyword.concat(Doc::<Semantic>::space()).render(80), "fn ");
+}
+
+#[test]
+fn doc_arena() {
+    let names: Vec<String> = (0..2_000).map(|i| format!("item{i}")).collect();
+    let direct: Doc = Doc::intersperse(
+        names.iter().map(|name| Doc::text(name.clone())),
+        Doc::text(",").concat(Doc::softline()),
+    )
+    .nest(4)
+    .group();
+
+    let mut arena: DocArena = DocArena::with_capacity(6 * names.len());
+    let mut list = arena.text(names[0].clone());
+    for name in &names[1..] {
+        let (comma, softline, name) = (arena.text(","), arena.softline(), arena.text(name.clone()));
+        let separator = arena.concat(comma, softline);
+        let item = arena.concat(separator, name);
+        list = arena.concat(list, item);
+    }
+    let list = arena.nest(list, 4);
+    let list = arena.group(list);
+    arena.text("unused");
+    assert_eq!(arena.len(), 6 * names.len() - 2);
+    let built = arena.build(list);
+    assert_eq!(built.clone().render(60), direct.clone().render(60));
+    assert_eq!(built.render(1_000_000), direct.render(1_000_000));
+
+    // Statements without line breaks are one text node each, their nesting and groups dropped.
+    let mut arena: DocArena = DocArena::new();
+    let mut direct: Doc = Doc::nil();
+    let mut body = arena.nil();
+    for i in 0..1_000 {
+        let name = format!("x{i}");
+        let tokens = [
+            Doc::text("let "),
+            Doc::text(name.clone()),
+            Doc::text(" = 1;"),
+        ];
+        direct = direct
+            .concat(Doc::hcat(tokens).nest(4).group())
+            .concat(Doc::line());
+        let tokens = [arena.text("let "), arena.text(name), arena.text(" = 1;")];
+        let statement = tokens
+            .into_iter()
+            .reduce(|x, y| arena.concat(x, y))
+            .unwrap();
+        let statement = arena.nest(statement, 4);
+        let (statement, line) = (arena.group(statement), arena.line());
+        let statement = arena.concat(statement, line);
+        body = arena.concat(body, statement);
+    }
+    let direct = direct.nest(2).group();
+    let body = arena.nest(body, 2);
+    let body = arena.group(body);
+    let built = arena.build(body);
+    assert_eq!(direct.stats().nodes, 10_005);
+    assert_eq!(built.stats().nodes, 3_003);
+    assert_eq!(built.render(80), direct.render(80));
+
+    // A handle used twice is one shared node, and deep documents build without recursing.
+    let mut arena: DocArena = DocArena::new();
+    let (x, y) = (arena.text("x"), arena.text("y"));
+    let mut deep = arena.concat(x, y);
+    for _ in 0..100_000 {
+        deep = arena.concat(x, deep);
+    }
+    let doc = arena.build(deep);
+    assert_eq!(doc.sharing_report().nodes, 100_003);
+    assert_eq!(doc.render(80).len(), 100_002);
+
+    // Cleared arenas are reused.
+    arena.clear();
+    assert!(arena.is_empty());
+    let (x, space, y) = (
+        arena.text("x"),
+        arena.space(),
+        arena.text(String::from("y")),
+    );
+    let xy = arena.concat(x, space);
+    let xy = arena.concat(xy, y);
+    let doc = arena.build(xy);
+    assert_eq!(doc.stats().nodes, 1);
+    assert_eq!(doc.render(80), "x y");
+}
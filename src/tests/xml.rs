@@ -1,171 +1,7 @@
 // Copyright 2025 Cameron Swords
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::*;
-
-// -------------------------------------------------------------------------------------------------
-// XML AST a la Wadler
-// -------------------------------------------------------------------------------------------------
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub enum XML {
-    Element {
-        name: String,
-        attrs: Vec<Attribute>,
-        body: Vec<XML>,
-    },
-    Text(String),
-}
-
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Attribute {
-    pub name: String,
-    pub value: String,
-}
-
-impl Attribute {
-    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Self {
-        Self {
-            name: name.into(),
-            value: value.into(),
-        }
-    }
-}
-
-impl XML {
-    pub fn text<S: Into<String>>(s: S) -> Self {
-        XML::Text(s.into())
-    }
-
-    pub fn element<N: Into<String>>(name: N, attrs: Vec<Attribute>, body: Vec<XML>) -> Self {
-        XML::Element {
-            name: name.into(),
-            attrs,
-            body,
-        }
-    }
-
-    /// Convenience: `XML::elem("a", [("href","/")], [XML::text("home")])`
-    pub fn elem(name: &str, attrs: Vec<(&str, &str)>, children: Vec<XML>) -> Self {
-        let name = name.into();
-        let attrs = attrs
-            .into_iter()
-            .map(|p| {
-                let (n, v) = p.into();
-                Attribute::new(n, v)
-            })
-            .collect();
-        let body = children.into_iter().collect();
-        XML::Element { name, attrs, body }
-    }
-}
-
-// -------------------------------------------------------------------------------------------------
-// XML to Doc
-// -------------------------------------------------------------------------------------------------
-
-pub fn xml_doc_pretty(x: &XML) -> Doc {
-    match x {
-        XML::Text(s) => Doc::text(escape_text(s)),
-
-        XML::Element { name, attrs, body } => {
-            let open_head = Doc::langle()
-                .concat(Doc::text(name.clone()))
-                .concat(attrs_doc(attrs));
-
-            if body.is_empty() {
-                // <tag .../>
-                return open_head.concat_space(Doc::text("/>"));
-            }
-
-            let open = open_head.clone().concat(Doc::rangle());
-            let close = Doc::text("</".to_string())
-                .concat(Doc::text(name.clone()))
-                .concat(Doc::rangle());
-
-            if body.iter().any(|entry| matches!(entry, XML::Text(_))) {
-                return open
-                    .concat(Doc::hsep(body.iter().map(xml_doc_pretty)))
-                    .concat(close);
-            }
-
-            // Soft separator between children: space when flat, newline when broken
-            let kids_soft = Doc::sep(body.iter().map(xml_doc_pretty));
-
-            // Inline: no leading/trailing softlines → no stray spaces
-            let inline = open
-                .clone()
-                .concat(kids_soft.clone().flatten()) // children separated by spaces
-                .concat(close.clone());
-
-            // Block: one child per line, indented. No .group() on the kids.
-            let kids_vertical = body
-                .iter()
-                .map(xml_doc_pretty)
-                .reduce(|a, b| a.concat(Doc::line()).concat(b))
-                .unwrap_or_else(Doc::nil);
-
-            let block = open
-                .concat(Doc::line())
-                .concat(kids_vertical.indent(4))
-                .concat(Doc::line())
-                .concat(close);
-
-            // Choose: first if it fits, otherwise the vertical one.
-            Doc::alt(inline, block)
-        }
-    }
-}
-
-pub fn xml_doc_compact(x: &XML) -> Doc {
-    xml_doc_pretty(x).flatten()
-}
-
-// -------------------------------------------------------------------------------------------------
-// Attributes
-// -------------------------------------------------------------------------------------------------
-
-fn attrs_doc(attrs: &[Attribute]) -> Doc {
-    if attrs.is_empty() {
-        return Doc::nil();
-    }
-    let parts = attrs.iter().map(|a| {
-        Doc::text(&a.name)
-            .concat(Doc::text("=\""))
-            .concat(Doc::text(escape_attr(&a.value)))
-            .concat(Doc::text("\""))
-    });
-    // Leading space before first attribute, then space-separated list.
-    Doc::text(" ").concat(Doc::hsep(parts))
-}
-
-fn escape_text(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '&' => out.push_str("&amp;"),
-            '<' => out.push_str("&lt;"),
-            '>' => out.push_str("&gt;"),
-            _ => out.push(ch),
-        }
-    }
-    out
-}
-
-fn escape_attr(s: &str) -> String {
-    let mut out = String::with_capacity(s.len());
-    for ch in s.chars() {
-        match ch {
-            '&' => out.push_str("&amp;"),
-            '<' => out.push_str("&lt;"),
-            '>' => out.push_str("&gt;"),
-            '"' => out.push_str("&quot;"),
-            '\'' => out.push_str("&apos;"),
-            _ => out.push(ch),
-        }
-    }
-    out
-}
+use crate::{examples::xml::*, *};
 
 // -------------------------------------------------------------------------------------------------
 // Tests
@@ -176,7 +12,7 @@ mod tests {
     use insta::assert_snapshot;
 
     // Adjust this helper if your API differs (e.g., `render_to_string(width)`).
-    fn render(d: Doc, width: i16) -> String {
+    fn render(d: Doc, width: Width) -> String {
         d.render(width)
     }
 
@@ -256,4 +92,31 @@ mod tests {
         assert_snapshot!("t7_pretty_narrow", render(xml_doc_pretty(&xml), 6));
         assert_snapshot!("t7_pretty_wide", render(xml_doc_pretty(&xml), 30));
     }
+
+    #[test]
+    fn t8_roundtrip() {
+        let li = |s| XML::elem("li", vec![], vec![XML::text(s)]);
+        let docs = [
+            XML::elem("ul", vec![], vec![li("one"), li("two"), li("three")]),
+            XML::elem(
+                "p",
+                vec![("class", "it's \"quoted\"")],
+                vec![
+                    XML::text("Hello "),
+                    XML::elem("em", vec![], vec![XML::text("world")]),
+                    XML::text(" & <goodbye>!"),
+                ],
+            ),
+        ];
+        for xml in &docs {
+            for width in [4, 20, 120] {
+                check_roundtrip(xml, width).unwrap();
+            }
+        }
+        check_source_roundtrip(
+            "<?xml version=\"1.0\"?><a href='/x?a=1&amp;b=2'><!-- c --><b>x &#x3C; y</b> <i/></a>",
+            10,
+        )
+        .unwrap();
+    }
 }
@@ -0,0 +1,178 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    style::Ansi, Annotation, Color, Doc, HtmlClass, Layout, Markup, RenderOptions, Style, Width,
+};
+
+// -------------------------------------------------------------------------------------------------
+// Semantic Annotations
+// -------------------------------------------------------------------------------------------------
+
+/// What a region of a document is, as opposed to how it looks. Annotating with these keeps
+/// [`ToDoc`](crate::ToDoc) implementations free of presentation; a [`Theme`] picks the styles
+/// when rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Semantic {
+    Keyword,
+    Literal,
+    Comment,
+    Error,
+}
+
+impl Annotation for Semantic {}
+
+/// Regions become classes named after their kind, such as `keyword`.
+impl HtmlClass for Semantic {
+    fn html_class(&self) -> Option<String> {
+        Some(format!("{:?}", self).to_lowercase())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Themes
+// -------------------------------------------------------------------------------------------------
+
+/// The [`Style`] for each kind of [`Semantic`] region.
+///
+/// # Example
+/// ```rust
+/// use pretty_simple::*;
+///
+/// let doc = Doc::annotate(Semantic::Keyword, Doc::<Semantic>::text("fn"))
+///     .concat(Doc::<Semantic>::text(" main"));
+/// let theme = Theme::plain().keyword(Style::new().bold(true));
+/// assert_eq!(doc.render_themed(&theme, 80), "\x1b[1mfn\x1b[0m main");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Theme {
+    keyword: Style,
+    literal: Style,
+    comment: Style,
+    error: Style,
+}
+
+/// Bold magenta keywords, green literals, italic blue comments, and bold red errors.
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            keyword: Style::new().bold(true).fg(Color::Magenta),
+            literal: Style::new().fg(Color::Green),
+            comment: Style::new().italic(true).fg(Color::Blue),
+            error: Style::new().bold(true).fg(Color::Red),
+        }
+    }
+}
+
+impl Theme {
+    /// The theme that leaves every region plain, unlike the colorful default theme.
+    pub fn plain() -> Self {
+        Theme {
+            keyword: Style::new(),
+            literal: Style::new(),
+            comment: Style::new(),
+            error: Style::new(),
+        }
+    }
+
+    /// The style for keywords.
+    pub fn keyword(mut self, style: Style) -> Self {
+        self.keyword = style;
+        self
+    }
+
+    /// The style for literals.
+    pub fn literal(mut self, style: Style) -> Self {
+        self.literal = style;
+        self
+    }
+
+    /// The style for comments.
+    pub fn comment(mut self, style: Style) -> Self {
+        self.comment = style;
+        self
+    }
+
+    /// The style for errors.
+    pub fn error(mut self, style: Style) -> Self {
+        self.error = style;
+        self
+    }
+
+    /// The style for regions of the given kind.
+    pub fn style(&self, kind: Semantic) -> Style {
+        match kind {
+            Semantic::Keyword => self.keyword,
+            Semantic::Literal => self.literal,
+            Semantic::Comment => self.comment,
+            Semantic::Error => self.error,
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+// Themed Rendering
+// -------------------------------------------------------------------------------------------------
+
+/// Looks up each region's style as it's opened, and renders it as ANSI escape codes.
+struct Themed<'a> {
+    theme: &'a Theme,
+    ansi: Ansi,
+}
+
+impl Markup<Semantic> for Themed<'_> {
+    fn push(&mut self, kind: &Semantic, output: &mut String) {
+        self.ansi.push(&self.theme.style(*kind), output);
+    }
+
+    fn pop(&mut self, output: &mut String) {
+        Markup::<Style>::pop(&mut self.ansi, output);
+    }
+}
+
+impl Doc<Semantic> {
+    /// Render the document at the given `width`, styling each region as `theme` says. See
+    /// [`Doc::render_ansi`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_themed(&self, theme: &Theme, width: Width) -> String {
+        self.render_themed_with(theme, &RenderOptions::new(width))
+    }
+
+    /// Render the document using the given [`RenderOptions`], styling each region as `theme`
+    /// says if [`RenderOptions::color`] allows it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn render_themed_with(&self, theme: &Theme, options: &RenderOptions) -> String {
+        self.clone().layout_with(options).to_ansi(theme)
+    }
+}
+
+impl Layout<Semantic> {
+    /// Paint the layout with ANSI escape codes, styling each region as `theme` says if the
+    /// layout's [`RenderOptions::color`] allows it. See [`Doc::render_themed`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if rendering fails.
+    pub fn to_ansi(&self, theme: &Theme) -> String {
+        let mut output = String::new();
+        let result = if self.options.color.enabled() {
+            let markup = &mut Themed {
+                theme,
+                ansi: Ansi::default(),
+            };
+            self.paint(&mut output, markup)
+        } else {
+            self.paint(&mut output, &mut ())
+        };
+        if let Err(err) = result {
+            panic!("failed to render document: {}", err)
+        }
+        output
+    }
+}
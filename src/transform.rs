@@ -0,0 +1,212 @@
+// Copyright 2025 Cameron Swords
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{analysis::children, Annotation, Doc, DocFn, DocInner, LayoutContext, LeafDoc, Width};
+
+// -------------------------------------------------------------------------------------------------
+// Annotation Mapping
+// -------------------------------------------------------------------------------------------------
+
+type AnnotationFn<A, B> = Rc<dyn Fn(&A) -> Option<B>>;
+
+impl<A: Annotation> Doc<A> {
+    /// Convert this document's annotations with `f`, e.g., from semantic tags chosen by a library
+    /// to the concrete styles of a back end. Shared subtrees stay shared.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// #[derive(Clone)]
+    /// enum Tag {
+    ///     Keyword,
+    ///     Name,
+    /// }
+    ///
+    /// impl Annotation for Tag {}
+    ///
+    /// let doc = Doc::annotate(Tag::Keyword, Doc::<Tag>::text("let"))
+    ///     .concat_space(Doc::annotate(Tag::Name, Doc::<Tag>::text("x")));
+    /// let styled = doc.map_annotations(|tag| match tag {
+    ///     Tag::Keyword => "kw",
+    ///     Tag::Name => "name",
+    /// });
+    /// assert_eq!(
+    ///     styled.render_html(80),
+    ///     "<span class=\"kw\">let</span> <span class=\"name\">x</span>"
+    /// );
+    /// ```
+    pub fn map_annotations<B: Annotation>(&self, f: impl Fn(&A) -> B + 'static) -> Doc<B> {
+        self.filter_map_annotations(&(Rc::new(move |a: &A| Some(f(a))) as AnnotationFn<A, B>))
+    }
+
+    /// Remove this document's annotations.
+    pub fn strip_annotations(&self) -> Doc {
+        self.filter_map_annotations(&(Rc::new(|_: &A| None) as AnnotationFn<A, ()>))
+    }
+
+    // This document without its annotations, as a document of any annotation type, so that
+    // `doc_template!` can splice plain pieces among annotated ones.
+    pub(crate) fn unannotated<B: Annotation>(&self) -> Doc<B> {
+        self.filter_map_annotations(&(Rc::new(|_: &A| None) as AnnotationFn<A, B>))
+    }
+
+    // Rebuild the document bottom-up (without recursing, so deep documents are fine), replacing
+    // each annotation with `f`'s result, or with the annotated document alone if that's `None`.
+    fn filter_map_annotations<B: Annotation>(&self, f: &AnnotationFn<A, B>) -> Doc<B> {
+        let mut mapped: HashMap<*const DocInner<A>, Doc<B>> = HashMap::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if mapped.contains_key(&Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            if !children_done {
+                let children = children(&doc);
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let child = |d: &Doc<A>| mapped[&Rc::as_ptr(&d.0)].clone();
+            let node = match &*doc.0 {
+                DocInner::Empty => Doc::<B>::nil(),
+                DocInner::Text(s) => DocInner::Text(s.clone()).into_doc(),
+                DocInner::Line => Doc::<B>::line(),
+                DocInner::LineBreak => Doc::<B>::line_break(),
+                DocInner::Fail => Doc::<B>::fail(),
+                DocInner::Concat(x, y) => DocInner::Concat(child(x), child(y)).into_doc(),
+                DocInner::Nest(i, x) => DocInner::Nest(*i, child(x)).into_doc(),
+                DocInner::Alt(x, y) => DocInner::Alt(child(x), child(y)).into_doc(),
+                DocInner::FlatAlt(x, y) => DocInner::FlatAlt(child(x), child(y)).into_doc(),
+                DocInner::Nesting(g) => DocInner::Nesting(map_fn(g, f)).into_doc(),
+                DocInner::Column(g) => DocInner::Column(map_fn(g, f)).into_doc(),
+                DocInner::Depth(g) => DocInner::Depth(map_fn(g, f)).into_doc(),
+                DocInner::PageWidth(g) => DocInner::PageWidth(map_fn(g, f)).into_doc(),
+                DocInner::Context(g) => {
+                    let (g, f) = (g.clone(), f.clone());
+                    let g =
+                        move |context: &LayoutContext<'_>| g(context).filter_map_annotations(&f);
+                    DocInner::Context(Rc::new(g)).into_doc()
+                }
+                DocInner::Memo(x, _) => child(x).memoize(),
+                DocInner::Flat(x) => DocInner::Flat(child(x)).into_doc(),
+                DocInner::Expand(x) => DocInner::Expand(child(x)).into_doc(),
+                DocInner::Prefix(s, x) => DocInner::Prefix(s.clone(), child(x)).into_doc(),
+                DocInner::Narrow(n, x) => DocInner::Narrow(*n, child(x)).into_doc(),
+                DocInner::Hint(w, x) => DocInner::Hint(*w, child(x)).into_doc(),
+                DocInner::Priority(p, x) => DocInner::Priority(*p, child(x)).into_doc(),
+                DocInner::MapText(g, x) => DocInner::MapText(g.clone(), child(x)).into_doc(),
+                DocInner::Elide(n, x, y) => DocInner::Elide(*n, child(x), child(y)).into_doc(),
+                DocInner::Annotate(a, x) => match f(a) {
+                    Some(b) => DocInner::Annotate(b, child(x)).into_doc(),
+                    None => child(x),
+                },
+                DocInner::EndAnnotation(w) => DocInner::EndAnnotation(*w).into_doc(),
+            };
+            mapped.insert(Rc::as_ptr(&doc.0), node);
+        }
+        mapped[&Rc::as_ptr(&self.0)].clone()
+    }
+}
+
+// Documents built by `g` are mapped as they're built.
+fn map_fn<A: Annotation, B: Annotation>(g: &DocFn<A>, f: &AnnotationFn<A, B>) -> DocFn<B> {
+    let (g, f) = (g.clone(), f.clone());
+    Rc::new(move |i| g(i).filter_map_annotations(&f))
+}
+
+// -------------------------------------------------------------------------------------------------
+// Normalization
+// -------------------------------------------------------------------------------------------------
+
+impl<A: Annotation> Doc<A> {
+    /// Rebuild the document with fewer nodes, for generated documents made of many tiny pieces:
+    /// adjacent text is fused, empty documents are dropped from concatenations, and nested
+    /// [`Doc::nest`]s are collapsed into one. The result renders identically, and shared
+    /// subtrees stay shared. Documents built by closures (e.g., [`Doc::column`]) and documents
+    /// whose text is rewritten (see [`Doc::map_text`]) are left as they are.
+    ///
+    /// # Example
+    /// ```rust
+    /// use pretty_simple::*;
+    ///
+    /// let doc: Doc = Doc::hcat(["f", "(", "", "x", ")"].map(Doc::text)).nest(2).nest(2);
+    /// let normal = doc.normalize();
+    /// assert_eq!(normal.sharing_report().nodes, 1);
+    /// assert_eq!(normal.render(80), "f(x)");
+    /// ```
+    pub fn normalize(&self) -> Doc<A> {
+        let mut normal: HashMap<*const DocInner<A>, Doc<A>> = HashMap::new();
+        let mut stack = vec![(self.clone(), false)];
+        while let Some((doc, children_done)) = stack.pop() {
+            if normal.contains_key(&Rc::as_ptr(&doc.0)) {
+                continue;
+            }
+            // Rewriting fused text isn't the same as rewriting its pieces.
+            if let DocInner::MapText(..) = &*doc.0 {
+                normal.insert(Rc::as_ptr(&doc.0), doc.clone());
+                continue;
+            }
+            if !children_done {
+                let children = children(&doc);
+                stack.push((doc, true));
+                stack.extend(children.into_iter().map(|child| (child, false)));
+                continue;
+            }
+            let child = |d: &Doc<A>| normal[&Rc::as_ptr(&d.0)].clone();
+            let node = match &*doc.0 {
+                DocInner::Text(s) if s.is_empty() => Doc::<A>::nil(),
+                DocInner::Concat(x, y) => concat_normal(child(x), child(y)),
+                DocInner::Nest(i, x) => nest_normal(*i, child(x)),
+                _ => {
+                    let old = children(&doc);
+                    let new: Vec<Doc<A>> = old.iter().map(child).collect();
+                    if old.iter().zip(&new).all(|(o, n)| Rc::ptr_eq(&o.0, &n.0)) {
+                        doc.clone()
+                    } else {
+                        doc.with_children(new)
+                    }
+                }
+            };
+            normal.insert(Rc::as_ptr(&doc.0), node);
+        }
+        normal[&Rc::as_ptr(&self.0)].clone()
+    }
+}
+
+// Concatenate two normalized documents, fusing the text where they meet.
+fn concat_normal<A: Annotation>(x: Doc<A>, y: Doc<A>) -> Doc<A> {
+    use DocInner as DI;
+
+    match (&*x.0, &*y.0) {
+        (DI::Empty, _) => y,
+        (_, DI::Empty) => x,
+        (DI::Text(a), DI::Text(b)) => Doc::<A>::text(format!("{}{}", a, b)),
+        (DI::Concat(init, last), DI::Text(b)) => match &*last.0 {
+            DI::Text(a) => {
+                DI::Concat(init.clone(), Doc::<A>::text(format!("{}{}", a, b))).into_doc()
+            }
+            _ => DI::Concat(x, y).into_doc(),
+        },
+        (DI::Text(a), DI::Concat(first, rest)) => match &*first.0 {
+            DI::Text(b) => {
+                DI::Concat(Doc::<A>::text(format!("{}{}", a, b)), rest.clone()).into_doc()
+            }
+            _ => DI::Concat(x, y).into_doc(),
+        },
+        _ => DI::Concat(x, y).into_doc(),
+    }
+}
+
+// Nest a normalized document, merging with its own nesting. Nesting only affects line breaks, so
+// it's dropped around text.
+fn nest_normal<A>(i: Width, x: Doc<A>) -> Doc<A> {
+    match &*x.0 {
+        DocInner::Empty | DocInner::Text(_) => x,
+        DocInner::Nest(j, inner) if i.saturating_add(*j) == 0 => inner.clone(),
+        DocInner::Nest(j, inner) => DocInner::Nest(i.saturating_add(*j), inner.clone()).into_doc(),
+        _ if i == 0 => x,
+        _ => DocInner::Nest(i, x).into_doc(),
+    }
+}